@@ -0,0 +1,73 @@
+use stylo_compute::{
+    BackendKind, FakeServoFixtures, MockBackend, ServoStyleEngineReal, StyleEngine, StyleRequest, StyleResult,
+    FAKE_SERVO_FIXTURES_ENV,
+};
+use tracing_subscriber::EnvFilter;
+
+const FIXTURES: &str = "examples/fixtures/fake_servo.json";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Engine diagnostics go to stderr; tune with RUST_LOG, e.g. RUST_LOG=stylo_compute=debug
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("stylo_compute=info")))
+        .with_writer(std::io::stderr)
+        .init();
+
+    println!("🧪 Fake Servo Demo");
+    println!("==================");
+    println!("Exercises the engines' console protocol without a Servo build");
+
+    let html = r#"<div class="highlight"><p>Hello</p></div>"#;
+    let css = ".highlight { color: red; font-size: 24px; }";
+
+    // Test 1: In-process mock backend
+    println!("\n🧪 Test 1: MockBackend");
+    let mut engine = StyleEngine::with_backend(MockBackend::new(FakeServoFixtures::from_json_file(FIXTURES)?));
+    engine.set_html(html)?;
+    engine.add_stylesheet(css)?;
+
+    let results = engine.compute_styles_batch(vec![
+        StyleRequest::value("highlight-color", ".highlight", "color"),
+        StyleRequest::value("paragraph-size", "p", "font-size"),
+        StyleRequest::value("missing", ".missing", "color"),
+        StyleRequest::value("broken", ".broken", "color"),
+    ]).await?;
+    for StyleResult { key, result } in results {
+        match result {
+            Ok(value) => println!("   ✅ {} -> {}", key, value),
+            Err(e) => println!("   ❌ {} -> Error: {}", key, e),
+        }
+    }
+
+    // Test 2: fake-servo executable behind the Servo engines
+    println!("\n🧪 Test 2: fake-servo executable");
+    let fake_servo = std::env::var("FAKE_SERVO").unwrap_or_else(|_| "target/debug/fake-servo".to_string());
+    if !std::path::Path::new(&fake_servo).exists() {
+        println!("   ⚠️  {} not found. Run `cargo build --bin fake-servo` first", fake_servo);
+        return Ok(());
+    }
+    std::env::set_var(FAKE_SERVO_FIXTURES_ENV, FIXTURES);
+
+    let mut real = StyleEngine::with_backend(ServoStyleEngineReal::with_servo_path(Some(fake_servo.clone()))?);
+    real.set_html(html)?;
+    real.add_stylesheet(css)?;
+    match real.get_computed_style(".highlight", "color").await {
+        Ok(color) => println!("   ✅ one-shot: .highlight -> color: {}", color),
+        Err(e) => println!("   ❌ one-shot error: {}", e),
+    }
+
+    for kind in [BackendKind::Batch, BackendKind::Daemon] {
+        let mut engine = StyleEngine::new(kind, Some(fake_servo.clone()))?;
+        engine.set_html(html)?;
+        engine.add_stylesheet(css)?;
+        match engine.get_all_computed_styles(".highlight").await {
+            Ok(styles) => println!("   ✅ {}: .highlight has {} properties", engine.backend().name(), styles.len()),
+            Err(e) => println!("   ❌ {} error: {}", engine.backend().name(), e),
+        }
+    }
+
+    println!("\n🎉 Fake Servo demo completed!");
+
+    Ok(())
+}
@@ -1,22 +1,31 @@
-use stylo_compute::ServoStyleEngineOptimized;
+use stylo_compute::{BackendKind, EngineConfig, ServoStyleEngineOptimized, StyleEngine, StyleRequest, StyleResult};
+use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Engine diagnostics go to stderr; tune with RUST_LOG, e.g. RUST_LOG=stylo_compute=debug
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("stylo_compute=info")))
+        .with_writer(std::io::stderr)
+        .init();
+
     println!("🚀 Servo Style Engine - Optimized Demo");
     println!("=====================================");
 
-    // Read Servo path from config if available
-    let servo_path = read_servo_config().unwrap_or_else(|| {
-        println!("⚠️  No servo_config.toml found. Please run ./enable_servo.sh first");
-        "/home/test/clone/servo/target/debug/servo".to_string()
-    });
+    // Read settings from servo_config.toml and STYLO_COMPUTE_* if available
+    let mut config = EngineConfig::load()?;
+    if config.servo_path.is_none() {
+        println!("⚠️  No Servo path configured. Please run ./enable_servo.sh first");
+        config = config.with_servo_path("/home/test/clone/servo/target/debug/servo");
+    }
+    let servo_path = config.servo_command().to_string();
 
     // Create optimized engine with daemon mode and batch processing
-    let mut engine = ServoStyleEngineOptimized::with_options(
-        Some(servo_path.clone()), 
-        true,  // Use daemon mode
-        5      // Batch size
+    let backend = ServoStyleEngineOptimized::with_config(
+        &config.with_mode(BackendKind::Daemon).with_batch_size(5)
     )?;
+    let artifact_dir = backend.debug_artifact_dir().map(|dir| dir.to_path_buf());
+    let mut engine = StyleEngine::with_backend(backend);
 
     // Test cases
     println!("\n📋 Running optimized test cases...");
@@ -45,19 +54,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     "#)?;
 
     let batch_requests = vec![
-        ("div".to_string(), Some("color".to_string())),
-        ("p".to_string(), Some("font-size".to_string())),
-        ("#special".to_string(), Some("background-color".to_string())),
-        ("div".to_string(), Some("font-weight".to_string())),
+        StyleRequest::value("div color", "div", "color"),
+        StyleRequest::value("p font-size", "p", "font-size"),
+        StyleRequest::value("#special background-color", "#special", "background-color"),
+        StyleRequest::value("div font-weight", "div", "font-weight"),
     ];
 
     println!("   Processing {} queries in batch...", batch_requests.len());
     match engine.compute_styles_batch(batch_requests).await {
         Ok(results) => {
-            for (selector, result) in results {
+            for StyleResult { key, result } in results {
                 match result {
-                    Ok(value) => println!("   ✅ {} -> {}", selector, value),
-                    Err(e) => println!("   ❌ {} -> Error: {}", selector, e),
+                    Ok(value) => println!("   ✅ {} -> {}", key, value),
+                    Err(e) => println!("   ❌ {} -> Error: {}", key, e),
                 }
             }
         }
@@ -73,7 +82,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             // Show some key properties
             let key_props = ["color", "font-size", "font-weight", "display", "margin"];
             for prop in &key_props {
-                if let Some(value) = styles.get(*prop) {
+                if let Some(value) = styles.get(prop) {
                     println!("   📋   {}: {}", prop, value);
                 }
             }
@@ -81,11 +90,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Err(e) => println!("   ❌ Error: {}", e),
     }
 
-    // Test 4: Convenience function for batch processing
-    println!("\n🧪 Test 4: Convenience Batch Function");
+    // Test 4: Convenience function as a style regression check
+    println!("\n🧪 Test 4: Expected-Value Batch Check");
     let batch_queries = vec![
-        ("h1".to_string(), "color".to_string(), None),
-        ("p".to_string(), "font-size".to_string(), None),
+        ("h1".to_string(), "color".to_string(), Some("green".to_string())),
+        ("p".to_string(), "font-size".to_string(), Some("16px".to_string())),
         ("div".to_string(), "display".to_string(), None),
     ];
 
@@ -98,34 +107,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         batch_queries, 
         Some(servo_path.clone())
     ).await {
-        Ok(results) => {
-            println!("   ✅ Batch convenience function results:");
-            for (selector, result) in results {
-                match result {
-                    Ok(value) => println!("   📋 {} -> {}", selector, value),
-                    Err(e) => println!("   ❌ {} -> Error: {}", selector, e),
-                }
+        Ok(report) => {
+            for assertion in &report.assertions {
+                let icon = if assertion.passed { "✅" } else { "❌" };
+                println!("   {} {}", icon, assertion);
             }
+            println!("   📋 {} passed, {} failed", report.pass_count(), report.fail_count());
         }
         Err(e) => println!("   ❌ Convenience function error: {}", e),
     }
 
     println!("\n🎉 Optimized demo completed!");
-    println!("📁 Check /tmp/ for debug files and batch results");
+    match artifact_dir {
+        Some(dir) => println!("📁 Debug artifacts written to {}", dir.display()),
+        None => println!("📁 Set STYLO_COMPUTE_DEBUG_DIR to keep each batch's page and Servo output"),
+    }
 
     Ok(())
 }
-
-fn read_servo_config() -> Option<String> {
-    use std::fs;
-    let config_content = fs::read_to_string("servo_config.toml").ok()?;
-    
-    for line in config_content.lines() {
-        if line.starts_with("servo_path") {
-            if let Some(path) = line.split('=').nth(1) {
-                return Some(path.trim().trim_matches('"').to_string());
-            }
-        }
-    }
-    None
-}
\ No newline at end of file
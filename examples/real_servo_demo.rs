@@ -1,7 +1,14 @@
-use stylo_compute::ServoStyleEngineReal;
+use stylo_compute::{EngineConfig, ServoStyleEngineReal, StyleEngine};
+use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Engine diagnostics go to stderr; tune with RUST_LOG, e.g. RUST_LOG=stylo_compute=debug
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("stylo_compute=info")))
+        .with_writer(std::io::stderr)
+        .init();
+
     println!("🎨 Real Servo-Stylo Integration Demo");
     println!("===================================");
     println!("This example uses REAL Servo executable to compute CSS styles");
@@ -11,30 +18,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Try to create real Servo engine
     println!("📋 Test 1: Creating Real ServoStyleEngine");
     
-    // First check if we have a servo_config.toml file
-    let config_path = std::path::Path::new("servo_config.toml");
-    let servo_path_from_config: Option<String> = if config_path.exists() {
-        match std::fs::read_to_string(config_path) {
-            Ok(content) => {
-                // Simple TOML parsing to extract executable_path
-                let mut found_path = None;
-                for line in content.lines() {
-                    if line.trim().starts_with("executable_path = ") {
-                        if let Some(path_part) = line.split('=').nth(1) {
-                            let path = path_part.trim().trim_matches('"').to_string();
-                            println!("🔧 Found Servo path in config: {}", path);
-                            found_path = Some(path);
-                            break;
-                        }
-                    }
-                }
-                found_path
-            }
-            Err(_) => None,
-        }
-    } else {
-        None
-    };
+    // First check servo_config.toml and STYLO_COMPUTE_SERVO_PATH
+    let servo_path_from_config = EngineConfig::load()?.servo_path;
+    if let Some(ref path) = servo_path_from_config {
+        println!("🔧 Found Servo path in config: {}", path);
+    }
     
     // Try different approaches to find Servo
     let engine = if let Some(config_path) = servo_path_from_config {
@@ -86,7 +74,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
     
     let mut engine = match engine {
-        Some(engine) => engine,
+        Some(engine) => StyleEngine::with_backend(engine),
         None => {
             println!("❌ Servo executable not found");
             println!("   Please ensure Servo is built and available");
@@ -1,7 +1,14 @@
-use stylo_compute::{ServoStyleEngineReal, ServoStyleError};
+use stylo_compute::{ServoStyleEngineReal, StyleEngine, StyleError};
+use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Engine diagnostics go to stderr; tune with RUST_LOG, e.g. RUST_LOG=stylo_compute=debug
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("stylo_compute=info")))
+        .with_writer(std::io::stderr)
+        .init();
+
     println!("🎨 Servo-Stylo Integration Demonstration");
     println!("=========================================");
     println!("This demonstrates how to use Servo as an intermediary to access Stylo's native APIs");
@@ -9,7 +16,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Test 1: Basic engine creation
     println!("📋 Test 1: Creating ServoStyleEngineReal");
-    let mut engine = ServoStyleEngineReal::new()?;
+    let mut engine = StyleEngine::with_backend(ServoStyleEngineReal::new()?);
     println!("✅ Successfully created ServoStyleEngineReal");
     println!("   This engine communicates with Servo processes to access Stylo's APIs");
     println!();
@@ -106,8 +113,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             Ok(value) => {
                 println!("  {} -> {}: {}", selector, property, value);
             }
-            Err(ServoStyleError::ComputationError(msg)) => {
-                println!("  {} -> {}: Computation error ({})", selector, property, msg);
+            Err(StyleError::ElementNotFound { .. }) => {
+                println!("  {} -> {}: No matching element", selector, property);
             }
             Err(e) => {
                 println!("  {} -> {}: Error - {}", selector, property, e);
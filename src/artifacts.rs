@@ -0,0 +1,148 @@
+//! Opt-in debug artifacts for Servo runs.
+//!
+//! When `EngineConfig::debug_artifact_dir` is set, each engine gets its own run directory
+//! below it, and every Servo batch gets a numbered subdirectory holding the generated page,
+//! the Servo command line, its exit status and the raw console output. Writing artifacts is
+//! best effort: failures are logged and never fail a query.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+use crate::config::EngineConfig;
+
+/// Run directory that an engine's batches write their artifacts to
+#[derive(Debug)]
+pub(crate) struct DebugArtifacts {
+    run_dir: PathBuf,
+    next_batch: AtomicUsize,
+}
+
+impl DebugArtifacts {
+    /// Create a fresh run directory if `config` enables artifacts
+    pub(crate) fn for_config(config: &EngineConfig) -> Option<DebugArtifacts> {
+        let root = config.debug_artifact_dir.as_ref()?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let run_id = uuid::Uuid::new_v4().simple().to_string();
+        let run_dir = root.join(format!("run-{}-{}-{}", timestamp, std::process::id(), &run_id[..8]));
+
+        match std::fs::create_dir_all(&run_dir) {
+            Ok(()) => {
+                debug!(dir = %run_dir.display(), "Writing debug artifacts");
+                Some(DebugArtifacts {
+                    run_dir,
+                    next_batch: AtomicUsize::new(1),
+                })
+            }
+            Err(e) => {
+                warn!(dir = %run_dir.display(), error = %e, "Cannot create debug artifact directory, artifacts disabled");
+                None
+            }
+        }
+    }
+
+    /// This run's directory
+    pub(crate) fn run_dir(&self) -> &Path {
+        &self.run_dir
+    }
+
+    /// Start the artifacts of one batch, in a directory named after its sequence number and id
+    pub(crate) fn batch(&self, kind: &str, batch_id: &str) -> ArtifactSet {
+        let seq = self.next_batch.fetch_add(1, Ordering::SeqCst);
+        let dir = self.run_dir.join(format!("{:04}-{}-{}", seq, kind, batch_id));
+        match std::fs::create_dir_all(&dir) {
+            Ok(()) => debug!(dir = %dir.display(), "Recording debug artifacts"),
+            Err(e) => warn!(dir = %dir.display(), error = %e, "Cannot create debug artifact directory"),
+        }
+        ArtifactSet { dir }
+    }
+}
+
+/// Artifact files of one batch
+#[derive(Debug)]
+pub(crate) struct ArtifactSet {
+    dir: PathBuf,
+}
+
+impl ArtifactSet {
+    /// Write one artifact file, logging rather than failing on errors
+    pub(crate) fn write(&self, name: &str, contents: impl AsRef<[u8]>) {
+        let path = self.dir.join(name);
+        if let Err(e) = std::fs::write(&path, contents) {
+            warn!(path = %path.display(), error = %e, "Failed to write debug artifact");
+        }
+    }
+
+    /// Create an artifact file that is written to incrementally
+    pub(crate) fn create(&self, name: &str) -> Option<File> {
+        let path = self.dir.join(name);
+        File::create(&path)
+            .inspect_err(|e| warn!(path = %path.display(), error = %e, "Failed to create debug artifact"))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::servo_process::run_servo_page;
+
+    fn entries(dir: &Path) -> Vec<PathBuf> {
+        let mut entries: Vec<PathBuf> = std::fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn writes_nothing_unless_enabled() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(DebugArtifacts::for_config(&EngineConfig::default()).is_none());
+        assert!(entries(root.path()).is_empty());
+    }
+
+    #[test]
+    fn gives_every_run_and_batch_its_own_directory() {
+        let root = tempfile::tempdir().unwrap();
+        let config = EngineConfig::default().with_debug_artifact_dir(root.path());
+        let first = DebugArtifacts::for_config(&config).unwrap();
+        let second = DebugArtifacts::for_config(&config).unwrap();
+        assert_ne!(first.run_dir(), second.run_dir());
+        let mut runs = vec![first.run_dir().to_path_buf(), second.run_dir().to_path_buf()];
+        runs.sort();
+        assert_eq!(entries(root.path()), runs);
+
+        first.batch("batch", "a").write("page.html", "<p></p>");
+        first.batch("batch", "a").write("page.html", "<p></p>");
+        let batches: Vec<String> = entries(first.run_dir()).iter()
+            .map(|dir| dir.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(batches, ["0001-batch-a", "0002-batch-a"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn records_the_page_command_status_and_logs_of_a_run() {
+        let root = tempfile::tempdir().unwrap();
+        // `echo` stands in for Servo and prints its own command line
+        let config = EngineConfig::default().with_debug_artifact_dir(root.path()).with_servo_path("echo");
+        let artifacts = DebugArtifacts::for_config(&config).unwrap();
+        let batch = artifacts.batch("query", "q1");
+        batch.write("page.html", "<p>page</p>");
+        run_servo_page(&config, "file:///page.html", Duration::from_secs(10), Some(&batch)).await.unwrap();
+
+        let dir = artifacts.run_dir().join("0001-query-q1");
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("page.html"), "<p>page</p>");
+        assert!(read("command.txt").starts_with("echo"));
+        assert!(read("command.txt").contains("file:///page.html"));
+        assert!(read("status.txt").contains("exit status: 0"));
+        assert!(read("stdout.log").contains("--headless file:///page.html"));
+        assert_eq!(read("stderr.log"), "");
+    }
+}
@@ -0,0 +1,138 @@
+//! Expected-value checks over computed styles, for style regression tests.
+//!
+//! Expected and computed values are compared with `ComputedValue::equivalent`, so
+//! `red`, `#f00` and `rgb(255, 0, 0)` match, as do `0` and `0px`.
+
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+use crate::computed_value::ComputedValue;
+use crate::error::StyleError;
+
+/// One property checked against its expected value
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StyleAssertion {
+    pub selector: String,
+    pub property: String,
+    /// Expected value as written by the caller; `None` only requires the property to compute
+    pub expected: Option<String>,
+    /// The computed value, or why it could not be computed
+    pub actual: Result<String, StyleError>,
+    pub passed: bool,
+}
+
+impl StyleAssertion {
+    /// Check `actual` against `expected`
+    pub fn check(selector: &str, property: &str, expected: Option<&str>, actual: Result<String, StyleError>) -> Self {
+        let passed = match (&actual, expected) {
+            (Err(_), _) => false,
+            (Ok(_), None) => true,
+            (Ok(actual), Some(expected)) => ComputedValue::parse(actual).equivalent(&ComputedValue::parse(expected)),
+        };
+        StyleAssertion {
+            selector: selector.to_string(),
+            property: property.to_string(),
+            expected: expected.map(str::to_string),
+            actual,
+            passed,
+        }
+    }
+}
+
+impl fmt::Display for StyleAssertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = if self.passed { "PASS" } else { "FAIL" };
+        write!(f, "{} {} {{ {} }}: ", status, self.selector, self.property)?;
+        match (&self.actual, &self.expected) {
+            (Ok(actual), Some(expected)) if !self.passed => write!(f, "expected {}, got {}", expected, actual),
+            (Ok(actual), _) => write!(f, "{}", actual),
+            (Err(e), Some(expected)) => write!(f, "expected {}, got error: {}", expected, e),
+            (Err(e), None) => write!(f, "error: {}", e),
+        }
+    }
+}
+
+/// Pass/fail report over a batch of `StyleAssertion`s, in request order
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct StyleReport {
+    pub assertions: Vec<StyleAssertion>,
+}
+
+impl StyleReport {
+    /// Whether every assertion passed
+    pub fn passed(&self) -> bool {
+        self.assertions.iter().all(|assertion| assertion.passed)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &StyleAssertion> {
+        self.assertions.iter().filter(|assertion| !assertion.passed)
+    }
+
+    pub fn pass_count(&self) -> usize {
+        self.assertions.iter().filter(|assertion| assertion.passed).count()
+    }
+
+    pub fn fail_count(&self) -> usize {
+        self.assertions.len() - self.pass_count()
+    }
+}
+
+impl fmt::Display for StyleReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for assertion in &self.assertions {
+            writeln!(f, "{}", assertion)?;
+        }
+        write!(f, "{} passed, {} failed", self.pass_count(), self.fail_count())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(expected: Option<&str>, actual: Result<&str, StyleError>) -> StyleAssertion {
+        StyleAssertion::check(".a", "color", expected, actual.map(str::to_string))
+    }
+
+    #[test]
+    fn compares_normalized_values() {
+        assert!(check(Some("red"), Ok("rgb(255, 0, 0)")).passed);
+        assert!(check(Some("#f00"), Ok("rgb(255, 0, 0)")).passed);
+        assert!(check(Some("0"), Ok("0px")).passed);
+        assert!(check(Some("BLOCK"), Ok("block")).passed);
+        assert!(!check(Some("blue"), Ok("rgb(255, 0, 0)")).passed);
+        assert!(!check(Some("1"), Ok("1px")).passed);
+    }
+
+    #[test]
+    fn without_an_expected_value_only_requires_a_result() {
+        assert!(check(None, Ok("")).passed);
+        let missing = check(None, Err(StyleError::ElementNotFound { selector: ".a".into() }));
+        assert!(!missing.passed);
+        assert_eq!(missing.to_string(), "FAIL .a { color }: error: No element matches selector '.a'");
+    }
+
+    #[test]
+    fn errors_fail_even_when_a_value_is_expected() {
+        let failed = check(Some("red"), Err(StyleError::Script { message: "boom".into() }));
+        assert!(!failed.passed);
+        assert!(failed.to_string().starts_with("FAIL .a { color }: expected red, got error: "));
+    }
+
+    #[test]
+    fn report_counts_and_lists_failures() {
+        let report = StyleReport {
+            assertions: vec![
+                check(Some("red"), Ok("rgb(255, 0, 0)")),
+                check(Some("blue"), Ok("rgb(255, 0, 0)")),
+            ],
+        };
+        assert!(!report.passed());
+        assert_eq!((report.pass_count(), report.fail_count()), (1, 1));
+        assert_eq!(report.failures().count(), 1);
+        assert_eq!(
+            report.to_string(),
+            "PASS .a { color }: rgb(255, 0, 0)\nFAIL .a { color }: expected blue, got rgb(255, 0, 0)\n1 passed, 1 failed"
+        );
+    }
+}
@@ -0,0 +1,888 @@
+//! Pluggable style computation backends and the `StyleEngine` front-end.
+//!
+//! A `StyleBackend` evaluates a batch of `StyleQuery`s against a `StyleDocument`.
+//! `StyleEngine` owns the document and stylesheets and dispatches to whichever
+//! backend it was configured with, so callers can switch between one-shot, batched
+//! and daemon Servo integration (or their own engine) without changing types.
+
+use std::collections::HashMap;
+use std::time::Duration;
+use async_trait::async_trait;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::breakpoints::{self, ViewportSweep};
+use crate::computed_value::ComputedValue;
+use crate::config::EngineConfig;
+use crate::error::StyleError;
+use crate::media::MediaEnvironment;
+use crate::properties::expand_shorthands;
+use crate::servo_style_engine_optimized::ServoStyleEngineOptimized;
+use crate::servo_style_engine_real::ServoStyleEngineReal;
+
+/// HTML content and stylesheets that queries are evaluated against
+#[derive(Debug, Clone, Default)]
+pub struct StyleDocument {
+    pub html: String,
+    pub stylesheets: Vec<String>,
+    /// Viewport and media the document is styled for
+    pub media: MediaEnvironment,
+}
+
+impl StyleDocument {
+    /// All stylesheets joined in the order they were added
+    pub fn combined_css(&self) -> String {
+        self.stylesheets.join("\n")
+    }
+
+    /// Wrap the HTML and stylesheets in a full document, optionally with a script
+    pub fn to_html(&self, script: &str) -> String {
+        let script_block = if script.is_empty() {
+            String::new()
+        } else {
+            format!("<script>\n        {}\n    </script>", script)
+        };
+        self.wrap("", &script_block)
+    }
+
+    /// Wrap the HTML and stylesheets in a full document, with `head` as raw markup at the
+    /// very start of `<head>`, ahead of anything the document contributes
+    pub(crate) fn to_html_with_head(&self, head: &str) -> String {
+        self.wrap(head, "")
+    }
+
+    fn wrap(&self, head: &str, tail: &str) -> String {
+        format!(r#"<!DOCTYPE html>
+<html>
+<head>
+    {}
+    <style>
+        {}
+    </style>
+</head>
+<body>
+    {}
+    {}
+</body>
+</html>"#, head, self.combined_css(), self.html, tail)
+    }
+}
+
+/// Per-call settings for style queries
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    /// Deadline for this call, overriding the engine's configured timeout
+    pub timeout: Option<Duration>,
+    /// Pseudo-element to compute instead of the element itself, e.g. `::before`
+    pub pseudo_element: Option<String>,
+    /// Interaction states forced on each matched element while it is computed
+    pub forced_states: Vec<ForcedState>,
+    /// Force `forced_states` on the matched element's ancestors too
+    pub states_on_ancestors: bool,
+}
+
+impl QueryOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Target a pseudo-element of the matched elements (`::before`, `::after`,
+    /// `::marker`, `::placeholder`, ...); the legacy single-colon form is accepted too
+    pub fn with_pseudo_element(mut self, pseudo_element: &str) -> Self {
+        self.pseudo_element = Some(normalize_pseudo_element(pseudo_element));
+        self
+    }
+
+    /// Compute the matched elements as if they were in `state`, e.g. hovered
+    pub fn with_state(mut self, state: ForcedState) -> Self {
+        if !self.forced_states.contains(&state) {
+            self.forced_states.push(state);
+        }
+        self
+    }
+
+    /// Force the states on every ancestor of the matched element as well, as when the
+    /// pointer hovers a descendant
+    pub fn with_states_on_ancestors(mut self) -> Self {
+        self.states_on_ancestors = true;
+        self
+    }
+}
+
+/// Interaction state that can be forced on an element for a query
+///
+/// Servo runs headless, so no element is ever hovered, focused or active on its own.
+/// A forced state makes the matching pseudo-class (`:hover`, `:focus`, ...) apply to the
+/// element; `Focus` also makes it and its ancestors match `:focus-within`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum ForcedState {
+    Hover,
+    Focus,
+    FocusVisible,
+    Active,
+    Checked,
+    Disabled,
+    Visited,
+}
+
+/// `::name` form of a pseudo-element written as `::name`, `:name` or `name`
+fn normalize_pseudo_element(pseudo_element: &str) -> String {
+    format!("::{}", pseudo_element.trim().trim_start_matches(':'))
+}
+
+/// A single computed style query
+///
+/// `property: None` requests every property `getComputedStyle()` exposes.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StyleQuery {
+    pub id: String,
+    pub selector: String,
+    pub property: Option<String>,
+    /// How long this query may take; `None` uses the backend's default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Compute every element matching `selector` rather than only the first
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub all_matches: bool,
+    /// Longhand properties to compute when `property` is `None`; empty for all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<String>,
+    /// Pseudo-element passed to `getComputedStyle(element, pseudo)`, e.g. `::before`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pseudo_element: Option<String>,
+    /// Interaction states forced on the matched element while it is computed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forced_states: Vec<ForcedState>,
+    /// Force the states on the matched element's ancestors too
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub states_on_ancestors: bool,
+}
+
+impl StyleQuery {
+    /// Create a query with a fresh unique id
+    pub fn new(selector: &str, property: Option<&str>) -> Self {
+        StyleQuery {
+            id: uuid::Uuid::new_v4().to_string(),
+            selector: selector.to_string(),
+            property: property.map(str::to_string),
+            timeout_ms: None,
+            all_matches: false,
+            properties: Vec::new(),
+            pseudo_element: None,
+            forced_states: Vec::new(),
+            states_on_ancestors: false,
+        }
+    }
+
+    /// Create a query computing `properties` (or every property, if empty) for the first
+    /// element matching `selector`
+    ///
+    /// Shorthands are expanded to their longhands, which are what the result contains.
+    pub fn for_properties(selector: &str, properties: &[&str]) -> Self {
+        StyleQuery {
+            properties: expand_shorthands(properties),
+            ..Self::new(selector, None)
+        }
+    }
+
+    /// Create a query computing `properties` (or every property, if empty) for each
+    /// element matching `selector`
+    pub fn all_matches(selector: &str, properties: &[&str]) -> Self {
+        StyleQuery {
+            all_matches: true,
+            ..Self::for_properties(selector, properties)
+        }
+    }
+
+    /// Apply per-call options to this query
+    pub fn with_options(mut self, options: &QueryOptions) -> Self {
+        if let Some(timeout) = options.timeout {
+            self.timeout_ms = Some(timeout.as_millis() as u64);
+        }
+        if let Some(ref pseudo_element) = options.pseudo_element {
+            self.pseudo_element = Some(normalize_pseudo_element(pseudo_element));
+        }
+        if !options.forced_states.is_empty() {
+            self.forced_states = options.forced_states.clone();
+            self.states_on_ancestors = options.states_on_ancestors;
+        }
+        self
+    }
+
+    /// This query's deadline, or `default` if it has none
+    pub fn timeout_or(&self, default: Duration) -> Duration {
+        self.timeout_ms.map(Duration::from_millis).unwrap_or(default)
+    }
+}
+
+/// Identifies one element matched by a selector
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct ElementDescriptor {
+    /// Lower-case tag name
+    pub tag: String,
+    pub id: Option<String>,
+    pub classes: Vec<String>,
+    /// Path from the root element, e.g. `html > body:nth-child(2) > ul:nth-child(1) > li:nth-child(3)`
+    pub path: String,
+    /// Position among the selector's matches, in document order
+    pub index: usize,
+}
+
+/// Computed property values of one element, in a stable order
+///
+/// Properties keep the order the engine reported them in: the order
+/// `getComputedStyle()` enumerates them, or the requested order for named properties.
+/// The map serializes to a JSON object in that order and deserializes back unchanged,
+/// so serialized results are identical across runs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(transparent)]
+pub struct ComputedStyles(IndexMap<String, String>);
+
+impl ComputedStyles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Value of `property`, if it was computed
+    pub fn get(&self, property: &str) -> Option<&str> {
+        self.0.get(property).map(String::as_str)
+    }
+
+    pub fn contains(&self, property: &str) -> bool {
+        self.0.contains_key(property)
+    }
+
+    /// Set `property`, keeping its position if it is already present
+    pub fn insert(&mut self, property: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        self.0.insert(property.into(), value.into())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Properties and values in order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(property, value)| (property.as_str(), value.as_str()))
+    }
+
+    /// Property names in order
+    pub fn properties(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
+    /// Reorder the properties alphabetically
+    pub fn sort(&mut self) {
+        self.0.sort_keys();
+    }
+
+    /// Convert into an unordered map
+    pub fn into_hash_map(self) -> HashMap<String, String> {
+        self.0.into_iter().collect()
+    }
+}
+
+impl FromIterator<(String, String)> for ComputedStyles {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        ComputedStyles(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for ComputedStyles {
+    type Item = (String, String);
+    type IntoIter = indexmap::map::IntoIter<String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ComputedStyles {
+    type Item = (&'a String, &'a String);
+    type IntoIter = indexmap::map::Iter<'a, String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// Computed styles of one element matched by an `all_matches` query
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ElementStyles {
+    pub element: ElementDescriptor,
+    pub styles: ComputedStyles,
+}
+
+/// One request of a typed batch, labelled with a caller-chosen key
+///
+/// Keys are only echoed back in the matching `StyleResult`; they need not be unique.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StyleRequest {
+    pub key: String,
+    pub selector: String,
+    /// The single property to compute; `None` computes `properties`
+    pub property: Option<String>,
+    /// Longhand properties to compute when `property` is `None`; empty for all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<String>,
+    /// Pseudo-element to compute, overriding the batch's `QueryOptions`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pseudo_element: Option<String>,
+}
+
+impl StyleRequest {
+    /// Request the computed value of one property
+    pub fn value(key: &str, selector: &str, property: &str) -> Self {
+        StyleRequest {
+            key: key.to_string(),
+            selector: selector.to_string(),
+            property: Some(property.to_string()),
+            properties: Vec::new(),
+            pseudo_element: None,
+        }
+    }
+
+    /// Request the named properties, expanding shorthands to their longhands
+    pub fn styles(key: &str, selector: &str, properties: &[&str]) -> Self {
+        StyleRequest {
+            property: None,
+            properties: expand_shorthands(properties),
+            ..Self::value(key, selector, "")
+        }
+    }
+
+    /// Request every computed property
+    pub fn all_styles(key: &str, selector: &str) -> Self {
+        Self::styles(key, selector, &[])
+    }
+
+    /// Compute the styles of a pseudo-element such as `::before`
+    pub fn with_pseudo_element(mut self, pseudo_element: &str) -> Self {
+        self.pseudo_element = Some(normalize_pseudo_element(pseudo_element));
+        self
+    }
+
+    /// The query sent to the backend for this request, with a fresh id
+    fn to_query(&self, options: &QueryOptions) -> StyleQuery {
+        let mut query = StyleQuery {
+            properties: self.properties.clone(),
+            ..StyleQuery::new(&self.selector, self.property.as_deref())
+        }.with_options(options);
+        if self.pseudo_element.is_some() {
+            query.pseudo_element = self.pseudo_element.clone();
+        }
+        query
+    }
+}
+
+/// Outcome of one `StyleRequest`, carrying the request's key
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StyleResult {
+    pub key: String,
+    pub result: Result<StyleValue, StyleError>,
+}
+
+impl StyleResult {
+    /// The computed value, if this is a successful single-property result
+    pub fn value(&self) -> Option<&str> {
+        match self.result {
+            Ok(StyleValue::Single(ref value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The computed styles, if this is a successful property-map result
+    pub fn styles(&self) -> Option<&ComputedStyles> {
+        match self.result {
+            Ok(StyleValue::Map(ref styles)) => Some(styles),
+            _ => None,
+        }
+    }
+}
+
+/// What a successful `StyleRequest` computed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StyleValue {
+    /// The value of the requested property
+    Single(String),
+    /// The requested properties, or all of them
+    Map(ComputedStyles),
+}
+
+impl std::fmt::Display for StyleValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StyleValue::Single(value) => f.write_str(value),
+            StyleValue::Map(styles) => {
+                for (i, (property, value)) in styles.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str("; ")?;
+                    }
+                    write!(f, "{}: {}", property, value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The backend's answer to one `StyleQuery`, matched by `id`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StyleResponse {
+    pub id: String,
+    pub success: bool,
+    pub computed_value: Option<String>,
+    pub computed_styles: Option<ComputedStyles>,
+    /// One entry per matched element, for `all_matches` queries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matches: Option<Vec<ElementStyles>>,
+    /// Why the query failed, when `success` is false
+    #[serde(default)]
+    pub error: Option<StyleError>,
+}
+
+impl StyleResponse {
+    /// A failed response for the query with the given id
+    pub fn failure(id: &str, error: StyleError) -> Self {
+        StyleResponse {
+            id: id.to_string(),
+            success: false,
+            computed_value: None,
+            computed_styles: None,
+            matches: None,
+            error: Some(error),
+        }
+    }
+
+    /// A response for a query that ran out of time after `waited`
+    pub fn timeout(id: &str, waited: Duration) -> Self {
+        Self::failure(id, StyleError::Timeout { waited })
+    }
+
+    fn into_value(self) -> Result<String, StyleError> {
+        if self.success {
+            self.computed_value.ok_or_else(|| StyleError::protocol("No computed value returned"))
+        } else {
+            Err(self.into_error())
+        }
+    }
+
+    fn into_styles(self) -> Result<ComputedStyles, StyleError> {
+        if self.success {
+            self.computed_styles.ok_or_else(|| StyleError::protocol("No computed styles returned"))
+        } else {
+            Err(self.into_error())
+        }
+    }
+
+    fn into_style_value(self) -> Result<StyleValue, StyleError> {
+        if !self.success {
+            return Err(self.into_error());
+        }
+        match (self.computed_value, self.computed_styles) {
+            (Some(value), _) => Ok(StyleValue::Single(value)),
+            (None, Some(styles)) => Ok(StyleValue::Map(styles)),
+            (None, None) => Err(StyleError::protocol("No result data")),
+        }
+    }
+
+    fn into_matches(self) -> Result<Vec<ElementStyles>, StyleError> {
+        if self.success {
+            self.matches.ok_or_else(|| StyleError::protocol("No matched elements returned"))
+        } else {
+            Err(self.into_error())
+        }
+    }
+
+    fn into_error(self) -> StyleError {
+        self.error.unwrap_or_else(|| StyleError::protocol("Failed response without an error"))
+    }
+}
+
+/// A style computation engine that can evaluate batches of queries
+///
+/// Implementations receive the full document with every call, so they may be
+/// shared between `StyleEngine`s and hold no per-document state.
+#[async_trait]
+pub trait StyleBackend: Send + Sync {
+    /// Short human-readable backend name
+    fn name(&self) -> &str;
+
+    /// Evaluate `queries` against `document`, returning one response per query id
+    ///
+    /// Responses may be returned in any order; callers correlate them by id.
+    async fn compute_batch(&self, document: &StyleDocument, queries: Vec<StyleQuery>) -> Result<Vec<StyleResponse>, StyleError>;
+}
+
+/// Built-in backends selectable by configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    /// One Servo launch per query (`ServoStyleEngineReal`)
+    OneShot,
+    /// One Servo launch per batch of queries
+    Batch,
+    /// Persistent Servo daemon reused across batches; document scripts do not run
+    Daemon,
+    /// In-process Stylo, without Servo (`NativeStyloBackend`)
+    #[cfg(feature = "native-stylo")]
+    Native,
+}
+
+/// Style engine front-end that owns the document and dispatches to a backend
+pub struct StyleEngine {
+    document: StyleDocument,
+    backend: Box<dyn StyleBackend>,
+}
+
+impl StyleEngine {
+    /// Create an engine using one of the built-in Servo backends
+    pub fn new(kind: BackendKind, servo_path: Option<String>) -> Result<Self, StyleError> {
+        Self::from_config(&EngineConfig {
+            servo_path,
+            mode: kind,
+            ..EngineConfig::default()
+        })
+    }
+
+    /// Create an engine using the built-in backend selected by `config.mode`
+    pub fn from_config(config: &EngineConfig) -> Result<Self, StyleError> {
+        let backend: Box<dyn StyleBackend> = match config.mode {
+            BackendKind::OneShot => Box::new(ServoStyleEngineReal::with_config(config)?),
+            BackendKind::Batch | BackendKind::Daemon => Box::new(ServoStyleEngineOptimized::with_config(config)?),
+            #[cfg(feature = "native-stylo")]
+            BackendKind::Native => Box::new(crate::native_stylo::NativeStyloBackend::new()),
+        };
+        Ok(StyleEngine {
+            document: StyleDocument::default(),
+            backend,
+        })
+    }
+
+    /// Create an engine around any backend, including third-party ones
+    pub fn with_backend<B: StyleBackend + 'static>(backend: B) -> Self {
+        StyleEngine {
+            document: StyleDocument::default(),
+            backend: Box::new(backend),
+        }
+    }
+
+    /// The backend queries are dispatched to
+    pub fn backend(&self) -> &dyn StyleBackend {
+        self.backend.as_ref()
+    }
+
+    /// Add a CSS stylesheet to the style engine
+    pub fn add_stylesheet(&mut self, css: &str) -> Result<(), StyleError> {
+        self.document.stylesheets.push(css.to_string());
+        Ok(())
+    }
+
+    /// Set the HTML content for style computation
+    pub fn set_html(&mut self, html: &str) -> Result<(), StyleError> {
+        self.document.html = html.to_string();
+        Ok(())
+    }
+
+    /// Set the viewport and media later queries are evaluated in
+    pub fn set_media_environment(&mut self, media: MediaEnvironment) {
+        self.document.media = media;
+    }
+
+    /// The viewport and media queries are evaluated in
+    pub fn media_environment(&self) -> &MediaEnvironment {
+        &self.document.media
+    }
+
+    /// Get computed style for a specific CSS property
+    pub async fn get_computed_style(&self, selector: &str, property: &str) -> Result<String, StyleError> {
+        self.get_computed_style_with_options(selector, property, &QueryOptions::default()).await
+    }
+
+    /// Get computed style for a specific CSS property with per-call options
+    pub async fn get_computed_style_with_options(&self, selector: &str, property: &str, options: &QueryOptions) -> Result<String, StyleError> {
+        compute_value(self.backend.as_ref(), &self.document, selector, property, options).await
+    }
+
+    /// Get computed style for a specific CSS property, parsed into a `ComputedValue`
+    pub async fn get_computed_value_typed(&self, selector: &str, property: &str) -> Result<ComputedValue, StyleError> {
+        self.get_computed_value_typed_with_options(selector, property, &QueryOptions::default()).await
+    }
+
+    /// Get computed style for a specific CSS property, parsed, with per-call options
+    pub async fn get_computed_value_typed_with_options(&self, selector: &str, property: &str, options: &QueryOptions) -> Result<ComputedValue, StyleError> {
+        self.get_computed_style_with_options(selector, property, options).await.map(|value| ComputedValue::parse(&value))
+    }
+
+    /// Get all computed styles for an element
+    ///
+    /// Wrap the result in `TypedStyles` to read the values as `ComputedValue`s.
+    pub async fn get_all_computed_styles(&self, selector: &str) -> Result<ComputedStyles, StyleError> {
+        self.get_all_computed_styles_with_options(selector, &QueryOptions::default()).await
+    }
+
+    /// Get all computed styles for an element with per-call options
+    pub async fn get_all_computed_styles_with_options(&self, selector: &str, options: &QueryOptions) -> Result<ComputedStyles, StyleError> {
+        compute_all(self.backend.as_ref(), &self.document, selector, options).await
+    }
+
+    /// Get computed styles of the named properties for an element
+    ///
+    /// Shorthands such as `margin` are expanded, so the result holds exactly their
+    /// longhands and the other properties named; an empty slice computes all of them.
+    pub async fn get_computed_styles(&self, selector: &str, properties: &[&str]) -> Result<ComputedStyles, StyleError> {
+        self.get_computed_styles_with_options(selector, properties, &QueryOptions::default()).await
+    }
+
+    /// Get computed styles of the named properties for an element with per-call options
+    pub async fn get_computed_styles_with_options(&self, selector: &str, properties: &[&str], options: &QueryOptions) -> Result<ComputedStyles, StyleError> {
+        compute_properties(self.backend.as_ref(), &self.document, selector, properties, options).await
+    }
+
+    /// Get computed styles for every element matching `selector`
+    ///
+    /// `properties` names the properties to compute; an empty slice computes all of them.
+    /// Elements are returned in document order; no match yields an empty list.
+    pub async fn get_computed_styles_all_matches(&self, selector: &str, properties: &[&str]) -> Result<Vec<ElementStyles>, StyleError> {
+        self.get_computed_styles_all_matches_with_options(selector, properties, &QueryOptions::default()).await
+    }
+
+    /// Get computed styles for every element matching `selector` with per-call options
+    pub async fn get_computed_styles_all_matches_with_options(&self, selector: &str, properties: &[&str], options: &QueryOptions) -> Result<Vec<ElementStyles>, StyleError> {
+        compute_all_matches(self.backend.as_ref(), &self.document, selector, properties, options).await
+    }
+
+    /// Process multiple style requests in one backend call
+    ///
+    /// Results come back in request order, each carrying its request's key.
+    pub async fn compute_styles_batch(&self, requests: Vec<StyleRequest>) -> Result<Vec<StyleResult>, StyleError> {
+        self.compute_styles_batch_with_options(requests, &QueryOptions::default()).await
+    }
+
+    /// Process multiple style requests in one backend call with per-call options
+    ///
+    /// The timeout applies to each request; requests that run out of time fail with
+    /// `StyleError::Timeout` while the others still return.
+    pub async fn compute_styles_batch_with_options(&self, requests: Vec<StyleRequest>, options: &QueryOptions) -> Result<Vec<StyleResult>, StyleError> {
+        compute_batch(self.backend.as_ref(), &self.document, requests, options).await
+    }
+
+    /// Process the same style requests at each viewport width and compare the results
+    ///
+    /// Widths are in CSS pixels and replace only the viewport width of the media
+    /// environment. `ViewportSweep::report()` lists the properties that change.
+    pub async fn compute_across_viewports(&self, viewports: &[u32], requests: Vec<StyleRequest>) -> Result<ViewportSweep, StyleError> {
+        self.compute_across_viewports_with_options(viewports, requests, &QueryOptions::default()).await
+    }
+
+    /// Process the same style requests at each viewport width with per-call options
+    pub async fn compute_across_viewports_with_options(&self, viewports: &[u32], requests: Vec<StyleRequest>, options: &QueryOptions) -> Result<ViewportSweep, StyleError> {
+        breakpoints::compute_across_viewports(self.backend.as_ref(), &self.document, viewports, requests, options).await
+    }
+}
+
+/// Run a single-property query through `backend`
+pub(crate) async fn compute_value(
+    backend: &dyn StyleBackend,
+    document: &StyleDocument,
+    selector: &str,
+    property: &str,
+    options: &QueryOptions,
+) -> Result<String, StyleError> {
+    let query = StyleQuery::new(selector, Some(property)).with_options(options);
+    take_response(backend.compute_batch(document, vec![query.clone()]).await?, &query.id)?.into_value()
+}
+
+/// Run an all-properties query through `backend`
+pub(crate) async fn compute_all(
+    backend: &dyn StyleBackend,
+    document: &StyleDocument,
+    selector: &str,
+    options: &QueryOptions,
+) -> Result<ComputedStyles, StyleError> {
+    let query = StyleQuery::new(selector, None).with_options(options);
+    take_response(backend.compute_batch(document, vec![query.clone()]).await?, &query.id)?.into_styles()
+}
+
+/// Run a named-properties query through `backend`
+pub(crate) async fn compute_properties(
+    backend: &dyn StyleBackend,
+    document: &StyleDocument,
+    selector: &str,
+    properties: &[&str],
+    options: &QueryOptions,
+) -> Result<ComputedStyles, StyleError> {
+    let query = StyleQuery::for_properties(selector, properties).with_options(options);
+    take_response(backend.compute_batch(document, vec![query.clone()]).await?, &query.id)?.into_styles()
+}
+
+/// Run an all-matches query through `backend`
+pub(crate) async fn compute_all_matches(
+    backend: &dyn StyleBackend,
+    document: &StyleDocument,
+    selector: &str,
+    properties: &[&str],
+    options: &QueryOptions,
+) -> Result<Vec<ElementStyles>, StyleError> {
+    let query = StyleQuery::all_matches(selector, properties).with_options(options);
+    take_response(backend.compute_batch(document, vec![query.clone()]).await?, &query.id)?.into_matches()
+}
+
+/// Run typed requests through `backend` as one batch
+///
+/// Each request is sent as a query with a fresh id, and results are matched to requests
+/// by that id only, never by position. Responses for unknown ids are ignored and a
+/// request without a response fails with a protocol error.
+pub(crate) async fn compute_batch(
+    backend: &dyn StyleBackend,
+    document: &StyleDocument,
+    requests: Vec<StyleRequest>,
+    options: &QueryOptions,
+) -> Result<Vec<StyleResult>, StyleError> {
+    let queries: Vec<StyleQuery> = requests.iter().map(|request| request.to_query(options)).collect();
+    let ids: Vec<String> = queries.iter().map(|query| query.id.clone()).collect();
+
+    let mut by_id: HashMap<String, StyleResponse> = HashMap::new();
+    for response in backend.compute_batch(document, queries).await? {
+        if !ids.contains(&response.id) {
+            tracing::warn!(query_id = %response.id, "Ignoring response for an unknown query");
+            continue;
+        }
+        by_id.entry(response.id.clone()).or_insert(response);
+    }
+
+    Ok(requests.into_iter().zip(ids).map(|(request, id)| {
+        let result = match by_id.remove(&id) {
+            Some(response) => response.into_style_value(),
+            None => Err(StyleError::protocol(format!("No response for request '{}'", request.key))),
+        };
+        StyleResult { key: request.key, result }
+    }).collect())
+}
+
+/// Fill in `default` as the deadline of queries that have none
+pub(crate) fn with_default_timeout(queries: Vec<StyleQuery>, default: Duration) -> Vec<StyleQuery> {
+    queries.into_iter()
+        .map(|mut query| {
+            query.timeout_ms.get_or_insert(default.as_millis() as u64);
+            query
+        })
+        .collect()
+}
+
+/// How long a batch may take overall: the longest of its queries' deadlines
+pub(crate) fn batch_deadline(queries: &[StyleQuery], default: Duration) -> Duration {
+    queries.iter()
+        .map(|query| query.timeout_or(default))
+        .max()
+        .unwrap_or(default)
+}
+
+/// Record the outcome of one query as a tracing event
+pub(crate) fn log_outcome(response: &StyleResponse) {
+    if response.success {
+        tracing::debug!(
+            query_id = %response.id,
+            value = response.computed_value.as_deref(),
+            properties = response.computed_styles.as_ref().map(ComputedStyles::len),
+            matches = response.matches.as_ref().map(Vec::len),
+            "Query succeeded"
+        );
+    } else {
+        tracing::debug!(
+            query_id = %response.id,
+            error = response.error.as_ref().map(tracing::field::display),
+            "Query failed"
+        );
+    }
+}
+
+fn take_response(responses: Vec<StyleResponse>, id: &str) -> Result<StyleResponse, StyleError> {
+    responses.into_iter()
+        .find(|response| response.id == id)
+        .ok_or_else(|| StyleError::protocol("No response received"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Backend answering in reverse order, skipping `.missing` and adding a stray response
+    struct ShufflingBackend;
+
+    #[async_trait]
+    impl StyleBackend for ShufflingBackend {
+        fn name(&self) -> &str {
+            "shuffling"
+        }
+
+        async fn compute_batch(&self, _document: &StyleDocument, queries: Vec<StyleQuery>) -> Result<Vec<StyleResponse>, StyleError> {
+            let mut responses: Vec<StyleResponse> = queries.iter()
+                .filter(|query| query.selector != ".missing")
+                .map(|query| StyleResponse {
+                    id: query.id.clone(),
+                    success: true,
+                    computed_value: Some(format!("{} of {}", query.property.as_deref().unwrap_or_default(), query.selector)),
+                    computed_styles: None,
+                    matches: None,
+                    error: None,
+                })
+                .rev()
+                .collect();
+            responses.insert(1, StyleResponse {
+                id: "unknown".to_string(),
+                success: true,
+                computed_value: Some("stray".to_string()),
+                computed_styles: None,
+                matches: None,
+                error: None,
+            });
+            Ok(responses)
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_results_are_matched_by_query_id() {
+        let engine = StyleEngine::with_backend(ShufflingBackend);
+        let results = engine.compute_styles_batch(vec![
+            StyleRequest::value("first", ".a", "color"),
+            StyleRequest::value("gone", ".missing", "color"),
+            StyleRequest::value("second", ".b", "margin-top"),
+            StyleRequest::value("third", ".c", "display"),
+        ]).await.unwrap();
+
+        let keys: Vec<_> = results.iter().map(|result| result.key.as_str()).collect();
+        assert_eq!(keys, ["first", "gone", "second", "third"]);
+        let value = |index: usize| results[index].result.as_ref().unwrap().to_string();
+        assert_eq!(value(0), "color of .a");
+        assert_eq!(value(2), "margin-top of .b");
+        assert_eq!(value(3), "display of .c");
+        assert!(matches!(results[1].result, Err(StyleError::Protocol { .. })));
+    }
+
+    #[test]
+    fn computed_styles_keep_insertion_order_through_json() {
+        let mut styles = ComputedStyles::new();
+        for (property, value) in [("z-index", "auto"), ("color", "red"), ("margin-top", "0px"), ("align-items", "normal")] {
+            styles.insert(property, value);
+        }
+        let order = ["z-index", "color", "margin-top", "align-items"];
+        assert_eq!(styles.properties().collect::<Vec<_>>(), order);
+
+        let json = serde_json::to_string(&styles).unwrap();
+        assert_eq!(json, r#"{"z-index":"auto","color":"red","margin-top":"0px","align-items":"normal"}"#);
+        let decoded: ComputedStyles = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.properties().collect::<Vec<_>>(), order);
+        assert_eq!(decoded, styles);
+    }
+}
@@ -0,0 +1,118 @@
+//! Stand-in Servo executable driven by a fixture table.
+//!
+//! Accepts the same command line the engines use (`--headless <url>`). For `file://`
+//! pages it prints the console protocol for the queries embedded in the page; for
+//! `http://` daemon pages it polls the loopback channel like the real daemon page.
+//! Fixtures are read from the JSON file named by `FAKE_SERVO_FIXTURES`.
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use stylo_compute::{FakeServo, FakeServoFixtures, StyleQuery, StyleResponse};
+
+#[derive(Deserialize)]
+struct DaemonBatch {
+    batch_id: String,
+    queries: Vec<StyleQuery>,
+}
+
+#[derive(Serialize)]
+struct DaemonResult {
+    batch_id: String,
+    responses: Vec<StyleResponse>,
+}
+
+fn main() {
+    let fixtures = match FakeServoFixtures::from_env() {
+        Ok(fixtures) => fixtures,
+        Err(e) => {
+            eprintln!("fake-servo: failed to load fixtures: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let servo = FakeServo::new(fixtures);
+
+    let Some(url) = std::env::args().skip(1).find(|arg| !arg.starts_with("--")) else {
+        eprintln!("fake-servo: usage: fake-servo [--headless] <url>");
+        std::process::exit(2);
+    };
+
+    std::thread::sleep(Duration::from_millis(servo.fixtures().delay_ms));
+
+    if let Some(path) = url.strip_prefix("file://") {
+        match std::fs::read_to_string(path) {
+            Ok(page) => println!("{}", servo.render_console(&page)),
+            Err(e) => {
+                eprintln!("fake-servo: failed to read {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        let (authority, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let host = authority.trim_end_matches('/');
+        let token = query.split('&').find_map(|pair| pair.strip_prefix("token=")).unwrap_or_default();
+        println!("SERVO_DAEMON_READY");
+        run_daemon(&servo, host, token);
+    } else {
+        eprintln!("fake-servo: unsupported url {}", url);
+        std::process::exit(2);
+    }
+
+    std::process::exit(servo.fixtures().exit_code);
+}
+
+/// Poll the daemon channel until the engine goes away
+fn run_daemon(servo: &FakeServo, host: &str, token: &str) {
+    loop {
+        let Ok((status, body)) = http_request(host, token, "GET", "/next", b"") else {
+            return;
+        };
+        if status == 403 {
+            println!("SERVO_DAEMON_ERROR:channel rejected the token");
+            return;
+        }
+        if status != 200 {
+            continue;
+        }
+
+        let batch: DaemonBatch = match serde_json::from_slice(&body) {
+            Ok(batch) => batch,
+            Err(e) => {
+                println!("SERVO_DAEMON_ERROR:{}", e);
+                continue;
+            }
+        };
+        let result = DaemonResult {
+            batch_id: batch.batch_id,
+            responses: batch.queries.iter().map(|query| servo.respond(query)).collect(),
+        };
+        let payload = serde_json::to_vec(&result).unwrap_or_default();
+        if http_request(host, token, "POST", "/result", &payload).is_err() {
+            return;
+        }
+    }
+}
+
+/// Minimal HTTP/1.1 client for the loopback channel
+fn http_request(host: &str, token: &str, method: &str, path: &str, body: &[u8]) -> std::io::Result<(u16, Vec<u8>)> {
+    let mut stream = TcpStream::connect(host)?;
+    let head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nX-Stylo-Compute-Token: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        method, path, host, token, body.len()
+    );
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+
+    let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap_or(response.len());
+    let status = String::from_utf8_lossy(&response[..split])
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .unwrap_or(0);
+    let body = response.get(split + 4..).unwrap_or_default().to_vec();
+    Ok((status, body))
+}
@@ -0,0 +1,214 @@
+//! Computing the same requests at several viewport widths and reporting what changes.
+//!
+//! Each width is one `compute_styles_batch` call against the document with only the
+//! viewport width of its media environment changed. Daemon engines send every width to
+//! the same workers, since daemon pools are shared by environments that differ only in
+//! viewport size.
+
+use std::collections::HashSet;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{self, QueryOptions, StyleBackend, StyleDocument, StyleRequest, StyleResult, StyleValue};
+use crate::error::StyleError;
+
+/// Results of the same requests computed at several viewport widths
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ViewportSweep {
+    /// Viewport widths in CSS pixels, narrowest first
+    pub viewports: Vec<u32>,
+    pub requests: Vec<StyleRequest>,
+    /// One column per viewport, each holding the results in request order
+    pub results: Vec<Vec<StyleResult>>,
+}
+
+impl ViewportSweep {
+    /// The results computed at `width`, in request order
+    pub fn at(&self, width: u32) -> Option<&[StyleResult]> {
+        let column = self.viewports.iter().position(|&viewport| viewport == width)?;
+        self.results.get(column).map(Vec::as_slice)
+    }
+
+    /// The properties whose values differ between viewports
+    pub fn report(&self) -> BreakpointReport {
+        let mut changes = Vec::new();
+        for (row, request) in self.requests.iter().enumerate() {
+            let cells: Vec<Option<&StyleValue>> = self.results.iter()
+                .map(|column| column.get(row).and_then(|result| result.result.as_ref().ok()))
+                .collect();
+
+            let mut properties: Vec<&str> = Vec::new();
+            for value in cells.iter().flatten() {
+                match value {
+                    StyleValue::Single(_) => properties.extend(request.property.as_deref()),
+                    StyleValue::Map(styles) => properties.extend(styles.properties()),
+                }
+            }
+            let mut seen = HashSet::new();
+            properties.retain(|property| seen.insert(*property));
+
+            for property in properties {
+                let mut values: Vec<(u32, Option<String>)> = Vec::new();
+                for (&width, cell) in self.viewports.iter().zip(&cells) {
+                    let value = cell.and_then(|value| match value {
+                        StyleValue::Single(value) => Some(value.clone()),
+                        StyleValue::Map(styles) => styles.get(property).map(str::to_string),
+                    });
+                    if values.last().is_none_or(|(_, last)| *last != value) {
+                        values.push((width, value));
+                    }
+                }
+                if values.len() > 1 {
+                    changes.push(PropertyChange {
+                        key: request.key.clone(),
+                        selector: request.selector.clone(),
+                        property: property.to_string(),
+                        values,
+                    });
+                }
+            }
+        }
+        BreakpointReport { changes }
+    }
+}
+
+/// A property whose computed value differs between viewports
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PropertyChange {
+    pub key: String,
+    pub selector: String,
+    pub property: String,
+    /// The value at the narrowest viewport, then each new value with the width it first
+    /// appears at; `None` where the property could not be computed
+    pub values: Vec<(u32, Option<String>)>,
+}
+
+impl fmt::Display for PropertyChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {{ {} }}: ", self.selector, self.property)?;
+        for (i, (width, value)) in self.values.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" -> ")?;
+            }
+            write!(f, "{} @{}px", value.as_deref().unwrap_or("(not computed)"), width)?;
+        }
+        Ok(())
+    }
+}
+
+/// The properties of a `ViewportSweep` that change between breakpoints, in request order
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct BreakpointReport {
+    pub changes: Vec<PropertyChange>,
+}
+
+impl BreakpointReport {
+    /// Whether every property computed the same at every viewport
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for BreakpointReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        write!(f, "{} properties change between breakpoints", self.changes.len())
+    }
+}
+
+/// Run `requests` through `backend` once per viewport width, concurrently
+pub(crate) async fn compute_across_viewports(
+    backend: &dyn StyleBackend,
+    document: &StyleDocument,
+    viewports: &[u32],
+    requests: Vec<StyleRequest>,
+    options: &QueryOptions,
+) -> Result<ViewportSweep, StyleError> {
+    let mut viewports = viewports.to_vec();
+    viewports.sort_unstable();
+    viewports.dedup();
+
+    let documents: Vec<StyleDocument> = viewports.iter().map(|&width| {
+        let mut document = document.clone();
+        document.media.viewport_width = width;
+        document
+    }).collect();
+    let results = futures::future::try_join_all(
+        documents.iter().map(|document| backend::compute_batch(backend, document, requests.clone(), options))
+    ).await?;
+
+    Ok(ViewportSweep { viewports, requests, results })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::ComputedStyles;
+
+    fn single(key: &str, value: &str) -> StyleResult {
+        StyleResult { key: key.to_string(), result: Ok(StyleValue::Single(value.to_string())) }
+    }
+
+    fn map(key: &str, styles: &[(&str, &str)]) -> StyleResult {
+        let styles: ComputedStyles = styles.iter().map(|(property, value)| (property.to_string(), value.to_string())).collect();
+        StyleResult { key: key.to_string(), result: Ok(StyleValue::Map(styles)) }
+    }
+
+    fn sweep(viewports: &[u32], requests: Vec<StyleRequest>, results: Vec<Vec<StyleResult>>) -> ViewportSweep {
+        ViewportSweep { viewports: viewports.to_vec(), requests, results }
+    }
+
+    fn at(values: &[(u32, Option<&str>)]) -> Vec<(u32, Option<String>)> {
+        values.iter().map(|(width, value)| (*width, value.map(str::to_string))).collect()
+    }
+
+    #[test]
+    fn runs_of_unchanged_values_collapse_to_their_first_width() {
+        let sweep = sweep(
+            &[320, 480, 768, 1024, 1280],
+            vec![StyleRequest::value("cols", ".grid", "grid-template-columns")],
+            ["1fr", "1fr", "1fr 1fr", "1fr 1fr", "1fr"].iter().map(|value| vec![single("cols", value)]).collect(),
+        );
+        let report = sweep.report();
+        assert_eq!(report.changes.len(), 1);
+        assert_eq!(report.changes[0].property, "grid-template-columns");
+        assert_eq!(report.changes[0].values, at(&[(320, Some("1fr")), (768, Some("1fr 1fr")), (1280, Some("1fr"))]));
+        assert_eq!(
+            report.changes[0].to_string(),
+            ".grid { grid-template-columns }: 1fr @320px -> 1fr 1fr @768px -> 1fr @1280px"
+        );
+    }
+
+    #[test]
+    fn unchanged_properties_are_not_reported() {
+        let sweep = sweep(
+            &[320, 1024],
+            vec![StyleRequest::styles("nav", "nav", &["display", "color"]), StyleRequest::value("h1", "h1", "color")],
+            vec![
+                vec![map("nav", &[("display", "none"), ("color", "red")]), single("h1", "blue")],
+                vec![map("nav", &[("display", "flex"), ("color", "red")]), single("h1", "blue")],
+            ],
+        );
+        let report = sweep.report();
+        assert_eq!(report.changes.len(), 1);
+        assert_eq!((report.changes[0].key.as_str(), report.changes[0].property.as_str()), ("nav", "display"));
+        assert_eq!(report.to_string(), "nav { display }: none @320px -> flex @1024px\n1 properties change between breakpoints");
+        assert!(sweep.at(1024).is_some() && sweep.at(800).is_none());
+    }
+
+    #[test]
+    fn failures_count_as_a_value_of_their_own() {
+        let missing = StyleResult { key: "menu".into(), result: Err(StyleError::ElementNotFound { selector: ".menu".into() }) };
+        let sweep = sweep(
+            &[320, 480, 768],
+            vec![StyleRequest::value("menu", ".menu", "display")],
+            vec![vec![missing.clone()], vec![missing], vec![single("menu", "block")]],
+        );
+        assert_eq!(sweep.report().changes[0].values, at(&[(320, None), (768, Some("block"))]));
+
+        let everywhere = ViewportSweep { results: vec![vec![single("menu", "block")]; 3], ..sweep };
+        assert!(everywhere.report().is_empty());
+    }
+}
@@ -0,0 +1,534 @@
+//! Typed interpretation of computed style strings.
+//!
+//! The engines return values exactly as `getComputedStyle()` serializes them.
+//! `ComputedValue::parse` turns those serializations into structured values, and
+//! `TypedStyles` applies it to the property maps returned for whole elements.
+
+use serde::{Deserialize, Serialize};
+
+use crate::backend::ComputedStyles;
+
+/// A computed value, parsed from Servo's serialization
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", content = "value", rename_all = "kebab-case")]
+pub enum ComputedValue {
+    /// `rgb()`/`rgba()` or hex color
+    Color(Rgba),
+    /// Length in CSS pixels, e.g. `24px`
+    Length(f64),
+    /// Unitless number, e.g. `1.5` or `400`
+    Number(f64),
+    /// Percentage as written, e.g. `50%` is `50.0`
+    Percentage(f64),
+    /// Number with a unit other than `px`, e.g. `0.3s` or `45deg`
+    Dimension { value: f64, unit: String },
+    /// Identifier such as `block`, `auto` or `none`
+    Keyword(String),
+    /// Quoted string with its quotes and escapes removed
+    String(String),
+    /// `url()` reference
+    Url(String),
+    /// Any other function, e.g. `calc()`, `matrix()` or `linear-gradient()`
+    Function { name: String, arguments: String },
+    /// Several values separated by commas or whitespace
+    List { separator: ListSeparator, items: Vec<ComputedValue> },
+    /// A serialization none of the above matched, including the empty string
+    Raw(String),
+}
+
+/// Separator between the items of a `ComputedValue::List`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ListSeparator {
+    Comma,
+    Space,
+}
+
+/// Color with 8-bit channels and a 0–1 alpha
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Rgba {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+    pub alpha: f32,
+}
+
+impl ComputedValue {
+    /// Parse a computed value serialization
+    ///
+    /// Never fails: text that is not recognized is kept as `ComputedValue::Raw`.
+    pub fn parse(text: &str) -> ComputedValue {
+        let text = text.trim();
+        let items = split_top_level(text, |c| c == ',');
+        if items.len() > 1 {
+            return ComputedValue::List {
+                separator: ListSeparator::Comma,
+                items: items.iter().map(|item| ComputedValue::parse(item)).collect(),
+            };
+        }
+        let items = split_top_level(text, char::is_whitespace);
+        if items.len() > 1 {
+            return ComputedValue::List {
+                separator: ListSeparator::Space,
+                items: items.iter().map(|item| parse_component(item)).collect(),
+            };
+        }
+        parse_component(text)
+    }
+
+    /// Whether `self` and `other` denote the same value
+    ///
+    /// Lenient where serializations of equal values differ: numbers compare with a small
+    /// tolerance, `0` equals `0px`, keywords and function names ignore case, and a named
+    /// color keyword equals its `rgb()` form.
+    pub fn equivalent(&self, other: &ComputedValue) -> bool {
+        use ComputedValue::*;
+
+        match (self, other) {
+            (Color(a), Color(b)) => a.equivalent(b),
+            (Color(color), Keyword(name)) | (Keyword(name), Color(color)) => {
+                Rgba::named(name).is_some_and(|named| named.equivalent(color))
+            }
+            (Length(a), Length(b)) | (Number(a), Number(b)) | (Percentage(a), Percentage(b)) => approx_eq(*a, *b),
+            (Length(a), Number(b)) | (Number(b), Length(a)) => *a == 0.0 && *b == 0.0,
+            (Dimension { value: a, unit: unit_a }, Dimension { value: b, unit: unit_b }) => {
+                approx_eq(*a, *b) && unit_a.eq_ignore_ascii_case(unit_b)
+            }
+            (Keyword(a), Keyword(b)) => a.eq_ignore_ascii_case(b),
+            (String(a), String(b)) | (Url(a), Url(b)) => a == b,
+            (Function { name: name_a, arguments: a }, Function { name: name_b, arguments: b }) => {
+                name_a.eq_ignore_ascii_case(name_b) && ComputedValue::parse(a).equivalent(&ComputedValue::parse(b))
+            }
+            (List { separator: sep_a, items: a }, List { separator: sep_b, items: b }) => {
+                sep_a == sep_b && a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.equivalent(b))
+            }
+            (Raw(a), Raw(b)) => a.split_whitespace().eq(b.split_whitespace()),
+            _ => false,
+        }
+    }
+
+    /// The length in pixels, if this is a length
+    pub fn as_px(&self) -> Option<f64> {
+        match self {
+            ComputedValue::Length(px) => Some(*px),
+            _ => None,
+        }
+    }
+
+    /// The number, if this is a unitless number
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            ComputedValue::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    /// The color, if this is one
+    pub fn as_color(&self) -> Option<Rgba> {
+        match self {
+            ComputedValue::Color(color) => Some(*color),
+            _ => None,
+        }
+    }
+
+    /// The identifier, if this is a keyword
+    pub fn as_keyword(&self) -> Option<&str> {
+        match self {
+            ComputedValue::Keyword(keyword) => Some(keyword),
+            _ => None,
+        }
+    }
+}
+
+impl Rgba {
+    /// The color a CSS named color keyword stands for, e.g. `rebeccapurple`
+    pub fn named(name: &str) -> Option<Rgba> {
+        if name.eq_ignore_ascii_case("transparent") {
+            return Some(Rgba { red: 0, green: 0, blue: 0, alpha: 0.0 });
+        }
+        let (_, rgb) = NAMED_COLORS.iter().find(|(named, _)| named.eq_ignore_ascii_case(name))?;
+        Some(Rgba {
+            red: (rgb >> 16) as u8,
+            green: (rgb >> 8) as u8,
+            blue: *rgb as u8,
+            alpha: 1.0,
+        })
+    }
+
+    /// Equal channels, and alphas equal at the 8-bit precision colors are stored with
+    pub fn equivalent(&self, other: &Rgba) -> bool {
+        (self.red, self.green, self.blue) == (other.red, other.green, other.blue)
+            && (self.alpha - other.alpha).abs() <= 0.5 / 255.0 + f32::EPSILON
+    }
+}
+
+impl std::str::FromStr for ComputedValue {
+    type Err = std::convert::Infallible;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Ok(ComputedValue::parse(text))
+    }
+}
+
+/// Typed view over the property map returned for an element
+///
+/// Values are parsed on access; the underlying strings are left untouched.
+#[derive(Debug, Clone, Copy)]
+pub struct TypedStyles<'a> {
+    styles: &'a ComputedStyles,
+}
+
+impl<'a> TypedStyles<'a> {
+    pub fn new(styles: &'a ComputedStyles) -> Self {
+        TypedStyles { styles }
+    }
+
+    /// Parsed value of `property`, if it was computed
+    pub fn get(&self, property: &str) -> Option<ComputedValue> {
+        self.styles.get(property).map(ComputedValue::parse)
+    }
+
+    /// Every property with its parsed value, in the styles' order
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, ComputedValue)> + 'a {
+        self.styles.iter().map(|(property, value)| (property, ComputedValue::parse(value)))
+    }
+
+    /// Parse every property into owned pairs, in the styles' order
+    pub fn to_vec(&self) -> Vec<(String, ComputedValue)> {
+        self.iter().map(|(property, value)| (property.to_string(), value)).collect()
+    }
+}
+
+impl<'a> From<&'a ComputedStyles> for TypedStyles<'a> {
+    fn from(styles: &'a ComputedStyles) -> Self {
+        TypedStyles::new(styles)
+    }
+}
+
+/// CSS named colors as `0xRRGGBB`
+const NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff), ("antiquewhite", 0xfaebd7), ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4), ("azure", 0xf0ffff), ("beige", 0xf5f5dc), ("bisque", 0xffe4c4),
+    ("black", 0x000000), ("blanchedalmond", 0xffebcd), ("blue", 0x0000ff), ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a), ("burlywood", 0xdeb887), ("cadetblue", 0x5f9ea0), ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e), ("coral", 0xff7f50), ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc), ("crimson", 0xdc143c), ("cyan", 0x00ffff), ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b), ("darkgoldenrod", 0xb8860b), ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400), ("darkgrey", 0xa9a9a9), ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b), ("darkolivegreen", 0x556b2f), ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc), ("darkred", 0x8b0000), ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f), ("darkslateblue", 0x483d8b), ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f), ("darkturquoise", 0x00ced1), ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493), ("deepskyblue", 0x00bfff), ("dimgray", 0x696969), ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff), ("firebrick", 0xb22222), ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22), ("fuchsia", 0xff00ff), ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff), ("gold", 0xffd700), ("goldenrod", 0xdaa520), ("gray", 0x808080),
+    ("green", 0x008000), ("greenyellow", 0xadff2f), ("grey", 0x808080), ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4), ("indianred", 0xcd5c5c), ("indigo", 0x4b0082), ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c), ("lavender", 0xe6e6fa), ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00), ("lemonchiffon", 0xfffacd), ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080), ("lightcyan", 0xe0ffff), ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3), ("lightgreen", 0x90ee90), ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1), ("lightsalmon", 0xffa07a), ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa), ("lightslategray", 0x778899), ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de), ("lightyellow", 0xffffe0), ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32), ("linen", 0xfaf0e6), ("magenta", 0xff00ff), ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa), ("mediumblue", 0x0000cd), ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db), ("mediumseagreen", 0x3cb371), ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a), ("mediumturquoise", 0x48d1cc), ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970), ("mintcream", 0xf5fffa), ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5), ("navajowhite", 0xffdead), ("navy", 0x000080), ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000), ("olivedrab", 0x6b8e23), ("orange", 0xffa500), ("orangered", 0xff4500),
+    ("orchid", 0xda70d6), ("palegoldenrod", 0xeee8aa), ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee), ("palevioletred", 0xdb7093), ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9), ("peru", 0xcd853f), ("pink", 0xffc0cb), ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6), ("purple", 0x800080), ("rebeccapurple", 0x663399), ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f), ("royalblue", 0x4169e1), ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072), ("sandybrown", 0xf4a460), ("seagreen", 0x2e8b57), ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d), ("silver", 0xc0c0c0), ("skyblue", 0x87ceeb), ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090), ("slategrey", 0x708090), ("snow", 0xfffafa), ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4), ("tan", 0xd2b48c), ("teal", 0x008080), ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347), ("turquoise", 0x40e0d0), ("violet", 0xee82ee), ("wheat", 0xf5deb3),
+    ("white", 0xffffff), ("whitesmoke", 0xf5f5f5), ("yellow", 0xffff00), ("yellowgreen", 0x9acd32),
+];
+
+/// A single value with no top-level separators
+fn parse_component(text: &str) -> ComputedValue {
+    if text.is_empty() {
+        return ComputedValue::Raw(String::new());
+    }
+    if let Some(quoted) = parse_string(text) {
+        return ComputedValue::String(quoted);
+    }
+    if let Some((name, arguments)) = parse_function(text) {
+        return match name.to_ascii_lowercase().as_str() {
+            "rgb" | "rgba" => parse_rgba(arguments).map_or_else(|| ComputedValue::Raw(text.to_string()), ComputedValue::Color),
+            "url" => ComputedValue::Url(parse_string(arguments.trim()).unwrap_or_else(|| arguments.trim().to_string())),
+            _ => ComputedValue::Function { name: name.to_string(), arguments: arguments.to_string() },
+        };
+    }
+    if let Some(color) = text.strip_prefix('#').and_then(parse_hex) {
+        return ComputedValue::Color(color);
+    }
+    if let Some(value) = parse_numeric(text) {
+        return value;
+    }
+    if is_identifier(text) {
+        return ComputedValue::Keyword(text.to_string());
+    }
+    ComputedValue::Raw(text.to_string())
+}
+
+/// `12`, `1.5e2`, `-4px`, `50%` or `0.3s`
+fn parse_numeric(text: &str) -> Option<ComputedValue> {
+    let bytes = text.as_bytes();
+    let mut end = 0;
+    if matches!(bytes.first(), Some(b'+' | b'-')) {
+        end += 1;
+    }
+    let digits_start = end;
+    while end < bytes.len() && (bytes[end].is_ascii_digit() || bytes[end] == b'.') {
+        end += 1;
+    }
+    if end == digits_start {
+        return None;
+    }
+    if end + 1 < bytes.len() && matches!(bytes[end], b'e' | b'E') && (bytes[end + 1].is_ascii_digit() || matches!(bytes[end + 1], b'+' | b'-')) {
+        end += 2;
+        while end < bytes.len() && bytes[end].is_ascii_digit() {
+            end += 1;
+        }
+    }
+    let value: f64 = text[..end].parse().ok()?;
+    let unit = &text[end..];
+    match unit {
+        "" => Some(ComputedValue::Number(value)),
+        "%" => Some(ComputedValue::Percentage(value)),
+        _ if unit.eq_ignore_ascii_case("px") => Some(ComputedValue::Length(value)),
+        _ if unit.chars().all(|c| c.is_ascii_alphabetic()) => Some(ComputedValue::Dimension {
+            value,
+            unit: unit.to_ascii_lowercase(),
+        }),
+        _ => None,
+    }
+}
+
+/// Channels of `rgb(r, g, b)`, `rgba(r, g, b, a)` or `rgb(r g b / a)`
+fn parse_rgba(arguments: &str) -> Option<Rgba> {
+    let parts: Vec<&str> = arguments
+        .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .collect();
+    if parts.len() != 3 && parts.len() != 4 {
+        return None;
+    }
+    let channel = |part: &str| -> Option<u8> {
+        let value = match part.strip_suffix('%') {
+            Some(percent) => percent.parse::<f64>().ok()? * 2.55,
+            None => part.parse::<f64>().ok()?,
+        };
+        Some(value.round().clamp(0.0, 255.0) as u8)
+    };
+    let alpha = match parts.get(3) {
+        Some(part) => match part.strip_suffix('%') {
+            Some(percent) => percent.parse::<f32>().ok()? / 100.0,
+            None => part.parse::<f32>().ok()?,
+        },
+        None => 1.0,
+    };
+    Some(Rgba {
+        red: channel(parts[0])?,
+        green: channel(parts[1])?,
+        blue: channel(parts[2])?,
+        alpha: alpha.clamp(0.0, 1.0),
+    })
+}
+
+/// `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`, without the `#`
+fn parse_hex(digits: &str) -> Option<Rgba> {
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channels: Vec<u8> = match digits.len() {
+        3 | 4 => digits.chars().map(|c| c.to_digit(16).map(|d| d as u8 * 17)).collect::<Option<_>>()?,
+        6 | 8 => (0..digits.len()).step_by(2).map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok()).collect::<Option<_>>()?,
+        _ => return None,
+    };
+    Some(Rgba {
+        red: channels[0],
+        green: channels[1],
+        blue: channels[2],
+        alpha: channels.get(3).map_or(1.0, |alpha| *alpha as f32 / 255.0),
+    })
+}
+
+/// Name and argument text of `name(arguments)`
+fn parse_function(text: &str) -> Option<(&str, &str)> {
+    let open = text.find('(')?;
+    let name = &text[..open];
+    let arguments = text[open + 1..].strip_suffix(')')?;
+    // The parenthesis opened after the name must be the one closing the text
+    let mut depth = 0usize;
+    for c in arguments.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1)?,
+            _ => {}
+        }
+    }
+    (is_identifier(name) && depth == 0).then_some((name, arguments))
+}
+
+/// Contents of a single- or double-quoted string, unescaped
+fn parse_string(text: &str) -> Option<String> {
+    let quote = text.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let inner = text.strip_prefix(quote)?.strip_suffix(quote)?;
+    let mut unescaped = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            unescaped.extend(chars.next());
+        } else if c == quote {
+            return None;
+        } else {
+            unescaped.push(c);
+        }
+    }
+    Some(unescaped)
+}
+
+/// Equal up to float noise and the rounding of serialized values
+fn approx_eq(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-3 * a.abs().max(b.abs()).max(1.0)
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    let first = chars.next();
+    let first = match first {
+        Some('-') => chars.next(),
+        other => other,
+    };
+    matches!(first, Some(c) if c.is_alphabetic() || c == '_' || c == '-')
+        && chars.all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Split `text` at separators outside parentheses and quotes, dropping empty pieces
+fn split_top_level(text: &str, is_separator: impl Fn(char) -> bool) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (_, '\\') => escaped = true,
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') if depth > 0 => depth -= 1,
+            (None, _) if depth == 0 && is_separator(c) => {
+                pieces.push(text[start..i].trim());
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    pieces.push(text[start..].trim());
+    pieces.retain(|piece| !piece.is_empty());
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgba(red: u8, green: u8, blue: u8, alpha: f32) -> ComputedValue {
+        ComputedValue::Color(Rgba { red, green, blue, alpha })
+    }
+
+    fn equivalent(a: &str, b: &str) -> bool {
+        ComputedValue::parse(a).equivalent(&ComputedValue::parse(b))
+    }
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(ComputedValue::parse("rgb(255, 0, 0)"), rgba(255, 0, 0, 1.0));
+        assert_eq!(ComputedValue::parse("rgba(0, 128, 255, 0.5)"), rgba(0, 128, 255, 0.5));
+        assert_eq!(ComputedValue::parse("rgb(0 0 0 / 25%)"), rgba(0, 0, 0, 0.25));
+        assert_eq!(ComputedValue::parse("#f00"), rgba(255, 0, 0, 1.0));
+        assert_eq!(ComputedValue::parse("#00ff0080"), rgba(0, 255, 0, 128.0 / 255.0));
+        assert_eq!(ComputedValue::parse("#12345"), ComputedValue::Raw("#12345".into()));
+        assert_eq!(ComputedValue::parse("red"), ComputedValue::Keyword("red".into()));
+    }
+
+    #[test]
+    fn named_and_hex_colors_equal_their_rgb_forms() {
+        assert!(equivalent("red", "rgb(255, 0, 0)"));
+        assert!(equivalent("RebeccaPurple", "rgb(102, 51, 153)"));
+        assert!(equivalent("transparent", "rgba(0, 0, 0, 0)"));
+        assert!(equivalent("#ff000080", "rgba(255, 0, 0, 0.5)"));
+        assert!(!equivalent("red", "rgb(254, 0, 0)"));
+        assert!(!equivalent("auto", "rgb(0, 0, 0)"));
+    }
+
+    #[test]
+    fn parses_numbers_and_dimensions() {
+        assert_eq!(ComputedValue::parse("24px"), ComputedValue::Length(24.0));
+        assert_eq!(ComputedValue::parse("-1.5e2px"), ComputedValue::Length(-150.0));
+        assert_eq!(ComputedValue::parse("400"), ComputedValue::Number(400.0));
+        assert_eq!(ComputedValue::parse("50%"), ComputedValue::Percentage(50.0));
+        assert_eq!(ComputedValue::parse("0.3S"), ComputedValue::Dimension { value: 0.3, unit: "s".into() });
+        assert_eq!(ComputedValue::parse(""), ComputedValue::Raw(String::new()));
+    }
+
+    #[test]
+    fn zero_equals_zero_px_only() {
+        assert!(equivalent("0", "0px"));
+        assert!(equivalent("0px", "0"));
+        assert!(!equivalent("1", "1px"));
+        assert!(!equivalent("0", "0%"));
+        assert!(equivalent("16.0001px", "16px"));
+    }
+
+    #[test]
+    fn parses_nested_functions() {
+        let value = ComputedValue::parse("linear-gradient(rgb(255, 0, 0), calc(50% + (2px * 3)))");
+        let ComputedValue::Function { ref name, ref arguments } = value else { panic!("{:?}", value) };
+        assert_eq!(name, "linear-gradient");
+        assert_eq!(arguments, "rgb(255, 0, 0), calc(50% + (2px * 3))");
+
+        // Functions next to each other are a list, not one function
+        let list = ComputedValue::parse("translate(1px) rotate(2deg)");
+        assert!(matches!(list, ComputedValue::List { separator: ListSeparator::Space, ref items } if items.len() == 2));
+        assert_eq!(ComputedValue::parse("url(\"a b.png\")"), ComputedValue::Url("a b.png".into()));
+    }
+
+    #[test]
+    fn nested_functions_compare_their_arguments() {
+        assert!(equivalent("drop-shadow(red 0 0 2px)", "DROP-SHADOW(rgb(255, 0, 0) 0px 0px 2px)"));
+        assert!(equivalent("calc(min(10px, 5%) + 0)", "calc(min(10px, 5%) + 0px)"));
+        assert!(!equivalent("scale(1.5)", "scale(2)"));
+    }
+
+    #[test]
+    fn splits_lists_outside_quotes_and_parentheses() {
+        let ComputedValue::List { separator, items } = ComputedValue::parse("\"Helvetica Neue\", Arial, sans-serif") else {
+            panic!("expected a list");
+        };
+        assert_eq!(separator, ListSeparator::Comma);
+        assert_eq!(items, [
+            ComputedValue::String("Helvetica Neue".into()),
+            ComputedValue::Keyword("Arial".into()),
+            ComputedValue::Keyword("sans-serif".into()),
+        ]);
+        assert!(equivalent("0px  0px", "0 0"));
+        assert!(!equivalent("1px 2px", "1px, 2px"));
+    }
+}
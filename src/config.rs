@@ -0,0 +1,364 @@
+//! Typed engine configuration.
+//!
+//! `EngineConfig` is built from defaults, then `servo_config.toml`, then `STYLO_COMPUTE_*`
+//! environment variables, then builder overrides, each layer replacing the settings it
+//! specifies. Every engine constructor accepts one.
+//!
+//! Logging is not configured here: the engines emit `tracing` events, filtered by the
+//! subscriber the application installs (e.g. `RUST_LOG=stylo_compute=debug`, or
+//! `stylo_compute=trace` to include Servo's console output). The `verbose_logging` and
+//! `show_servo_output` keys older config files carry are ignored.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use serde::Deserialize;
+
+use crate::backend::BackendKind;
+use crate::error::StyleError;
+
+/// Config file read by `EngineConfig::load()` from the working directory
+pub const DEFAULT_CONFIG_FILE: &str = "servo_config.toml";
+
+/// Configuration shared by every engine constructor
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineConfig {
+    /// Servo executable; `None` looks `servo` up on `PATH`
+    pub servo_path: Option<String>,
+    /// Which built-in backend `StyleEngine::from_config` creates
+    pub mode: BackendKind,
+    /// How long a Servo run or daemon batch may take
+    pub timeout: Duration,
+    /// Queries per Servo batch
+    pub batch_size: usize,
+    /// Servo daemon workers in daemon mode
+    pub workers: usize,
+    /// Directory debug artifacts are written to; `None` disables them
+    pub debug_artifact_dir: Option<PathBuf>,
+    /// Arguments passed to Servo in addition to `--headless <url>`
+    pub extra_servo_args: Vec<String>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            servo_path: None,
+            mode: BackendKind::Daemon,
+            timeout: Duration::from_secs(10),
+            batch_size: 5,
+            workers: 1,
+            debug_artifact_dir: None,
+            extra_servo_args: Vec::new(),
+        }
+    }
+}
+
+/// On-disk layout of `servo_config.toml`; unknown sections and keys are ignored
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ConfigFile {
+    servo: ServoSection,
+    engine: EngineSection,
+    debug: DebugSection,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ServoSection {
+    executable_path: Option<String>,
+    mode: Option<String>,
+    extra_args: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct EngineSection {
+    timeout_ms: Option<u64>,
+    batch_size: Option<usize>,
+    workers: Option<usize>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct DebugSection {
+    artifact_dir: Option<PathBuf>,
+}
+
+impl FromStr for BackendKind {
+    type Err = StyleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "one-shot" | "oneshot" => Ok(BackendKind::OneShot),
+            "batch" => Ok(BackendKind::Batch),
+            "daemon" => Ok(BackendKind::Daemon),
+            #[cfg(feature = "native-stylo")]
+            "native" => Ok(BackendKind::Native),
+            other => Err(StyleError::config(format!(
+                "Unknown engine mode '{}' (expected one-shot, batch or daemon)", other
+            ))),
+        }
+    }
+}
+
+impl EngineConfig {
+    /// Defaults, overlaid with `servo_config.toml` if present, then the environment
+    pub fn load() -> Result<Self, StyleError> {
+        let config = if Path::new(DEFAULT_CONFIG_FILE).exists() {
+            Self::from_file(DEFAULT_CONFIG_FILE)?
+        } else {
+            Self::default()
+        };
+        config.apply_env()
+    }
+
+    /// Defaults overlaid with the environment
+    pub fn from_env() -> Result<Self, StyleError> {
+        Self::default().apply_env()
+    }
+
+    /// Defaults overlaid with a TOML config file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, StyleError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            StyleError::config(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+        Self::default().apply_toml(&content)
+    }
+
+    /// Defaults overlaid with TOML config content
+    pub fn from_toml_str(content: &str) -> Result<Self, StyleError> {
+        Self::default().apply_toml(content)
+    }
+
+    /// Overlay the settings present in TOML config content
+    pub fn apply_toml(mut self, content: &str) -> Result<Self, StyleError> {
+        let file: ConfigFile = toml::from_str(content)
+            .map_err(|e| StyleError::config(format!("Invalid config: {}", e)))?;
+
+        if let Some(path) = file.servo.executable_path {
+            self.servo_path = Some(path);
+        }
+        if let Some(mode) = file.servo.mode {
+            self.mode = parse_mode(&mode)?.unwrap_or(self.mode);
+        }
+        if let Some(args) = file.servo.extra_args {
+            self.extra_servo_args = args;
+        }
+        if let Some(timeout_ms) = file.engine.timeout_ms {
+            self.timeout = Duration::from_millis(timeout_ms);
+        }
+        if let Some(batch_size) = file.engine.batch_size {
+            self.batch_size = batch_size;
+        }
+        if let Some(workers) = file.engine.workers {
+            self.workers = workers;
+        }
+        if let Some(dir) = file.debug.artifact_dir {
+            self.debug_artifact_dir = Some(dir);
+        }
+
+        Ok(self)
+    }
+
+    /// Overlay the settings present in `STYLO_COMPUTE_*` environment variables
+    ///
+    /// - `STYLO_COMPUTE_SERVO_PATH`
+    /// - `STYLO_COMPUTE_MODE` (`one-shot`, `batch` or `daemon`)
+    /// - `STYLO_COMPUTE_TIMEOUT_MS`
+    /// - `STYLO_COMPUTE_BATCH_SIZE`
+    /// - `STYLO_COMPUTE_WORKERS`
+    /// - `STYLO_COMPUTE_DEBUG_DIR`
+    /// - `STYLO_COMPUTE_SERVO_ARGS` (whitespace separated)
+    pub fn apply_env(mut self) -> Result<Self, StyleError> {
+        if let Some(path) = env_var("STYLO_COMPUTE_SERVO_PATH") {
+            self.servo_path = Some(path);
+        }
+        if let Some(mode) = env_var("STYLO_COMPUTE_MODE") {
+            self.mode = parse_mode(&mode)?.unwrap_or(self.mode);
+        }
+        if let Some(timeout_ms) = env_parse::<u64>("STYLO_COMPUTE_TIMEOUT_MS")? {
+            self.timeout = Duration::from_millis(timeout_ms);
+        }
+        if let Some(batch_size) = env_parse("STYLO_COMPUTE_BATCH_SIZE")? {
+            self.batch_size = batch_size;
+        }
+        if let Some(workers) = env_parse("STYLO_COMPUTE_WORKERS")? {
+            self.workers = workers;
+        }
+        if let Some(dir) = env_var("STYLO_COMPUTE_DEBUG_DIR") {
+            self.debug_artifact_dir = Some(PathBuf::from(dir));
+        }
+        if let Some(args) = env_var("STYLO_COMPUTE_SERVO_ARGS") {
+            self.extra_servo_args = args.split_whitespace().map(str::to_string).collect();
+        }
+
+        Ok(self)
+    }
+
+    pub fn with_servo_path(mut self, servo_path: impl Into<String>) -> Self {
+        self.servo_path = Some(servo_path.into());
+        self
+    }
+
+    pub fn with_mode(mut self, mode: BackendKind) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    pub fn with_debug_artifact_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.debug_artifact_dir = Some(dir.into());
+        self
+    }
+
+    pub fn with_extra_servo_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.extra_servo_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// The Servo executable to run
+    pub fn servo_command(&self) -> &str {
+        self.servo_path.as_deref().unwrap_or("servo")
+    }
+
+    /// Check that the configured Servo executable exists
+    pub fn check_servo(&self) -> Result<(), StyleError> {
+        let found = match self.servo_path {
+            Some(ref path) => Path::new(path).exists(),
+            None => which::which("servo").is_ok(),
+        };
+        if found {
+            Ok(())
+        } else {
+            Err(StyleError::ServoNotFound)
+        }
+    }
+}
+
+/// The backend a configured mode names; `None` for `real`, which older setup scripts
+/// write to mean "use real Servo" and which keeps the mode already set
+fn parse_mode(mode: &str) -> Result<Option<BackendKind>, StyleError> {
+    if mode.trim().eq_ignore_ascii_case("real") {
+        Ok(None)
+    } else {
+        mode.parse().map(Some)
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+fn env_parse<T: FromStr>(name: &str) -> Result<Option<T>, StyleError> {
+    match env_var(name) {
+        Some(value) => value.trim().parse().map(Some).map_err(|_| {
+            StyleError::config(format!("Invalid value for {}: '{}'", name, value))
+        }),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_overlays_only_the_settings_it_names() {
+        let config = EngineConfig::default().with_workers(3).apply_toml(r#"
+            [servo]
+            executable_path = "/opt/servo"
+            mode = "batch"
+            extra_args = ["--pref=dom_svg_enabled"]
+
+            [engine]
+            timeout_ms = 2500
+
+            [debug]
+            artifact_dir = "servo_debug"
+        "#).unwrap();
+        assert_eq!(config.servo_path.as_deref(), Some("/opt/servo"));
+        assert_eq!(config.mode, BackendKind::Batch);
+        assert_eq!(config.extra_servo_args, ["--pref=dom_svg_enabled"]);
+        assert_eq!(config.timeout, Duration::from_millis(2500));
+        assert_eq!(config.batch_size, 5);
+        assert_eq!(config.workers, 3);
+        assert_eq!(config.debug_artifact_dir, Some(PathBuf::from("servo_debug")));
+    }
+
+    #[test]
+    fn real_mode_keeps_the_current_mode() {
+        let config = EngineConfig::from_toml_str("[servo]\nmode = \"real\"").unwrap();
+        assert_eq!(config.mode, BackendKind::Daemon);
+        let config = EngineConfig::default().with_mode(BackendKind::OneShot).apply_toml("[servo]\nmode = \"Real\"").unwrap();
+        assert_eq!(config.mode, BackendKind::OneShot);
+    }
+
+    #[test]
+    fn legacy_sections_are_ignored() {
+        let config = EngineConfig::from_toml_str(r#"
+            [integration]
+            enable_real_integration = true
+
+            [debug]
+            verbose_logging = true
+            show_servo_output = true
+        "#).unwrap();
+        assert_eq!(config, EngineConfig::default());
+    }
+
+    #[test]
+    fn rejects_unknown_modes_and_malformed_files() {
+        assert!(matches!(EngineConfig::from_toml_str("[servo]\nmode = \"fast\""), Err(StyleError::Config { .. })));
+        assert!(matches!(EngineConfig::from_toml_str("[engine]\nworkers = \"two\""), Err(StyleError::Config { .. })));
+    }
+
+    /// The only test touching `STYLO_COMPUTE_*`, as tests share the process environment
+    #[test]
+    fn environment_overrides_the_file() {
+        let vars = [
+            ("STYLO_COMPUTE_MODE", "real"),
+            ("STYLO_COMPUTE_BATCH_SIZE", " 12 "),
+            ("STYLO_COMPUTE_SERVO_ARGS", "--a  --b"),
+            ("STYLO_COMPUTE_WORKERS", ""),
+        ];
+        for (name, value) in vars {
+            std::env::set_var(name, value);
+        }
+        let config = EngineConfig::from_toml_str("[servo]\nmode = \"one-shot\"\n[engine]\nworkers = 2").unwrap().apply_env();
+
+        std::env::set_var("STYLO_COMPUTE_MODE", "batch");
+        let batch = EngineConfig::from_env();
+        std::env::set_var("STYLO_COMPUTE_TIMEOUT_MS", "soon");
+        let invalid = EngineConfig::from_env();
+        for name in vars.map(|(name, _)| name).iter().chain(&["STYLO_COMPUTE_TIMEOUT_MS"]) {
+            std::env::remove_var(name);
+        }
+
+        let config = config.unwrap();
+        assert_eq!(config.mode, BackendKind::OneShot);
+        assert_eq!(config.batch_size, 12);
+        assert_eq!(config.extra_servo_args, ["--a", "--b"]);
+        assert_eq!(config.workers, 2);
+        assert!(matches!(invalid, Err(StyleError::Config { .. })));
+        assert_eq!(batch.unwrap().mode, BackendKind::Batch);
+    }
+}
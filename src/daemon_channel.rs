@@ -0,0 +1,469 @@
+//! Loopback HTTP channel between the engine and a long-lived Servo daemon page.
+//!
+//! The daemon page is served from `http://127.0.0.1:<port>/` and long-polls `/next`
+//! for batches. Each batch document is rendered into an iframe, the queries are
+//! evaluated with `getComputedStyle()`, and the responses are POSTed back to `/result`,
+//! where they are routed to the waiting caller by batch id.
+//!
+//! Every channel has its own secret token, passed to the page in its URL. The page sends
+//! it back in a `X-Stylo-Compute-Token` header, and `/next` and `/result` refuse requests
+//! without it, so other local processes cannot take or answer batches.
+//!
+//! The iframe is sandboxed without `allow-scripts`: the daemon page can still read the
+//! document's styles, but the document's own scripts never run, so they cannot reach the
+//! daemon page's `fetch`, `JSON` or the channel endpoints. Styles that depend on a
+//! document script running are therefore only computed by the one-shot and batch backends.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
+
+use crate::backend::{StyleQuery, StyleResponse};
+use crate::error::StyleError;
+use crate::media::MediaEnvironment;
+use crate::page_script;
+
+/// How long a `/next` request is held open before the page is told to poll again
+const LONG_POLL_INTERVAL: Duration = Duration::from_secs(20);
+
+/// Upper bound on request bodies accepted from the daemon page
+const MAX_BODY_SIZE: usize = 64 * 1024 * 1024;
+
+/// Header carrying the channel's token on `/next` and `/result`
+const TOKEN_HEADER: &str = "X-Stylo-Compute-Token";
+
+/// Page loaded into the daemon Servo instance. It pulls batches from `/next`,
+/// evaluates them inside an iframe and posts the responses to `/result`.
+pub(crate) const DAEMON_PAGE: &str = concat!(r#"<!DOCTYPE html>
+<html>
+<head>
+    <title>Servo Style Daemon</title>
+    <style>
+        html, body { margin: 0; padding: 0; }
+        iframe { position: fixed; top: 0; left: 0; width: 100%; height: 100%; border: 0; }
+    </style>
+</head>
+<body>
+    <script>
+        (function() {
+"#, page_script::page_helpers!(), r#"
+            function failed(query, error) {
+                return { id: query.id, success: false, error: error };
+            }
+
+            function evaluateAll(doc, win, query) {
+                var unknown = firstUnknownProperty(win, query.properties);
+                if (unknown) {
+                    return failed(query, { kind: 'unknown-property', property: unknown });
+                }
+                var elements = doc.querySelectorAll(query.selector);
+                var matches = [];
+                for (var i = 0; i < elements.length; i++) {
+                    var restore = forceStates(elements[i], query);
+                    try {
+                        matches.push({
+                            element: describe(elements[i], i),
+                            styles: stylesOf(win.getComputedStyle(elements[i], query.pseudo_element || null), query.properties)
+                        });
+                    } finally {
+                        restore();
+                    }
+                }
+                return { id: query.id, success: true, matches: matches };
+            }
+
+            function evaluateOne(doc, win, query) {
+                try {
+                    if (query.all_matches) {
+                        return evaluateAll(doc, win, query);
+                    }
+                    var element = doc.querySelector(query.selector);
+                    if (!element) {
+                        return failed(query, { kind: 'element-not-found', selector: query.selector });
+                    }
+                    var restore = forceStates(element, query);
+                    try {
+                        var computedStyle = win.getComputedStyle(element, query.pseudo_element || null);
+                        if (query.property !== null && query.property !== undefined) {
+                            if (isUnknownProperty(win, query.property)) {
+                                return failed(query, { kind: 'unknown-property', property: query.property });
+                            }
+                            return {
+                                id: query.id,
+                                success: true,
+                                computed_value: computedStyle.getPropertyValue(query.property)
+                            };
+                        }
+                        var unknown = firstUnknownProperty(win, query.properties);
+                        if (unknown) {
+                            return failed(query, { kind: 'unknown-property', property: unknown });
+                        }
+                        return { id: query.id, success: true, computed_styles: stylesOf(computedStyle, query.properties) };
+                    } finally {
+                        restore();
+                    }
+                } catch (e) {
+                    if (e && e.name === 'SyntaxError') {
+                        return failed(query, { kind: 'invalid-selector', selector: query.selector, message: String(e.message) });
+                    }
+                    return failed(query, { kind: 'script', message: String(e && e.message) });
+                }
+            }
+
+            function timedOut(query, elapsed) {
+                return failed(query, { kind: 'timeout', waited_ms: elapsed });
+            }
+
+            // Watchdog: a query that overruns its timeout_ms is reported as timed out, and
+            // queries still waiting once the batch's longest deadline has passed are skipped
+            function evaluate(frame, queries, media) {
+                var doc = frame.contentDocument;
+                var win = frame.contentWindow;
+                var started = Date.now();
+                var deadline = queries.reduce(function(max, query) { return Math.max(max, budget(query)); }, 0);
+                emulateMedia(doc, media);
+                if (queries.some(function(query) { return query.forced_states && query.forced_states.length; })) {
+                    prepareStates(doc);
+                }
+                return queries.map(function(query) {
+                    var begin = Date.now();
+                    if (begin - started >= deadline) {
+                        return timedOut(query, begin - started);
+                    }
+                    var response = evaluateOne(doc, win, query);
+                    var elapsed = Date.now() - begin;
+                    return elapsed > budget(query) ? timedOut(query, elapsed) : response;
+                });
+            }
+
+            function run(batch) {
+                return new Promise(function(resolve) {
+                    var frame = document.createElement('iframe');
+                    frame.onload = function() {
+                        var responses = evaluate(frame, batch.queries, batch.media);
+                        frame.parentNode.removeChild(frame);
+                        resolve({ batch_id: batch.batch_id, responses: responses });
+                    };
+                    // Same origin so the styles can be read, but no scripts: the document must
+                    // not be able to reach this page or the channel
+                    frame.setAttribute('sandbox', 'allow-same-origin');
+                    // The frame is the document's viewport
+                    frame.style.width = batch.media.viewport_width + 'px';
+                    frame.style.height = batch.media.viewport_height + 'px';
+                    frame.srcdoc = batch.document;
+                    document.body.appendChild(frame);
+                });
+            }
+
+            var token = new URLSearchParams(location.search).get('token');
+            var headers = { 'X-Stylo-Compute-Token': token };
+
+            // Exit once the engine has gone away so orphaned daemons do not linger
+            var failures = 0;
+
+            function poll() {
+                var delay = 0;
+                fetch('/next', { cache: 'no-store', headers: headers })
+                    .then(function(response) {
+                        failures = 0;
+                        if (response.status !== 200) {
+                            return null;
+                        }
+                        return response.json().then(run).then(function(result) {
+                            return fetch('/result', { method: 'POST', headers: headers, body: JSON.stringify(result) });
+                        });
+                    })
+                    .catch(function(e) {
+                        console.log('SERVO_DAEMON_ERROR:' + e);
+                        failures++;
+                        delay = 500;
+                    })
+                    .then(function() {
+                        if (failures >= 10) {
+                            window.close();
+                        } else {
+                            setTimeout(poll, delay);
+                        }
+                    });
+            }
+
+            console.log('SERVO_DAEMON_READY');
+            poll();
+        })();
+    </script>
+</body>
+</html>"#);
+
+/// A batch of queries sent to the daemon page, evaluated against `document` in `media`
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct BatchQuery {
+    pub(crate) batch_id: String,
+    pub(crate) document: String,
+    pub(crate) media: MediaEnvironment,
+    pub(crate) queries: Vec<StyleQuery>,
+}
+
+/// Responses posted back by the daemon page for one batch
+#[derive(Serialize, Deserialize, Debug)]
+struct BatchResult {
+    batch_id: String,
+    responses: Vec<StyleResponse>,
+}
+
+struct ChannelState {
+    token: String,
+    jobs_tx: mpsc::UnboundedSender<BatchQuery>,
+    jobs_rx: tokio::sync::Mutex<mpsc::UnboundedReceiver<BatchQuery>>,
+    pending: Mutex<HashMap<String, oneshot::Sender<Vec<StyleResponse>>>>,
+    ready: watch::Sender<bool>,
+}
+
+impl ChannelState {
+    /// Whether a caller is still waiting for the responses of batch `batch_id`
+    fn is_awaited(&self, batch_id: &str) -> bool {
+        self.pending.lock().unwrap().get(batch_id).is_some_and(|sender| !sender.is_closed())
+    }
+}
+
+/// Request/response channel to a Servo daemon page over a loopback HTTP endpoint
+pub(crate) struct DaemonChannel {
+    addr: SocketAddr,
+    state: Arc<ChannelState>,
+    accept_task: JoinHandle<()>,
+}
+
+impl DaemonChannel {
+    /// Bind a loopback listener on an ephemeral port and start serving the daemon page
+    pub(crate) async fn bind() -> Result<Self, StyleError> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let addr = listener.local_addr()?;
+
+        let (jobs_tx, jobs_rx) = mpsc::unbounded_channel();
+        let (ready, _) = watch::channel(false);
+        let state = Arc::new(ChannelState {
+            token: uuid::Uuid::new_v4().simple().to_string(),
+            jobs_tx,
+            jobs_rx: tokio::sync::Mutex::new(jobs_rx),
+            pending: Mutex::new(HashMap::new()),
+            ready,
+        });
+
+        let accept_state = state.clone();
+        let accept_task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = accept_state.clone();
+                tokio::spawn(async move {
+                    let _ = handle_connection(stream, state).await;
+                });
+            }
+        });
+
+        Ok(DaemonChannel {
+            addr,
+            state,
+            accept_task,
+        })
+    }
+
+    /// Loopback address the channel listens on
+    pub(crate) fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// URL the daemon Servo instance should load, carrying the channel's token
+    pub(crate) fn page_url(&self) -> String {
+        format!("http://{}/?token={}", self.addr, self.state.token)
+    }
+
+    /// Wait until the daemon page has started polling for batches
+    pub(crate) async fn wait_ready(&self, timeout: Duration) -> Result<(), StyleError> {
+        let mut ready = self.state.ready.subscribe();
+        let connected = tokio::time::timeout(timeout, ready.wait_for(|ready| *ready))
+            .await
+            .map(|result| result.is_ok());
+        match connected {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(StyleError::daemon("Daemon channel closed")),
+            Err(_) => Err(StyleError::daemon(format!(
+                "Daemon page did not connect within {} seconds", timeout.as_secs()
+            ))),
+        }
+    }
+
+    /// Send a batch to the daemon page and wait for its responses
+    pub(crate) async fn submit(&self, batch: BatchQuery, timeout: Duration) -> Result<Vec<StyleResponse>, StyleError> {
+        let batch_id = batch.batch_id.clone();
+        let (tx, rx) = oneshot::channel();
+        self.state.pending.lock().unwrap().insert(batch_id.clone(), tx);
+
+        if self.state.jobs_tx.send(batch).is_err() {
+            self.state.pending.lock().unwrap().remove(&batch_id);
+            return Err(StyleError::daemon("Daemon channel closed"));
+        }
+
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(responses)) => Ok(responses),
+            Ok(Err(_)) => Err(StyleError::daemon(format!("Batch {} was dropped", batch_id))),
+            Err(_) => {
+                self.state.pending.lock().unwrap().remove(&batch_id);
+                Err(StyleError::Timeout { waited: timeout })
+            }
+        }
+    }
+}
+
+impl Drop for DaemonChannel {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+    }
+}
+
+/// Serve a single HTTP/1.1 request from the daemon page
+async fn handle_connection(stream: TcpStream, state: Arc<ChannelState>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    let mut token = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            } else if name.trim().eq_ignore_ascii_case(TOKEN_HEADER) {
+                token = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    // Requests without the token are turned away before their body is read
+    let path = path.split('?').next().unwrap_or_default();
+    let authorized = token.as_deref() == Some(state.token.as_str());
+    if matches!((method.as_str(), path), ("GET", "/next") | ("POST", "/result")) && !authorized {
+        return write_response(reader.get_mut(), "403 Forbidden", "text/plain", b"").await;
+    }
+    if content_length > MAX_BODY_SIZE {
+        return write_response(reader.get_mut(), "413 Payload Too Large", "text/plain", b"").await;
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    let stream = reader.get_mut();
+    match (method.as_str(), path) {
+        ("GET", "/") => {
+            write_response(stream, "200 OK", "text/html; charset=utf-8", DAEMON_PAGE.as_bytes()).await
+        }
+        ("GET", "/next") => {
+            state.ready.send_replace(true);
+            let job = {
+                let mut jobs = state.jobs_rx.lock().await;
+                let deadline = tokio::time::Instant::now() + LONG_POLL_INTERVAL;
+                loop {
+                    match tokio::time::timeout_at(deadline, jobs.recv()).await.ok().flatten() {
+                        // Batches nobody waits for any more are dropped without being evaluated
+                        Some(batch) if !state.is_awaited(&batch.batch_id) => continue,
+                        job => break job,
+                    }
+                }
+            };
+            match job {
+                Some(batch) => {
+                    let payload = serde_json::to_vec(&batch)?;
+                    write_response(stream, "200 OK", "application/json", &payload).await
+                }
+                None => write_response(stream, "204 No Content", "text/plain", b"").await,
+            }
+        }
+        ("POST", "/result") => {
+            match serde_json::from_slice::<BatchResult>(&body) {
+                Ok(result) => {
+                    let sender = state.pending.lock().unwrap().remove(&result.batch_id);
+                    if let Some(sender) = sender {
+                        let _ = sender.send(result.responses);
+                    }
+                    write_response(stream, "204 No Content", "text/plain", b"").await
+                }
+                Err(_) => write_response(stream, "400 Bad Request", "text/plain", b"").await,
+            }
+        }
+        _ => write_response(stream, "404 Not Found", "text/plain", b"").await,
+    }
+}
+
+async fn write_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> std::io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status, content_type, body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Send `request` to the channel and return the response's status line
+    async fn status_of(channel: &DaemonChannel, request: &str) -> String {
+        let mut stream = TcpStream::connect(channel.addr()).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut status = String::new();
+        BufReader::new(stream).read_line(&mut status).await.unwrap();
+        status.trim_end().to_string()
+    }
+
+    fn batch(batch_id: &str) -> BatchQuery {
+        BatchQuery {
+            batch_id: batch_id.to_string(),
+            document: String::new(),
+            media: MediaEnvironment::default(),
+            queries: vec![StyleQuery::new("p", Some("color"))],
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_requests_without_the_token_before_reading_the_body() {
+        let channel = DaemonChannel::bind().await.unwrap();
+        // The body is never sent: the answer must not wait for it
+        let request = format!("POST /result HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY_SIZE);
+        let status = tokio::time::timeout(Duration::from_secs(5), status_of(&channel, &request)).await.unwrap();
+        assert_eq!(status, "HTTP/1.1 403 Forbidden");
+
+        let request = format!("GET /next HTTP/1.1\r\n{}: wrong\r\n\r\n", TOKEN_HEADER);
+        assert_eq!(status_of(&channel, &request).await, "HTTP/1.1 403 Forbidden");
+        assert_eq!(status_of(&channel, "GET / HTTP/1.1\r\n\r\n").await, "HTTP/1.1 200 OK");
+    }
+
+    #[tokio::test]
+    async fn skips_batches_whose_caller_gave_up() {
+        let channel = Arc::new(DaemonChannel::bind().await.unwrap());
+        let stale = channel.submit(batch("stale"), Duration::from_millis(10)).await;
+        assert!(matches!(stale, Err(StyleError::Timeout { .. })));
+
+        let waiting = channel.clone();
+        let fresh = tokio::spawn(async move { waiting.submit(batch("fresh"), Duration::from_secs(30)).await });
+        let mut stream = TcpStream::connect(channel.addr()).await.unwrap();
+        let request = format!("GET /next HTTP/1.1\r\n{}: {}\r\n\r\n", TOKEN_HEADER, channel.state.token);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.contains(r#""batch_id":"fresh""#), "{}", response);
+        assert!(!response.contains("stale"));
+        fresh.abort();
+    }
+}
@@ -1,34 +1,125 @@
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 /// Errors that can occur during style computation
-#[derive(Error, Debug)]
+///
+/// Failures of individual queries travel inside `StyleResponse`s, so the error is
+/// serializable: it is tagged by `kind` (`"element-not-found"`, `"timeout"`, ...).
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
 pub enum StyleError {
-    #[error("Invalid DOM element: {0}")]
-    InvalidElement(String),
-    
-    #[error("Style computation failed: {0}")]
-    ComputationFailed(String),
-    
-    #[error("CSS parsing error: {0}")]
-    CssParsingError(String),
-    
-    #[error("Missing required stylesheet")]
-    MissingStylesheet,
-    
-    #[error("Invalid CSS property: {0}")]
-    InvalidProperty(String),
-    
-    #[error("Style context not initialized")]
-    ContextNotInitialized,
-    
-    #[error("DOM traversal error: {0}")]
-    TraversalError(String),
-    
-    #[error("Internal Stylo error: {0}")]
-    StyloError(String),
+    /// No element in the document matches the selector
+    #[error("No element matches selector '{selector}'")]
+    ElementNotFound { selector: String },
+
+    /// The selector could not be parsed
+    #[error("Invalid selector '{selector}': {message}")]
+    InvalidSelector { selector: String, message: String },
+
+    /// The property is not a CSS property Servo knows
+    #[error("Unknown CSS property '{property}'")]
+    UnknownProperty { property: String },
+
+    /// Evaluating the query threw a script error
+    #[error("Script error: {message}")]
+    Script { message: String },
+
+    #[error("Servo executable not found")]
+    ServoNotFound,
+
+    /// The Servo process could not be started
+    #[error("Failed to start Servo ({command}): {message}")]
+    SpawnFailed { command: String, message: String },
+
+    /// Servo exited before reporting results
+    #[error("Servo exited unexpectedly ({status}){}", stderr_suffix(.stderr_tail))]
+    ServoCrashed {
+        /// Exit status as reported by the OS, e.g. `exit status: 101`
+        status: String,
+        exit_code: Option<i32>,
+        /// Last lines Servo wrote to stderr
+        stderr_tail: String,
+    },
+
+    /// The query or batch ran out of time
+    #[error("Timed out after {} ms", .waited.as_millis())]
+    Timeout {
+        #[serde(rename = "waited_ms", with = "duration_ms")]
+        waited: Duration,
+    },
+
+    /// Servo's output or the daemon page's messages could not be understood
+    #[error("Servo protocol error: {message}")]
+    Protocol { message: String },
+
+    #[error("Servo daemon not available: {message}")]
+    Daemon { message: String },
+
+    /// The backend cannot evaluate this kind of query
+    #[error("Not supported: {message}")]
+    Unsupported { message: String },
+
+    #[error("Invalid engine configuration: {message}")]
+    Config { message: String },
+
+    #[error("I/O error: {message}")]
+    Io { message: String },
+}
+
+/// Former name of `StyleError`
+#[deprecated(note = "use `StyleError`")]
+pub type ServoStyleError = StyleError;
+
+impl StyleError {
+    pub(crate) fn protocol(message: impl Into<String>) -> Self {
+        StyleError::Protocol { message: message.into() }
+    }
+
+    pub(crate) fn daemon(message: impl Into<String>) -> Self {
+        StyleError::Daemon { message: message.into() }
+    }
+
+    pub(crate) fn config(message: impl Into<String>) -> Self {
+        StyleError::Config { message: message.into() }
+    }
+
+    #[cfg_attr(not(feature = "native-stylo"), allow(dead_code))]
+    pub(crate) fn unsupported(message: impl Into<String>) -> Self {
+        StyleError::Unsupported { message: message.into() }
+    }
 }
 
-/// Result type for style operations
-pub type StyleResult<T> = Result<T, StyleError>;
+impl From<std::io::Error> for StyleError {
+    fn from(e: std::io::Error) -> Self {
+        StyleError::Io { message: e.to_string() }
+    }
+}
 
-// CSS parsing error conversion would be implemented here when using actual CSS parser
+impl From<serde_json::Error> for StyleError {
+    fn from(e: serde_json::Error) -> Self {
+        StyleError::protocol(e.to_string())
+    }
+}
+
+fn stderr_suffix(stderr_tail: &str) -> String {
+    if stderr_tail.is_empty() {
+        String::new()
+    } else {
+        format!(": {}", stderr_tail)
+    }
+}
+
+/// Durations as whole milliseconds
+mod duration_ms {
+    use std::time::Duration;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
@@ -34,13 +34,13 @@
 //!
 //! ## Example
 //!
-//! ```rust
-//! use stylo_compute::ServoStyleEngine;
+//! ```rust,ignore
+//! use stylo_compute::{BackendKind, QueryOptions, StyleEngine};
 //!
 //! #[tokio::main]
 //! async fn main() -> Result<(), Box<dyn std::error::Error>> {
 //!     // Create a Servo-based style engine
-//!     let mut engine = ServoStyleEngine::new()?;
+//!     let mut engine = StyleEngine::new(BackendKind::Daemon, None)?;
 //!
 //!     // Set HTML content
 //!     engine.set_html(r#"
@@ -57,6 +57,10 @@
 //!             background-color: yellow;
 //!             font-weight: bold;
 //!         }
+//!         .highlight::before {
+//!             content: "★";
+//!             color: gold;
+//!         }
 //!         p {
 //!             margin: 10px;
 //!             padding: 5px;
@@ -64,11 +68,16 @@
 //!     "#)?;
 //!
 //!     // Get computed style for a specific property (uses Servo's getComputedStyle)
-//!     let color = engine.get_computed_style(".highlight", "color", None).await?;
+//!     let color = engine.get_computed_style(".highlight", "color").await?;
 //!     println!("Computed color: {}", color); // "rgb(255, 0, 0)"
 //!
+//!     // Target a pseudo-element with per-call options
+//!     let before = QueryOptions::new().with_pseudo_element("::before");
+//!     let content = engine.get_computed_style_with_options(".highlight", "content", &before).await?;
+//!     println!("Generated content: {}", content); // "\"★\""
+//!
 //!     // Get all computed styles for an element
-//!     let all_styles = engine.get_all_computed_styles(".highlight", None).await?;
+//!     let all_styles = engine.get_all_computed_styles(".highlight").await?;
 //!     for (property, value) in all_styles {
 //!         println!("{}: {}", property, value);
 //!     }
@@ -76,12 +85,81 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! ## Media emulation
+//!
+//! Queries are evaluated in an 800×600 light-scheme screen viewport unless the engine is
+//! given a `MediaEnvironment`, which sets the viewport size, device pixel ratio, color
+//! scheme and reduced-motion preferences, `screen` or `print` media and the root font
+//! size, so responsive and dark-mode styles compute the same on every machine.
+//!
+//! ```rust,ignore
+//! engine.set_media_environment(
+//!     MediaEnvironment::new().with_viewport(375, 812).with_color_scheme(ColorScheme::Dark),
+//! );
+//! ```
+//!
+//! `StyleEngine::compute_across_viewports` computes the same requests at several viewport
+//! widths and reports which properties change between those breakpoints.
+//!
+//! ## Native Stylo
+//!
+//! With the `native-stylo` feature, `BackendKind::Native` resolves styles in-process
+//! through Stylo's `Stylist` and style traversal, with no Servo executable involved.
+//! Stylo's API changes between releases, so the feature pins one release of the Stylo
+//! crates.
+//!
+//! ## Logging
+//!
+//! The engines print nothing themselves. Engine construction, Servo launches, batches,
+//! per-query outcomes and Servo's console output are emitted as `tracing` spans and
+//! events under the `stylo_compute` target; install a subscriber to see them.
 
+mod artifacts;
+mod assertions;
+mod backend;
+mod breakpoints;
+mod computed_value;
+mod config;
+mod error;
+mod media;
 mod servo_style_engine_real;
 mod servo_style_engine_optimized;
+mod daemon_channel;
+mod mock_servo;
+#[cfg(feature = "native-stylo")]
+mod native_html;
+#[cfg(feature = "native-stylo")]
+mod native_stylo;
+mod page_script;
+mod properties;
+mod servo_pool;
+mod servo_process;
+#[cfg(feature = "native-stylo")]
+mod stylo_element;
 
-pub use servo_style_engine_real::{ServoStyleEngineReal, ServoStyleError, compute_style_with_servo_real};
+pub use assertions::{StyleAssertion, StyleReport};
+pub use backend::{
+    BackendKind, ComputedStyles, ElementDescriptor, ElementStyles, ForcedState, QueryOptions, StyleBackend, StyleDocument,
+    StyleEngine, StyleQuery, StyleRequest, StyleResponse, StyleResult, StyleValue,
+};
+pub use breakpoints::{BreakpointReport, PropertyChange, ViewportSweep};
+pub use computed_value::{ComputedValue, ListSeparator, Rgba, TypedStyles};
+pub use config::{EngineConfig, DEFAULT_CONFIG_FILE};
+#[allow(deprecated)]
+pub use error::{ServoStyleError, StyleError};
+pub use media::{ColorScheme, MediaEnvironment, MediaType};
+pub use mock_servo::{FakeServo, FakeServoFixtures, MockBackend, FAKE_SERVO_FIXTURES_ENV};
+#[cfg(feature = "native-stylo")]
+pub use native_html::{parse_html_document, parse_html_fragment};
+#[cfg(feature = "native-stylo")]
+pub use native_stylo::NativeStyloBackend;
+pub use properties::longhands;
+pub use servo_pool::{ServoPool, WorkerHealth};
+pub use servo_style_engine_real::{ServoStyleEngineReal, compute_style_with_servo_real};
 pub use servo_style_engine_optimized::{ServoStyleEngineOptimized, compute_styles_batch_optimized};
+#[cfg(feature = "native-stylo")]
+pub use stylo_element::{NativeElement, NativeNode};
 
 
 
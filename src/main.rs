@@ -1,7 +1,14 @@
-use stylo_compute::{ServoStyleEngineReal, compute_style_with_servo_real};
+use stylo_compute::{ServoStyleEngineReal, StyleEngine, compute_style_with_servo_real};
+use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Engine diagnostics go to stderr; tune with RUST_LOG, e.g. RUST_LOG=stylo_compute=debug
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("stylo_compute=info")))
+        .with_writer(std::io::stderr)
+        .init();
+
     println!("🎨 Servo-based CSS Style Engine");
     println!("================================");
     println!("Using Servo's getComputedStyle() implementation with Stylo's native APIs");
@@ -19,7 +26,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Example 2: Using the full engine API
     println!("\n📋 Example 2: Full style engine");
-    let mut engine = ServoStyleEngineReal::new()?;
+    let mut engine = StyleEngine::with_backend(ServoStyleEngineReal::new()?);
     println!("✅ Created Servo-based style engine");
 
     // Set HTML content
@@ -0,0 +1,127 @@
+//! The device and media environment styles are computed in.
+//!
+//! Servo is configured through its command line: the window size, the device pixel ratio
+//! and the default font size. Servo has no switches for `prefers-color-scheme`,
+//! `prefers-reduced-motion` or print media, so the extraction pages rewrite media queries
+//! on those features to conditions that always or never match, according to the
+//! environment. The native backend configures Stylo's `Device` directly.
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::EngineConfig;
+
+/// Value of the `prefers-color-scheme` media feature
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorScheme {
+    #[default]
+    Light,
+    Dark,
+}
+
+/// Media type media queries are evaluated against
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum MediaType {
+    #[default]
+    Screen,
+    Print,
+}
+
+/// Viewport, device and user preferences media queries are evaluated against
+///
+/// The default is an 800×600 screen at a device pixel ratio of 1, with a light color
+/// scheme, no reduced-motion preference and a 16px root font size.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MediaEnvironment {
+    /// Viewport width in CSS pixels
+    pub viewport_width: u32,
+    /// Viewport height in CSS pixels
+    pub viewport_height: u32,
+    pub device_pixel_ratio: f32,
+    pub color_scheme: ColorScheme,
+    /// Whether `prefers-reduced-motion: reduce` matches
+    pub reduced_motion: bool,
+    pub media_type: MediaType,
+    /// Font size of `medium`, which `rem` units resolve against unless the document
+    /// sets its own, in pixels
+    pub root_font_size: f32,
+}
+
+impl Default for MediaEnvironment {
+    fn default() -> Self {
+        MediaEnvironment {
+            viewport_width: 800,
+            viewport_height: 600,
+            device_pixel_ratio: 1.0,
+            color_scheme: ColorScheme::Light,
+            reduced_motion: false,
+            media_type: MediaType::Screen,
+            root_font_size: 16.0,
+        }
+    }
+}
+
+impl MediaEnvironment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_viewport(mut self, width: u32, height: u32) -> Self {
+        self.viewport_width = width;
+        self.viewport_height = height;
+        self
+    }
+
+    pub fn with_device_pixel_ratio(mut self, ratio: f32) -> Self {
+        self.device_pixel_ratio = ratio;
+        self
+    }
+
+    pub fn with_color_scheme(mut self, color_scheme: ColorScheme) -> Self {
+        self.color_scheme = color_scheme;
+        self
+    }
+
+    pub fn with_reduced_motion(mut self, reduced_motion: bool) -> Self {
+        self.reduced_motion = reduced_motion;
+        self
+    }
+
+    pub fn with_media_type(mut self, media_type: MediaType) -> Self {
+        self.media_type = media_type;
+        self
+    }
+
+    /// Shorthand for `with_media_type(MediaType::Print)`
+    pub fn print(self) -> Self {
+        self.with_media_type(MediaType::Print)
+    }
+
+    pub fn with_root_font_size(mut self, size: f32) -> Self {
+        self.root_font_size = size;
+        self
+    }
+
+    /// `config` with the Servo arguments for this environment added, for runs that load
+    /// the extraction page directly into Servo's window
+    pub(crate) fn page_config(&self, config: &EngineConfig) -> EngineConfig {
+        let mut config = self.daemon_config(config);
+        config.extra_servo_args.push(format!("--window-size={}x{}", self.viewport_width, self.viewport_height));
+        config
+    }
+
+    /// `config` with the Servo arguments for this environment's device added
+    ///
+    /// Daemons size the frame each batch is rendered in to the viewport, so the window
+    /// size is left out and daemons can be shared by environments that differ only in it.
+    pub(crate) fn daemon_config(&self, config: &EngineConfig) -> EngineConfig {
+        let mut config = config.clone();
+        config.extra_servo_args.extend([
+            format!("--device-pixel-ratio={}", self.device_pixel_ratio),
+            // Servo's default font size preference is a whole number of pixels
+            format!("--pref=fonts_default_size={}", self.root_font_size.round() as i64),
+        ]);
+        config
+    }
+}
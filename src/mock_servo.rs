@@ -0,0 +1,317 @@
+//! Scriptable stand-in for Servo, for testing without a Servo build.
+//!
+//! `FakeServo` answers style queries from a fixture table of selector/property → value
+//! and speaks the same console protocol as the pages the engines inject
+//! (`BATCH_START`, `COMPUTED_STYLE_RESULT:`, `COMPUTED_STYLES_RESULT:`, `COMPUTED_STYLE_ERROR:`,
+//! `COMPUTED_STYLE_TIMEOUT:`).
+//! `MockBackend` runs batches through that protocol in-process, and the `fake-servo`
+//! binary does the same as a drop-in executable for `with_servo_path`.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{ComputedStyles, ElementDescriptor, ElementStyles, StyleBackend, StyleDocument, StyleQuery, StyleResponse};
+use crate::error::StyleError;
+use crate::page_script::{self, RecordBody, RecordKind};
+use crate::servo_style_engine_optimized::{create_batch_html, parse_batch_output};
+
+/// Environment variable the `fake-servo` binary reads its fixture file path from
+pub const FAKE_SERVO_FIXTURES_ENV: &str = "FAKE_SERVO_FIXTURES";
+
+/// Fixture table driving `FakeServo`
+///
+/// Selectors missing from `styles` are reported as "Element not found"; properties
+/// missing for a known selector compute to an empty string, like `getPropertyValue()`.
+/// Pseudo-element styles are keyed by the selector with the pseudo-element appended,
+/// e.g. `.icon::before`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FakeServoFixtures {
+    /// Computed values keyed by selector, then property
+    #[serde(default)]
+    pub styles: BTreeMap<String, BTreeMap<String, String>>,
+    /// Selectors whose query throws, with the script error message to report
+    #[serde(default)]
+    pub errors: BTreeMap<String, String>,
+    /// Selectors whose query takes this long to evaluate, in milliseconds
+    #[serde(default)]
+    pub slow: BTreeMap<String, u64>,
+    /// Elements matched by all-matches queries, keyed by selector; selectors only listed
+    /// in `styles` match a single element
+    #[serde(default)]
+    pub matches: BTreeMap<String, Vec<ElementStyles>>,
+    /// Extra console lines interleaved with the results
+    #[serde(default)]
+    pub console_noise: Vec<String>,
+    /// Split result lines longer than this many characters across several console lines
+    #[serde(default)]
+    pub wrap_width: Option<usize>,
+    /// Delay before the `fake-servo` binary produces any output
+    #[serde(default)]
+    pub delay_ms: u64,
+    /// Exit code of the `fake-servo` binary
+    #[serde(default)]
+    pub exit_code: i32,
+}
+
+impl FakeServoFixtures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load fixtures from a JSON file
+    pub fn from_json_file(path: impl AsRef<Path>) -> Result<Self, StyleError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        serde_json::from_str(&content)
+            .map_err(|e| StyleError::config(format!("Invalid fixture file {}: {}", path.display(), e)))
+    }
+
+    /// Load fixtures from the file named by `FAKE_SERVO_FIXTURES`, or an empty table
+    pub fn from_env() -> Result<Self, StyleError> {
+        match std::env::var(FAKE_SERVO_FIXTURES_ENV) {
+            Ok(path) => Self::from_json_file(path),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Set the computed value of `property` for elements matching `selector`
+    pub fn with_style(mut self, selector: &str, property: &str, value: &str) -> Self {
+        self.styles
+            .entry(selector.to_string())
+            .or_default()
+            .insert(property.to_string(), value.to_string());
+        self
+    }
+
+    /// Set the elements an all-matches query for `selector` finds
+    pub fn with_matches(mut self, selector: &str, elements: Vec<ElementStyles>) -> Self {
+        self.matches.insert(selector.to_string(), elements);
+        self
+    }
+
+    /// Make queries for `selector` fail with a script error
+    pub fn with_error(mut self, selector: &str, message: &str) -> Self {
+        self.errors.insert(selector.to_string(), message.to_string());
+        self
+    }
+
+    /// Make queries for `selector` take `ms` milliseconds, timing out shorter deadlines
+    pub fn with_slow_query(mut self, selector: &str, ms: u64) -> Self {
+        self.slow.insert(selector.to_string(), ms);
+        self
+    }
+
+    /// Add a console line that is not part of the result protocol
+    pub fn with_console_noise(mut self, line: &str) -> Self {
+        self.console_noise.push(line.to_string());
+        self
+    }
+
+    /// Split result lines across console lines of at most `width` characters
+    pub fn with_wrap_width(mut self, width: usize) -> Self {
+        self.wrap_width = Some(width);
+        self
+    }
+}
+
+/// Console-protocol emulation of Servo driven by `FakeServoFixtures`
+#[derive(Debug, Clone, Default)]
+pub struct FakeServo {
+    fixtures: FakeServoFixtures,
+}
+
+impl FakeServo {
+    pub fn new(fixtures: FakeServoFixtures) -> Self {
+        FakeServo { fixtures }
+    }
+
+    pub fn fixtures(&self) -> &FakeServoFixtures {
+        &self.fixtures
+    }
+
+    /// Console output Servo would print after loading `page_html`
+    ///
+    /// Result records carry the page's nonce. Batch pages additionally get
+    /// `BATCH_START`/`BATCH_END` framing.
+    pub fn render_console(&self, page_html: &str) -> String {
+        let payload = page_script::read_payload(page_html).unwrap_or_default();
+        let (batched, queries) = (payload.batch, payload.queries);
+        let wrap = |line: String| match self.fixtures.wrap_width {
+            Some(width) => wrap_line(&line, width),
+            None => vec![line],
+        };
+
+        let mut lines = Vec::new();
+        if batched {
+            lines.push(format!("BATCH_START:{}", queries.len()));
+        }
+        for (i, query) in queries.iter().enumerate() {
+            if let Some(noise) = self.fixtures.console_noise.get(i) {
+                lines.push(noise.clone());
+            }
+            lines.extend(wrap(self.render_result(query, &payload.nonce)));
+        }
+        for noise in self.fixtures.console_noise.iter().skip(queries.len()) {
+            lines.push(noise.clone());
+        }
+        if batched {
+            lines.push(format!("BATCH_END:{}", queries.len()));
+        }
+
+        lines.join("\n")
+    }
+
+    /// Answer a query directly, as the daemon page would
+    pub fn respond(&self, query: &StyleQuery) -> StyleResponse {
+        if let Some(elapsed) = self.overrun(&query.selector, query.timeout_ms) {
+            return StyleResponse::timeout(&query.id, Duration::from_millis(elapsed));
+        }
+        if query.all_matches {
+            return StyleResponse {
+                matches: Some(self.all_matches(query)),
+                ..Self::success(&query.id)
+            };
+        }
+        match self.lookup(&query.selector, query.pseudo_element.as_deref()) {
+            Err(error) => StyleResponse::failure(&query.id, error),
+            Ok(styles) => match query.property {
+                Some(ref property) => StyleResponse {
+                    computed_value: Some(styles.get(property).cloned().unwrap_or_default()),
+                    ..Self::success(&query.id)
+                },
+                None => StyleResponse {
+                    computed_styles: Some(Self::select(&styles.clone().into_iter().collect(), &query.properties)),
+                    ..Self::success(&query.id)
+                },
+            },
+        }
+    }
+
+    fn success(id: &str) -> StyleResponse {
+        StyleResponse {
+            id: id.to_string(),
+            success: true,
+            computed_value: None,
+            computed_styles: None,
+            matches: None,
+            error: None,
+        }
+    }
+
+    /// Elements matching an all-matches query, restricted to its properties
+    fn all_matches(&self, query: &StyleQuery) -> Vec<ElementStyles> {
+        let elements = match self.fixtures.matches.get(&query.selector) {
+            Some(elements) => elements.clone(),
+            None => self.lookup(&query.selector, query.pseudo_element.as_deref()).ok().map(|styles| ElementStyles {
+                element: ElementDescriptor::default(),
+                styles: styles.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            }).into_iter().collect(),
+        };
+        elements.into_iter().enumerate().map(|(index, mut element)| {
+            element.element.index = index;
+            element.styles = Self::select(&element.styles, &query.properties);
+            element
+        }).collect()
+    }
+
+    /// `properties` of `styles`, or all of them if none are named; missing ones compute to ""
+    fn select(styles: &ComputedStyles, properties: &[String]) -> ComputedStyles {
+        if properties.is_empty() {
+            return styles.clone();
+        }
+        properties.iter()
+            .map(|property| (property.clone(), styles.get(property).unwrap_or_default().to_string()))
+            .collect()
+    }
+
+    /// How long a query for `selector` ran if that exceeds `budget_ms`
+    fn overrun(&self, selector: &str, budget_ms: Option<u64>) -> Option<u64> {
+        let elapsed = *self.fixtures.slow.get(selector)?;
+        (elapsed > budget_ms?).then_some(elapsed)
+    }
+
+    /// Styles of `selector`, or of its pseudo-element, which fixtures key as `selector::pseudo`
+    fn lookup(&self, selector: &str, pseudo_element: Option<&str>) -> Result<&BTreeMap<String, String>, StyleError> {
+        static NO_STYLES: BTreeMap<String, String> = BTreeMap::new();
+
+        if let Some(message) = self.fixtures.errors.get(selector) {
+            return Err(StyleError::Script { message: message.clone() });
+        }
+        let element = self.fixtures.styles.get(selector).ok_or_else(|| StyleError::ElementNotFound {
+            selector: selector.to_string(),
+        })?;
+        match pseudo_element {
+            Some(pseudo_element) => Ok(self.fixtures.styles.get(&format!("{}{}", selector, pseudo_element)).unwrap_or(&NO_STYLES)),
+            None => Ok(element),
+        }
+    }
+
+    /// The console record the extraction script would print for `query`
+    fn render_result(&self, query: &StyleQuery, nonce: &str) -> String {
+        let response = self.respond(query);
+        let mut body = RecordBody {
+            id: Some(query.id.clone()),
+            ..RecordBody::default()
+        };
+
+        let kind = match response.error {
+            Some(StyleError::Timeout { waited }) => {
+                body.elapsed_ms = Some(waited.as_millis() as u64);
+                RecordKind::Timeout
+            }
+            Some(error) => {
+                body.error = Some(error);
+                RecordKind::Error
+            }
+            None => {
+                body.selector = Some(query.selector.clone());
+                body.property = query.property.clone();
+                body.value = response.computed_value;
+                body.styles = response.computed_styles;
+                body.matches = response.matches;
+                if body.value.is_some() { RecordKind::Value } else { RecordKind::Styles }
+            }
+        };
+        page_script::format_record(kind, nonce, &body)
+    }
+}
+
+/// Break `line` into pieces of at most `width` characters
+fn wrap_line(line: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    chars.chunks(width.max(1)).map(|chunk| chunk.iter().collect()).collect()
+}
+
+/// Backend answering queries from fixtures through the batch console protocol
+///
+/// Each batch is rendered with the same page generator and parsed with the same
+/// output parser as `ServoStyleEngineOptimized`, so only the Servo process is faked.
+#[derive(Debug, Clone, Default)]
+pub struct MockBackend {
+    servo: FakeServo,
+}
+
+impl MockBackend {
+    pub fn new(fixtures: FakeServoFixtures) -> Self {
+        MockBackend {
+            servo: FakeServo::new(fixtures),
+        }
+    }
+}
+
+#[async_trait]
+impl StyleBackend for MockBackend {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    async fn compute_batch(&self, document: &StyleDocument, queries: Vec<StyleQuery>) -> Result<Vec<StyleResponse>, StyleError> {
+        let nonce = page_script::new_nonce();
+        let page = create_batch_html(document, &queries, &nonce);
+        let console = self.servo.render_console(&page);
+        parse_batch_output(&console, "", &nonce, &queries, None)
+    }
+}
@@ -0,0 +1,292 @@
+//! HTML parsing for the native backend.
+//!
+//! Markup is parsed with html5ever, the same HTML5 tree builder Servo uses, so the
+//! native DOM has the structure Servo would build for the same input: implied
+//! `<html>`/`<head>`/`<body>` elements, foster-parented table content, foreign SVG and
+//! MathML elements. The parser builds into a small reference-counted tree that is
+//! converted to `NativeElement`s once parsing finishes.
+
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+
+use html5ever::interface::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
+use html5ever::tendril::{StrTendril, TendrilSink};
+use html5ever::{local_name, ns, parse_document, parse_fragment, Attribute, ExpandedName, ParseOpts, QualName};
+
+use crate::stylo_element::{NativeElement, NativeNode};
+
+/// Parse a complete HTML document, returning its root `<html>` element
+///
+/// Missing `<html>`, `<head>` and `<body>` elements are implied as in a browser.
+pub fn parse_html_document(html: &str) -> NativeElement {
+    let document = parse_document(Sink::default(), ParseOpts::default()).one(html);
+    let root = document.children.borrow().iter().find_map(|child| match convert(child) {
+        Some(NativeNode::Element(root)) => Some(root),
+        _ => None,
+    });
+    root.unwrap_or_else(|| NativeElement::new("html"))
+}
+
+/// Parse an HTML fragment the way `element.innerHTML` parses it for a `<body>` element
+pub fn parse_html_fragment(html: &str) -> Vec<NativeNode> {
+    let context = QualName::new(None, ns!(html), local_name!("body"));
+    let document = parse_fragment(Sink::default(), ParseOpts::default(), context, Vec::new(), false).one(html);
+    // The fragment parser puts the parsed nodes under a synthetic `<html>` element
+    let root = document.children.borrow().first().cloned();
+    root.map_or_else(Vec::new, |root| root.children.borrow().iter().filter_map(convert).collect())
+}
+
+type Handle = Rc<Node>;
+
+enum NodeData {
+    Document,
+    Element {
+        name: QualName,
+        attributes: RefCell<Vec<Attribute>>,
+        /// Contents of a `<template>`, which are not children of the element itself
+        template_contents: Option<Handle>,
+    },
+    Text(RefCell<StrTendril>),
+    Comment(StrTendril),
+    /// Doctypes and processing instructions, which do not take part in styling
+    Other,
+}
+
+struct Node {
+    data: NodeData,
+    parent: Cell<Option<Weak<Node>>>,
+    children: RefCell<Vec<Handle>>,
+}
+
+impl Node {
+    fn new(data: NodeData) -> Handle {
+        Rc::new(Node {
+            data,
+            parent: Cell::new(None),
+            children: RefCell::new(Vec::new()),
+        })
+    }
+
+    fn parent(&self) -> Option<Handle> {
+        let parent = self.parent.take();
+        let upgraded = parent.as_ref().and_then(Weak::upgrade);
+        self.parent.set(parent);
+        upgraded
+    }
+}
+
+/// Position of `node` among its parent's children, with the parent
+fn position(node: &Handle) -> Option<(Handle, usize)> {
+    let parent = node.parent()?;
+    let index = parent.children.borrow().iter().position(|child| Rc::ptr_eq(child, node))?;
+    Some((parent, index))
+}
+
+/// Insert `child` into `parent` before the child at `index`, merging adjacent text
+fn insert(parent: &Handle, index: usize, child: NodeOrText<Handle>) {
+    let node = match child {
+        NodeOrText::AppendText(text) => {
+            if let Some(previous) = index.checked_sub(1).map(|i| parent.children.borrow()[i].clone()) {
+                if let NodeData::Text(ref existing) = previous.data {
+                    existing.borrow_mut().push_tendril(&text);
+                    return;
+                }
+            }
+            Node::new(NodeData::Text(RefCell::new(text)))
+        }
+        NodeOrText::AppendNode(node) => {
+            detach(&node);
+            node
+        }
+    };
+    node.parent.set(Some(Rc::downgrade(parent)));
+    parent.children.borrow_mut().insert(index, node);
+}
+
+fn detach(node: &Handle) {
+    if let Some((parent, index)) = position(node) {
+        parent.children.borrow_mut().remove(index);
+        node.parent.set(None);
+    }
+}
+
+/// Tree builder callbacks from html5ever
+struct Sink {
+    document: Handle,
+}
+
+impl Default for Sink {
+    fn default() -> Self {
+        Sink { document: Node::new(NodeData::Document) }
+    }
+}
+
+impl TreeSink for Sink {
+    type Handle = Handle;
+    type Output = Handle;
+    type ElemName<'a> = ExpandedName<'a>;
+
+    fn finish(self) -> Handle {
+        self.document
+    }
+
+    fn parse_error(&self, _msg: Cow<'static, str>) {
+        // Browsers recover from malformed markup silently, and so do we
+    }
+
+    fn get_document(&self) -> Handle {
+        self.document.clone()
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a Handle) -> ExpandedName<'a> {
+        match target.data {
+            NodeData::Element { ref name, .. } => name.expanded(),
+            _ => panic!("elem_name called on a non-element node"),
+        }
+    }
+
+    fn create_element(&self, name: QualName, attributes: Vec<Attribute>, flags: ElementFlags) -> Handle {
+        Node::new(NodeData::Element {
+            name,
+            attributes: RefCell::new(attributes),
+            template_contents: flags.template.then(|| Node::new(NodeData::Document)),
+        })
+    }
+
+    fn create_comment(&self, text: StrTendril) -> Handle {
+        Node::new(NodeData::Comment(text))
+    }
+
+    fn create_pi(&self, _target: StrTendril, _data: StrTendril) -> Handle {
+        Node::new(NodeData::Other)
+    }
+
+    fn append(&self, parent: &Handle, child: NodeOrText<Handle>) {
+        let end = parent.children.borrow().len();
+        insert(parent, end, child);
+    }
+
+    fn append_based_on_parent_node(&self, element: &Handle, prev_element: &Handle, child: NodeOrText<Handle>) {
+        if element.parent().is_some() {
+            self.append_before_sibling(element, child);
+        } else {
+            self.append(prev_element, child);
+        }
+    }
+
+    fn append_doctype_to_document(&self, _name: StrTendril, _public_id: StrTendril, _system_id: StrTendril) {
+        self.append(&self.document, NodeOrText::AppendNode(Node::new(NodeData::Other)));
+    }
+
+    fn get_template_contents(&self, target: &Handle) -> Handle {
+        match target.data {
+            NodeData::Element { template_contents: Some(ref contents), .. } => contents.clone(),
+            _ => panic!("get_template_contents called on a non-template element"),
+        }
+    }
+
+    fn same_node(&self, x: &Handle, y: &Handle) -> bool {
+        Rc::ptr_eq(x, y)
+    }
+
+    fn set_quirks_mode(&self, _mode: QuirksMode) {}
+
+    fn append_before_sibling(&self, sibling: &Handle, new_node: NodeOrText<Handle>) {
+        if let Some((parent, index)) = position(sibling) {
+            insert(&parent, index, new_node);
+        }
+    }
+
+    fn add_attrs_if_missing(&self, target: &Handle, attrs: Vec<Attribute>) {
+        if let NodeData::Element { ref attributes, .. } = target.data {
+            let mut attributes = attributes.borrow_mut();
+            for attr in attrs {
+                if !attributes.iter().any(|existing| existing.name == attr.name) {
+                    attributes.push(attr);
+                }
+            }
+        }
+    }
+
+    fn remove_from_parent(&self, target: &Handle) {
+        detach(target);
+    }
+
+    fn reparent_children(&self, node: &Handle, new_parent: &Handle) {
+        let children = std::mem::take(&mut *node.children.borrow_mut());
+        for child in &children {
+            child.parent.set(Some(Rc::downgrade(new_parent)));
+        }
+        new_parent.children.borrow_mut().extend(children);
+    }
+}
+
+/// Convert a parsed node to its native form; doctypes and the like are dropped
+fn convert(node: &Handle) -> Option<NativeNode> {
+    match node.data {
+        NodeData::Element { ref name, ref attributes, .. } => Some(NativeNode::Element(NativeElement {
+            tag_name: name.local.to_string(),
+            namespace: name.ns.to_string(),
+            attributes: attributes.borrow().iter().map(|attr| (attribute_name(&attr.name), attr.value.to_string())).collect(),
+            children: node.children.borrow().iter().filter_map(convert).collect(),
+        })),
+        NodeData::Text(ref text) => Some(NativeNode::Text(text.borrow().to_string())),
+        NodeData::Comment(ref text) => Some(NativeNode::Comment(text.to_string())),
+        NodeData::Document | NodeData::Other => None,
+    }
+}
+
+/// Attribute name as written in markup, e.g. `xlink:href`
+fn attribute_name(name: &QualName) -> String {
+    match name.prefix {
+        Some(ref prefix) => format!("{}:{}", prefix, name.local),
+        None => name.local.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element_children(element: &NativeElement) -> Vec<&NativeElement> {
+        element.children.iter().filter_map(|child| match child {
+            NativeNode::Element(element) => Some(element),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn implies_missing_document_elements() {
+        let root = parse_html_document("<p class=a>text");
+        assert_eq!(root.tag_name, "html");
+        let children = element_children(&root);
+        assert_eq!(children.iter().map(|child| child.tag_name.as_str()).collect::<Vec<_>>(), ["head", "body"]);
+        let p = element_children(children[1])[0];
+        assert_eq!(p.attributes, [("class".to_string(), "a".to_string())]);
+        assert_eq!(p.children, [NativeNode::Text("text".to_string())]);
+    }
+
+    #[test]
+    fn builds_the_tree_a_browser_would() {
+        let root = parse_html_document("<table><div>moved</div><tr><td>cell</td></tr></table><svg><circle/></svg>");
+        let body = element_children(&root)[1];
+        let tags: Vec<_> = element_children(body).iter().map(|child| child.tag_name.as_str()).collect();
+        // Foster parenting moves the `<div>` out in front of the table
+        assert_eq!(tags, ["div", "table", "svg"]);
+        let table = element_children(body)[1];
+        assert_eq!(element_children(table)[0].tag_name, "tbody");
+        let svg = element_children(body)[2];
+        assert_eq!(svg.namespace, "http://www.w3.org/2000/svg");
+        assert_eq!(element_children(svg)[0].namespace, "http://www.w3.org/2000/svg");
+    }
+
+    #[test]
+    fn parses_fragments_in_body_context() {
+        let nodes = parse_html_fragment("a<b>c</b><!--d-->");
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(nodes[0], NativeNode::Text("a".to_string()));
+        assert!(matches!(nodes[1], NativeNode::Element(ref b) if b.tag_name == "b"));
+        assert_eq!(nodes[2], NativeNode::Comment("d".to_string()));
+    }
+}
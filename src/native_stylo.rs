@@ -0,0 +1,841 @@
+//! In-process style resolution with Stylo, without launching Servo.
+//!
+//! Compiled only with the `native-stylo` feature. The document's HTML is wrapped in the
+//! same page the Servo engines load and parsed into a `StyloDom` (or a hand-built
+//! `NativeElement` tree is used); every batch builds a `Stylist` from the page's
+//! `<style>` blocks, restyles the whole tree with Stylo's traversal and reads the values
+//! back from each element's primary `ComputedValues`, or from the style Stylo cascades
+//! for the queried pseudo-element.
+
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+
+use app_units::Au;
+use euclid::{Scale, Size2D};
+use selectors::context::{MatchingContext, MatchingForInvalidation, MatchingMode, NeedsSelectorFlags, SelectorCaches};
+use selectors::Element as SelectorsElement;
+use style::applicable_declarations::ApplicableDeclarationList;
+use style::animation::DocumentAnimationSet;
+use style::context::{CascadeInputs, RegisteredSpeculativePainter, RegisteredSpeculativePainters, SharedStyleContext, StyleContext};
+use style::dom::{TDocument, TElement, TNode};
+use style::dom_apis;
+use style::font_metrics::FontMetrics;
+use style::global_style_data::GLOBAL_STYLE_DATA;
+use style::device::servo::FontMetricsProvider;
+use style::device::Device;
+use style::media_queries::{MediaList, MediaType};
+use style::properties::style_structs::Font;
+use style::properties::{ComputedValues, Importance, LonghandId, PropertyDeclarationBlock, PropertyId, ShorthandId};
+use style::queries::values::PrefersColorScheme;
+use style::rule_tree::RuleCascadeFlags;
+use style::selector_parser::{PseudoElement, SelectorImpl, SelectorParser, SnapshotMap};
+use style::servo::media_features::PointerCapabilities;
+use style::shared_lock::{SharedRwLockReadGuard, StylesheetGuards};
+use style::stylesheets::{AllowImportRules, DocumentStyleSheet, Origin, Stylesheet};
+use style::stylist::{RuleInclusion, Stylist};
+use style::thread_state::{self, ThreadState};
+use style::traversal::{recalc_style_at, DomTraversal};
+use style::traversal_flags::TraversalFlags;
+use style::values::computed::font::{FontSize, GenericFontFamily};
+use style::values::computed::{CSSPixelLength, Length, NonNegativeLength};
+use style::values::resolved;
+use style::values::specified::font::{KeywordInfo, QueryFontMetricsFlags};
+use style::Atom;
+use servo_arc::Arc as ServoArc;
+use style_traits::{CSSPixel, DevicePixel};
+use stylo_dom::ElementState;
+
+use crate::backend::{
+    ComputedStyles, ElementDescriptor, ElementStyles, ForcedState, StyleBackend, StyleDocument, StyleQuery, StyleResponse,
+};
+use crate::error::StyleError;
+use crate::media::{self, ColorScheme, MediaEnvironment};
+use crate::native_html::parse_html_document;
+use crate::stylo_element::{NativeElement, NodeId, StyloDom, StyloElement};
+
+/// The subset of the HTML user-agent stylesheet the native backend applies
+///
+/// Servo ships the full sheet; this covers display types and the common default
+/// margins and fonts so simple documents compute the same values.
+const USER_AGENT_CSS: &str = r#"
+    html, address, blockquote, body, dd, div, dl, dt, fieldset, figcaption, figure, footer, form,
+    h1, h2, h3, h4, h5, h6, header, hr, main, nav, ol, p, pre, section, article, aside, ul, details,
+    summary, legend { display: block; }
+    head, link, meta, script, style, title, template, [hidden] { display: none; }
+    li { display: list-item; }
+    table { display: table; }
+    tr { display: table-row; }
+    td, th { display: table-cell; }
+    body { margin: 8px; }
+    p, blockquote, dl, figure, ul, ol { margin-top: 1em; margin-bottom: 1em; }
+    ul, ol { padding-left: 40px; }
+    h1 { font-size: 2em; margin-top: 0.67em; margin-bottom: 0.67em; }
+    h2 { font-size: 1.5em; margin-top: 0.83em; margin-bottom: 0.83em; }
+    h3 { font-size: 1.17em; margin-top: 1em; margin-bottom: 1em; }
+    h4 { margin-top: 1.33em; margin-bottom: 1.33em; }
+    h5 { font-size: 0.83em; margin-top: 1.67em; margin-bottom: 1.67em; }
+    h6 { font-size: 0.67em; margin-top: 2.33em; margin-bottom: 2.33em; }
+    h1, h2, h3, h4, h5, h6, b, strong, th { font-weight: bold; }
+    i, em, cite, var, dfn, address { font-style: italic; }
+    pre, code, kbd, samp, tt { font-family: monospace; }
+    a:link { color: #0000ee; text-decoration: underline; }
+"#;
+
+/// Style backend resolving styles in-process with Stylo
+///
+/// Documents with HTML are parsed exactly as the Servo engines receive them. A document
+/// without HTML is answered against the `NativeElement` tree the backend holds instead,
+/// styled by the document's stylesheets and the tree's own `<style>` elements.
+///
+/// Clones share the same documents, so a clone kept aside can update inline styles of a
+/// backend that has been moved into a `StyleEngine`.
+#[derive(Clone)]
+pub struct NativeStyloBackend {
+    documents: Arc<Mutex<NativeDocuments>>,
+}
+
+struct NativeDocuments {
+    /// Hand-built document, used when the `StyleDocument` has no HTML
+    tree: Box<StyloDom>,
+    /// Last parsed page and its markup, reused while the document is unchanged
+    parsed: Option<(String, Box<StyloDom>)>,
+}
+
+impl NativeStyloBackend {
+    /// A backend whose document is an empty `<html>` element
+    pub fn new() -> Self {
+        Self::with_document(&NativeElement::new("html"))
+    }
+
+    /// A backend whose document is `root` and its descendants
+    pub fn with_document(root: &NativeElement) -> Self {
+        NativeStyloBackend {
+            documents: Arc::new(Mutex::new(NativeDocuments {
+                tree: StyloDom::new(root),
+                parsed: None,
+            })),
+        }
+    }
+
+    /// Replace the tree used for documents without HTML
+    pub fn set_document(&self, root: &NativeElement) {
+        self.documents.lock().unwrap().tree = StyloDom::new(root);
+    }
+
+    /// Set the inline `style` attribute of every element matching `selector`, returning
+    /// how many elements were updated
+    ///
+    /// Only the new declarations are parsed; the rest of the document is kept as is. The
+    /// change applies to the hand-built tree and to the last parsed page, and lasts until
+    /// that document is replaced.
+    pub fn set_inline_style(&self, selector: &str, css: &str) -> Result<usize, StyleError> {
+        let mut documents = self.documents.lock().unwrap();
+        let documents = &mut *documents;
+        let mut updated = 0;
+        for dom in std::iter::once(&mut documents.tree).chain(documents.parsed.as_mut().map(|(_, dom)| dom)) {
+            let ids: Vec<NodeId> = select(dom, selector)?.iter().map(StyloElement::node_id).collect();
+            for id in &ids {
+                dom.set_style_attribute(*id, css);
+            }
+            updated += ids.len();
+        }
+        Ok(updated)
+    }
+
+    fn compute(&self, document: &StyleDocument, queries: &[StyleQuery]) -> Result<Vec<StyleResponse>, StyleError> {
+        let mut documents = self.documents.lock().unwrap();
+        let documents = &mut *documents;
+        let (dom, stylesheets) = if document.html.trim().is_empty() {
+            (&mut documents.tree, document.stylesheets.as_slice())
+        } else {
+            // The page already carries the stylesheets in a `<style>` block
+            let page = document.to_html("");
+            if documents.parsed.as_ref().is_none_or(|(source, _)| *source != page) {
+                let dom = StyloDom::new(&parse_html_document(&page));
+                documents.parsed = Some((page, dom));
+            }
+            (&mut documents.parsed.as_mut().unwrap().1, &[][..])
+        };
+        let dom = &*dom;
+        let root = dom.root_element().ok_or_else(|| StyleError::protocol("Native document has no root element"))?;
+
+        let mut stylist = Stylist::new(create_device(&document.media), dom.document().quirks_mode());
+        {
+            let guard = dom.lock().read();
+            let style_blocks = dom.style_blocks();
+            let author_sheets: Vec<String> = stylesheets.iter().chain(&style_blocks)
+                .map(|css| emulate_reduced_motion(css, document.media.reduced_motion))
+                .collect();
+            let sheets = std::iter::once((USER_AGENT_CSS, Origin::UserAgent))
+                .chain(author_sheets.iter().map(|css| (css.as_str(), Origin::Author)));
+            for (css, origin) in sheets {
+                let media = ServoArc::new(dom.lock().wrap(MediaList::empty()));
+                let sheet = Stylesheet::from_str(
+                    css,
+                    dom.url_data().clone(),
+                    origin,
+                    media,
+                    dom.lock().clone(),
+                    None,
+                    None,
+                    dom.document().quirks_mode(),
+                    AllowImportRules::Yes,
+                );
+                stylist.append_stylesheet(DocumentStyleSheet(ServoArc::new(sheet)), &guard);
+            }
+        }
+
+        let guard = dom.lock().read();
+        let snapshots = SnapshotMap::new();
+        stylist.flush(&StylesheetGuards::same(&guard));
+        let styler = Styler {
+            dom,
+            root,
+            stylist: &stylist,
+            guard: &guard,
+            snapshots: &snapshots,
+            styled_for: RefCell::new(None),
+        };
+
+        Ok(queries.iter().map(|query| match answer(&styler, query) {
+            Ok(response) => response,
+            Err(e) => StyleResponse::failure(&query.id, e),
+        }).collect())
+    }
+}
+
+impl Default for NativeStyloBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl StyleBackend for NativeStyloBackend {
+    fn name(&self) -> &str {
+        "native-stylo"
+    }
+
+    /// Restyles on a blocking thread, so the executor keeps running while Stylo works
+    async fn compute_batch(&self, document: &StyleDocument, queries: Vec<StyleQuery>) -> Result<Vec<StyleResponse>, StyleError> {
+        let backend = self.clone();
+        let document = document.clone();
+        tokio::task::spawn_blocking(move || backend.compute(&document, &queries))
+            .await
+            .map_err(|e| StyleError::protocol(format!("Native style computation was interrupted: {}", e)))?
+    }
+}
+
+/// A `Device` for `media`, set up the way Servo sets up its own for a window
+fn create_device(media: &MediaEnvironment) -> Device {
+    let media_type = match media.media_type {
+        media::MediaType::Screen => MediaType::screen(),
+        media::MediaType::Print => MediaType::print(),
+    };
+    let color_scheme = match media.color_scheme {
+        ColorScheme::Light => PrefersColorScheme::Light,
+        ColorScheme::Dark => PrefersColorScheme::Dark,
+    };
+    // `medium`, and so the root font size, comes from the initial font
+    let mut font = Font::initial_values();
+    let root_font_size = NonNegativeLength::new(media.root_font_size);
+    font.font_size = FontSize {
+        computed_size: root_font_size,
+        used_size: root_font_size,
+        keyword_info: KeywordInfo::medium(),
+    };
+    let viewport = Size2D::<f32, CSSPixel>::new(media.viewport_width as f32, media.viewport_height as f32);
+    let ratio = Scale::<f32, CSSPixel, DevicePixel>::new(media.device_pixel_ratio);
+    Device::new(
+        media_type,
+        style::context::QuirksMode::NoQuirks,
+        viewport,
+        viewport * ratio,
+        ratio,
+        Box::new(DefaultFontMetrics),
+        ComputedValues::initial_values_with_font_override(font),
+        color_scheme,
+        PointerCapabilities::default(),
+        PointerCapabilities::default(),
+    )
+}
+
+/// Media conditions that always and never match, for features the `Device` cannot express
+const MATCH_ALL: &str = "(min-width: 0px)";
+const MATCH_NONE: &str = "(not (min-width: 0px))";
+
+/// `css` with each `(prefers-reduced-motion)` test replaced by a condition that matches
+/// exactly when it would for the `reduce` preference
+///
+/// Stylo's `Device` has no reduced-motion setting, so the feature is emulated the way
+/// the Servo extraction pages emulate it.
+fn emulate_reduced_motion(css: &str, reduce: bool) -> String {
+    const FEATURE: &str = "prefers-reduced-motion";
+    // ASCII lowercasing keeps byte offsets, so positions found in `lower` index `css`
+    let lower = css.to_ascii_lowercase();
+    let mut emulated = String::with_capacity(css.len());
+    let mut copied = 0;
+    let mut search = 0;
+    while let Some(found) = lower[search..].find(FEATURE) {
+        let name = search + found;
+        let after_name = name + FEATURE.len();
+        search = after_name;
+        let Some(open) = lower[..name].trim_end().strip_suffix('(').map(str::len) else {
+            continue;
+        };
+        let Some(close) = lower[after_name..].find(')').map(|offset| after_name + offset) else {
+            break;
+        };
+        let value = lower[after_name..close].trim();
+        let matches = match value.strip_prefix(':').map(str::trim) {
+            None if value.is_empty() => reduce,
+            Some("reduce") => reduce,
+            Some("no-preference") => !reduce,
+            _ => continue,
+        };
+        emulated.push_str(&css[copied..open]);
+        emulated.push_str(if matches { MATCH_ALL } else { MATCH_NONE });
+        copied = close + 1;
+        search = copied;
+    }
+    emulated.push_str(&css[copied..]);
+    emulated
+}
+
+/// Forced states the tree's current styles were computed with
+#[derive(Debug, Clone, PartialEq)]
+struct StyledFor {
+    target: Option<NodeId>,
+    states: ElementState,
+    on_ancestors: bool,
+}
+
+/// Restyles the document for the states each query forces
+///
+/// The tree is styled once without forced states and shared by every query that forces
+/// none; queries forcing states restyle it for their own target element. States are
+/// reset as soon as a restyle finishes, so selectors always match the markup's states.
+struct Styler<'a> {
+    dom: &'a StyloDom,
+    root: StyloElement<'a>,
+    stylist: &'a Stylist,
+    guard: &'a SharedRwLockReadGuard<'a>,
+    snapshots: &'a SnapshotMap,
+    styled_for: RefCell<Option<StyledFor>>,
+}
+
+impl Styler<'_> {
+    /// Make sure the current styles are those of `target` in the states `query` forces
+    fn style_for(&self, target: StyloElement<'_>, query: &StyleQuery) {
+        let states = query.forced_states.iter().fold(ElementState::empty(), |states, state| states | element_state(*state));
+        let wanted = if states.is_empty() {
+            StyledFor { target: None, states, on_ancestors: false }
+        } else {
+            StyledFor { target: Some(target.node_id()), states, on_ancestors: query.states_on_ancestors }
+        };
+        if self.styled_for.borrow().as_ref() == Some(&wanted) {
+            return;
+        }
+
+        if let Some(target) = wanted.target {
+            self.dom.force_state(target, states, implied_removals(states));
+            let mut ancestor = self.dom.parent_element(target);
+            while let Some(id) = ancestor {
+                if wanted.on_ancestors {
+                    self.dom.force_state(id, states, implied_removals(states));
+                }
+                if states.contains(ElementState::FOCUS) {
+                    self.dom.force_state(id, ElementState::FOCUS_WITHIN, ElementState::empty());
+                }
+                ancestor = self.dom.parent_element(id);
+            }
+            if states.contains(ElementState::FOCUS) {
+                self.dom.force_state(target, ElementState::FOCUS_WITHIN, ElementState::empty());
+            }
+        }
+        self.dom.clear_styles();
+        restyle(self.root, self.stylist, StylesheetGuards::same(self.guard), self.snapshots);
+        self.dom.reset_states();
+        *self.styled_for.borrow_mut() = Some(wanted);
+    }
+}
+
+fn element_state(state: ForcedState) -> ElementState {
+    match state {
+        ForcedState::Hover => ElementState::HOVER,
+        ForcedState::Focus => ElementState::FOCUS,
+        ForcedState::FocusVisible => ElementState::FOCUSRING,
+        ForcedState::Active => ElementState::ACTIVE,
+        ForcedState::Checked => ElementState::CHECKED,
+        ForcedState::Disabled => ElementState::DISABLED,
+        ForcedState::Visited => ElementState::VISITED,
+    }
+}
+
+/// States that cannot hold together with `states`
+fn implied_removals(states: ElementState) -> ElementState {
+    let mut removals = ElementState::empty();
+    if states.contains(ElementState::DISABLED) {
+        removals |= ElementState::ENABLED;
+    }
+    if states.contains(ElementState::VISITED) {
+        removals |= ElementState::UNVISITED;
+    }
+    removals
+}
+
+/// Style every element of the tree under `root` from scratch
+fn restyle(root: StyloElement<'_>, stylist: &Stylist, guards: StylesheetGuards<'_>, snapshots: &SnapshotMap) {
+    let context = SharedStyleContext {
+        stylist,
+        visited_styles_enabled: false,
+        options: GLOBAL_STYLE_DATA.options.clone(),
+        guards,
+        current_time_for_animations: 0.0,
+        traversal_flags: TraversalFlags::empty(),
+        snapshot_map: snapshots,
+        animations: DocumentAnimationSet::default(),
+        registered_speculative_painters: &NoPainters,
+    };
+    let traversal = RecalcStyle { context };
+
+    thread_state::enter(ThreadState::LAYOUT);
+    let token = RecalcStyle::pre_traverse(root, &traversal.context);
+    if token.should_traverse() {
+        style::driver::traverse_dom(&traversal, token, None);
+    }
+    thread_state::exit(ThreadState::LAYOUT);
+}
+
+/// Evaluate one query, restyling the tree as its forced states require
+fn answer(styler: &Styler<'_>, query: &StyleQuery) -> Result<StyleResponse, StyleError> {
+    let pseudo = query.pseudo_element.as_deref().map(|name| parse_pseudo_element(styler.dom, name)).transpose()?;
+    let mut matches = select(styler.dom, &query.selector)?.into_iter();
+
+    let mut response = StyleResponse {
+        id: query.id.clone(),
+        success: true,
+        computed_value: None,
+        computed_styles: None,
+        matches: None,
+        error: None,
+    };
+
+    if query.all_matches {
+        let elements = matches.enumerate()
+            .map(|(index, element)| {
+                styler.style_for(element, query);
+                Ok(ElementStyles {
+                    element: describe(element, index),
+                    styles: computed_styles(&*resolved_style(styler, element, pseudo.as_ref())?, &query.properties)?,
+                })
+            })
+            .collect::<Result<Vec<_>, StyleError>>()?;
+        response.matches = Some(elements);
+        return Ok(response);
+    }
+
+    let element = matches.next().ok_or_else(|| StyleError::ElementNotFound { selector: query.selector.clone() })?;
+    styler.style_for(element, query);
+    let style = resolved_style(styler, element, pseudo.as_ref())?;
+    match query.property {
+        Some(ref property) => response.computed_value = Some(computed_value(&style, property)?),
+        None => response.computed_styles = Some(computed_styles(&style, &query.properties)?),
+    }
+    Ok(response)
+}
+
+/// The pseudo-element `name` (in `::name` form) designates
+fn parse_pseudo_element(dom: &StyloDom, name: &str) -> Result<PseudoElement, StyleError> {
+    let unsupported = || StyleError::unsupported(format!("unknown pseudo-element '{}'", name));
+    let selectors = SelectorParser::parse_author_origin_no_namespace(&format!("*{}", name), dom.url_data())
+        .map_err(|_| unsupported())?;
+    match selectors.slice() {
+        [selector] => selector.pseudo_element().filter(|pseudo| !pseudo.is_precomputed()).cloned().ok_or_else(unsupported),
+        _ => Err(unsupported()),
+    }
+}
+
+/// The style `getComputedStyle(element, pseudo)` reads, from the element's current styles
+///
+/// Eager pseudo-elements come from the traversal, which drops `::before` and `::after`
+/// when they generate no box; those, and lazy pseudo-elements, are cascaded on demand
+/// from the element's primary style.
+fn resolved_style(
+    styler: &Styler<'_>,
+    element: StyloElement<'_>,
+    pseudo: Option<&PseudoElement>,
+) -> Result<ServoArc<ComputedValues>, StyleError> {
+    let data = element.borrow_data().ok_or_else(|| StyleError::protocol("Element was not styled"))?;
+    let primary = data.styles.primary();
+    let Some(pseudo) = pseudo else {
+        return Ok(primary.clone());
+    };
+    let guards = StylesheetGuards::same(styler.guard);
+    if pseudo.is_lazy() {
+        // Without probing, a style is computed even when no rule matches
+        return styler.stylist
+            .lazily_compute_pseudo_element_style(&guards, element, pseudo, RuleInclusion::All, primary, false, None)
+            .ok_or_else(|| StyleError::protocol(format!("Stylo did not resolve {:?}", pseudo)));
+    }
+    if let Some(style) = data.styles.pseudos.get(pseudo) {
+        return Ok(style.clone());
+    }
+
+    let mut caches = SelectorCaches::default();
+    let mut context = MatchingContext::<'_, SelectorImpl>::new(
+        MatchingMode::ForStatelessPseudoElement,
+        None,
+        &mut caches,
+        styler.dom.document().quirks_mode(),
+        NeedsSelectorFlags::No,
+        MatchingForInvalidation::No,
+    );
+    context.extra_data.originating_element_style = Some(primary);
+    let mut declarations = ApplicableDeclarationList::new();
+    styler.stylist.push_applicable_declarations(
+        element,
+        Some(pseudo),
+        None,
+        None,
+        Default::default(),
+        RuleInclusion::All,
+        &mut declarations,
+        &mut context,
+    );
+    let inputs = CascadeInputs {
+        rules: Some(styler.stylist.rule_tree().compute_rule_node(&mut declarations, &guards)),
+        visited_rules: None,
+        flags: context.extra_data.cascade_input_flags,
+        included_cascade_flags: RuleCascadeFlags::empty(),
+    };
+    Ok(styler.stylist.compute_pseudo_element_style_with_inputs(inputs, pseudo, &guards, Some(primary), Some(element)))
+}
+
+/// Elements matching `selector`, in document order
+fn select<'a>(dom: &'a StyloDom, selector: &str) -> Result<Vec<StyloElement<'a>>, StyleError> {
+    let selectors = SelectorParser::parse_author_origin_no_namespace(selector, dom.url_data())
+        .map_err(|e| StyleError::InvalidSelector {
+            selector: selector.to_string(),
+            message: format!("{:?}", e.kind),
+        })?;
+    let quirks_mode = dom.document().quirks_mode();
+    Ok(dom.elements().filter(|element| dom_apis::element_matches(element, &selectors, quirks_mode)).collect())
+}
+
+/// Computed value of one property of `style`, as `getComputedStyle()` serializes it
+fn computed_value(style: &ComputedValues, property: &str) -> Result<String, StyleError> {
+    let id = PropertyId::parse_enabled_for_all_content(property)
+        .map_err(|_| StyleError::UnknownProperty { property: property.to_string() })?;
+    Ok(match id.as_shorthand() {
+        Ok(shorthand) => shorthand_value(style, shorthand),
+        Err(id) => style.computed_value_to_string(id),
+    })
+}
+
+/// A shorthand serialized from the resolved values of its longhands, as Servo's
+/// `getPropertyValue()` does
+///
+/// Shorthands the longhands cannot be expressed as (say `border` with differing
+/// sides) serialize as the empty string.
+fn shorthand_value(style: &ComputedValues, shorthand: ShorthandId) -> String {
+    let mut block = PropertyDeclarationBlock::new();
+    for longhand in shorthand.longhands() {
+        let mut context = resolved::Context {
+            style,
+            for_property: PropertyId::NonCustom(longhand.into()),
+            current_longhand: Some(longhand),
+        };
+        block.push(style.computed_or_resolved_declaration(longhand, Some(&mut context)), Importance::Normal);
+    }
+    let mut value = String::new();
+    match block.shorthand_to_css(shorthand, &mut value) {
+        Ok(()) => value,
+        Err(_) => String::new(),
+    }
+}
+
+/// Computed values of `properties` in `style`, or of every longhand if empty
+fn computed_styles(style: &ComputedValues, properties: &[String]) -> Result<ComputedStyles, StyleError> {
+    if properties.is_empty() {
+        return all_longhands().into_iter()
+            .map(|longhand| Ok((longhand.name().to_string(), computed_value(style, longhand.name())?)))
+            .collect();
+    }
+    properties.iter()
+        .map(|property| Ok((property.clone(), computed_value(style, property)?)))
+        .collect()
+}
+
+/// Every longhand, ordered by name as `getComputedStyle()` enumerates them
+fn all_longhands() -> Vec<LonghandId> {
+    // `all` resets everything except `direction` and `unicode-bidi`
+    let mut longhands: Vec<LonghandId> = ShorthandId::All.longhands()
+        .chain([LonghandId::Direction, LonghandId::UnicodeBidi])
+        .collect();
+    longhands.sort_by_key(|longhand| longhand.name());
+    longhands
+}
+
+/// Describe a matched element the way the page script does
+fn describe(element: StyloElement<'_>, index: usize) -> ElementDescriptor {
+    let mut path = Vec::new();
+    let mut current = Some(element);
+    while let Some(node) = current {
+        let mut step = node.local_name().to_string();
+        if node.traversal_parent().is_some() {
+            let position = std::iter::successors(node.prev_sibling_element(), |sibling| sibling.prev_sibling_element()).count() + 1;
+            step.push_str(&format!(":nth-child({})", position));
+        }
+        path.push(step);
+        current = node.traversal_parent();
+    }
+    path.reverse();
+
+    let mut classes = Vec::new();
+    element.each_class(|class| classes.push(class.to_string()));
+    ElementDescriptor {
+        tag: element.local_name().to_string(),
+        id: element.attribute("id").filter(|id| !id.is_empty()).map(str::to_string),
+        classes,
+        path: path.join(" > "),
+        index,
+    }
+}
+
+/// Sequential restyle of the whole tree, as in Stylo's own non-incremental mode
+struct RecalcStyle<'a> {
+    context: SharedStyleContext<'a>,
+}
+
+impl<'a, E: TElement> DomTraversal<E> for RecalcStyle<'a> {
+    fn process_preorder<F: FnMut(E::ConcreteNode)>(
+        &self,
+        context: &mut StyleContext<E>,
+        node: E::ConcreteNode,
+        note_child: F,
+    ) {
+        if let Some(element) = node.as_element() {
+            let mut data = unsafe { element.ensure_data() };
+            recalc_style_at(self, context, element, &mut data, note_child);
+        }
+    }
+
+    fn process_postorder(&self, _context: &mut StyleContext<E>, _node: E::ConcreteNode) {}
+
+    fn needs_postorder_traversal() -> bool {
+        false
+    }
+
+    fn shared_context(&self) -> &SharedStyleContext<'_> {
+        &self.context
+    }
+}
+
+/// Font metrics for a document without loaded fonts
+///
+/// Font-relative units other than `em` and `rem` resolve with Stylo's fallback ratios.
+#[derive(Debug)]
+struct DefaultFontMetrics;
+
+impl FontMetricsProvider for DefaultFontMetrics {
+    fn query_font_metrics(
+        &self,
+        _vertical: bool,
+        _font: &Font,
+        _base_size: CSSPixelLength,
+        _flags: QueryFontMetricsFlags,
+    ) -> FontMetrics {
+        FontMetrics::default()
+    }
+
+    fn base_size_for_generic(&self, generic: GenericFontFamily) -> Length {
+        let size = match generic {
+            GenericFontFamily::Monospace => 13.0,
+            _ => 16.0,
+        };
+        Length::from(Au::from_f32_px(size))
+    }
+}
+
+/// No CSS Paint API worklets are registered
+struct NoPainters;
+
+impl RegisteredSpeculativePainters for NoPainters {
+    fn get(&self, _name: &Atom) -> Option<&dyn RegisteredSpeculativePainter> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{QueryOptions, StyleEngine};
+    use crate::media::MediaType as EnvMediaType;
+
+    fn engine(html: &str, css: &str) -> StyleEngine {
+        let mut engine = StyleEngine::with_backend(NativeStyloBackend::new());
+        engine.set_html(html).unwrap();
+        engine.add_stylesheet(css).unwrap();
+        engine
+    }
+
+    #[tokio::test]
+    async fn cascades_author_and_user_agent_styles() {
+        let engine = engine(
+            r#"<div id="a" class="x"><p>text</p></div>"#,
+            ".x { color: red; margin: 10px } p { font-size: 2em }",
+        );
+        assert_eq!(engine.get_computed_style("#a", "color").await.unwrap(), "rgb(255, 0, 0)");
+        assert_eq!(engine.get_computed_style("p", "font-size").await.unwrap(), "32px");
+        assert_eq!(engine.get_computed_style("p", "display").await.unwrap(), "block");
+        assert_eq!(engine.get_computed_style("p", "color").await.unwrap(), "rgb(255, 0, 0)");
+        let margin = engine.get_computed_styles("#a", &["margin"]).await.unwrap();
+        assert_eq!(margin.properties().collect::<Vec<_>>(), ["margin-top", "margin-right", "margin-bottom", "margin-left"]);
+    }
+
+    #[tokio::test]
+    async fn matches_structural_selectors_over_text_nodes() {
+        let engine = engine(
+            "<ul><li>one</li> <li>two</li> <li></li></ul>",
+            "li:nth-child(2) { color: blue } li:empty { color: green } li + li { margin-left: 1px }",
+        );
+        let items = engine.get_computed_styles_all_matches("li", &["color", "margin-left"]).await.unwrap();
+        let colors: Vec<_> = items.iter().map(|item| item.styles.get("color").unwrap()).collect();
+        assert_eq!(colors, ["rgb(0, 0, 0)", "rgb(0, 0, 255)", "rgb(0, 128, 0)"]);
+        assert_eq!(items[0].styles.get("margin-left"), Some("0px"));
+        assert_eq!(items[1].element.path, "html > body:nth-child(2) > ul:nth-child(1) > li:nth-child(2)");
+    }
+
+    #[tokio::test]
+    async fn returns_no_matches_for_an_unmatched_selector() {
+        let engine = engine("<ul><li>one</li></ul>", "li { color: blue }");
+        let items = engine.get_computed_styles_all_matches("ol > li", &["color"]).await.unwrap();
+        assert!(items.is_empty());
+    }
+
+    #[tokio::test]
+    async fn applies_inline_styles_and_later_updates() {
+        let backend = NativeStyloBackend::new();
+        let mut engine = StyleEngine::with_backend(backend.clone());
+        engine.set_html(r#"<div id="a" style="padding: 3px; color: red"></div>"#).unwrap();
+        engine.add_stylesheet("#a { color: blue; padding: 1px }").unwrap();
+        assert_eq!(engine.get_computed_style("#a", "padding-left").await.unwrap(), "3px");
+        assert_eq!(engine.get_computed_style("#a", "color").await.unwrap(), "rgb(255, 0, 0)");
+
+        assert_eq!(backend.set_inline_style("#a", "padding: 7px").unwrap(), 1);
+        assert_eq!(engine.get_computed_style("#a", "padding-left").await.unwrap(), "7px");
+        assert_eq!(engine.get_computed_style("#a", "color").await.unwrap(), "rgb(0, 0, 255)");
+    }
+
+    #[tokio::test]
+    async fn forces_states_for_one_query_only() {
+        let engine = engine(
+            r#"<div class="card"><a href="/">link</a></div>"#,
+            "a:hover { color: blue } .card:hover { background-color: red } .card:focus-within { outline-style: solid }",
+        );
+        let hover = QueryOptions::new().with_state(ForcedState::Hover);
+        assert_eq!(engine.get_computed_style_with_options("a", "color", &hover).await.unwrap(), "rgb(0, 0, 255)");
+        assert_eq!(engine.get_computed_style("a", "color").await.unwrap(), "rgb(0, 0, 238)");
+
+        let card = engine.get_computed_style_with_options(".card", "background-color", &hover).await.unwrap();
+        assert_eq!(card, "rgb(255, 0, 0)");
+        let focus = QueryOptions::new().with_state(ForcedState::Focus);
+        assert_eq!(engine.get_computed_style_with_options("a", "outline-style", &focus).await.unwrap(), "none");
+        assert_eq!(engine.get_computed_style_with_options(".card", "outline-style", &focus).await.unwrap(), "solid");
+    }
+
+    #[tokio::test]
+    async fn evaluates_media_queries_in_the_media_environment() {
+        let mut engine = engine(
+            r#"<div id="a"></div>"#,
+            "@media (max-width: 500px) { #a { color: red } }
+             @media (prefers-color-scheme: dark) { #a { background-color: black } }
+             @media (prefers-reduced-motion: reduce) { #a { opacity: 0.5 } }
+             @media print { #a { display: none } }",
+        );
+        assert_eq!(engine.get_computed_style("#a", "color").await.unwrap(), "rgb(0, 0, 0)");
+        engine.set_media_environment(
+            MediaEnvironment::new()
+                .with_viewport(400, 600)
+                .with_color_scheme(ColorScheme::Dark)
+                .with_reduced_motion(true)
+                .with_media_type(EnvMediaType::Print),
+        );
+        let styles = engine.get_computed_styles("#a", &["color", "background-color", "opacity", "display"]).await.unwrap();
+        assert_eq!(styles.get("color"), Some("rgb(255, 0, 0)"));
+        assert_eq!(styles.get("background-color"), Some("rgb(0, 0, 0)"));
+        assert_eq!(styles.get("opacity"), Some("0.5"));
+        assert_eq!(styles.get("display"), Some("none"));
+    }
+
+    #[tokio::test]
+    async fn serializes_shorthands_from_their_longhands() {
+        let engine = engine(
+            r#"<div id="a"></div><div id="b"></div>"#,
+            "#a { margin: 1px 2px; border: 3px solid red } #b { border-top: 1px solid red; border-bottom: 2px dashed blue }",
+        );
+        assert_eq!(engine.get_computed_style("#a", "margin").await.unwrap(), "1px 2px");
+        assert_eq!(engine.get_computed_style("#a", "border").await.unwrap(), "3px solid rgb(255, 0, 0)");
+        assert_eq!(engine.get_computed_style("#b", "border").await.unwrap(), "");
+    }
+
+    #[tokio::test]
+    async fn reports_unknown_elements_and_properties() {
+        let engine = engine("<p></p>", "");
+        assert!(matches!(engine.get_computed_style("#missing", "color").await, Err(StyleError::ElementNotFound { .. })));
+        assert!(matches!(engine.get_computed_style("p", "colour").await, Err(StyleError::UnknownProperty { .. })));
+        assert!(matches!(engine.get_computed_style("p[", "color").await, Err(StyleError::InvalidSelector { .. })));
+    }
+
+    #[tokio::test]
+    async fn resolves_eager_and_lazy_pseudo_elements() {
+        let engine = engine(
+            r#"<ul class="x"><li>one</li></ul><input placeholder="name">"#,
+            r#".x { color: green } .x::before { content: "*"; color: red }
+               .x::after { margin-left: 2px } li::marker { color: blue } input::placeholder { opacity: 0.5 }"#,
+        );
+        let before = QueryOptions::new().with_pseudo_element("::before");
+        let styles = engine.get_computed_styles_with_options(".x", &["content", "color", "display"], &before).await.unwrap();
+        assert_eq!(styles.get("content"), Some("\"*\""));
+        assert_eq!(styles.get("color"), Some("rgb(255, 0, 0)"));
+        assert_eq!(styles.get("display"), Some("inline"));
+
+        // `::after` generates no box without `content`, but still has a style
+        let after = QueryOptions::new().with_pseudo_element("after");
+        let styles = engine.get_computed_styles_with_options(".x", &["margin-left", "color"], &after).await.unwrap();
+        assert_eq!(styles.get("margin-left"), Some("2px"));
+        assert_eq!(styles.get("color"), Some("rgb(0, 128, 0)"));
+
+        let marker = QueryOptions::new().with_pseudo_element("::marker");
+        assert_eq!(engine.get_computed_style_with_options("li", "color", &marker).await.unwrap(), "rgb(0, 0, 255)");
+        let placeholder = QueryOptions::new().with_pseudo_element("::placeholder");
+        assert_eq!(engine.get_computed_style_with_options("input", "opacity", &placeholder).await.unwrap(), "0.5");
+
+        let unknown = QueryOptions::new().with_pseudo_element("::nonsense");
+        assert!(matches!(
+            engine.get_computed_style_with_options(".x", "color", &unknown).await,
+            Err(StyleError::Unsupported { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn styles_a_hand_built_tree() {
+        let root = NativeElement::new("html").with_child(
+            NativeElement::new("body").with_child(NativeElement::new("span").with_attribute("class", "x").with_text("hi")),
+        );
+        let mut engine = StyleEngine::with_backend(NativeStyloBackend::with_document(&root));
+        engine.add_stylesheet(".x { font-weight: bold }").unwrap();
+        assert_eq!(engine.get_computed_style("span", "font-weight").await.unwrap(), "700");
+        assert_eq!(engine.get_computed_style("span", "display").await.unwrap(), "inline");
+    }
+
+    #[test]
+    fn emulates_reduced_motion_queries() {
+        let css = "@media (prefers-reduced-motion: reduce) { a {} } @media (PREFERS-REDUCED-MOTION) { b {} }";
+        assert_eq!(emulate_reduced_motion(css, true), format!("@media {} {{ a {{}} }} @media {} {{ b {{}} }}", MATCH_ALL, MATCH_ALL));
+        let css = "@media (prefers-reduced-motion: no-preference) { a {} }";
+        assert_eq!(emulate_reduced_motion(css, true), format!("@media {} {{ a {{}} }}", MATCH_NONE));
+    }
+}
@@ -0,0 +1,536 @@
+//! Query extraction page loaded by Servo for one-shot and batch runs.
+//!
+//! Queries are never spliced into JavaScript. They are serialized into a
+//! `<script type="application/json">` payload block that the fixed extraction script
+//! parses, so selectors and property names may contain any character.
+//!
+//! The script reports each query as one record on the console:
+//! `<MARKER>:<nonce>:<json>`, where the nonce is generated per page. The payload and the
+//! script open `<head>`, so they run before any of the document's markup is parsed: the
+//! script removes the payload and captures `console.log`, `JSON` and `getComputedStyle`
+//! before the document's scripts could observe or replace them. Console lines without the
+//! nonce are never taken as results, so `console.log` calls in the document or Servo
+//! diagnostics echoing page text are not mistaken for them. This guards against
+//! accidents, not hostile documents: the document's scripts still share the page and can
+//! change the elements the script inspects. A record whose JSON is cut short is
+//! completed from the following lines, for when Servo wraps long console messages.
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::backend::{ComputedStyles, ElementStyles, StyleDocument, StyleQuery};
+use crate::error::StyleError;
+use crate::media::MediaEnvironment;
+
+/// Element id of the JSON payload block
+const PAYLOAD_ELEMENT_ID: &str = "stylo-compute-payload";
+
+/// Parameters the extraction script reads from the page
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct PagePayload {
+    /// Batch pages frame their output with `BATCH_START`/`BATCH_END` and key results by
+    /// query id; single-query pages print bare results
+    pub(crate) batch: bool,
+    /// Secret every result record must carry
+    pub(crate) nonce: String,
+    pub(crate) media: MediaEnvironment,
+    pub(crate) queries: Vec<StyleQuery>,
+}
+
+/// Kind of a console record, named by its marker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RecordKind {
+    /// `COMPUTED_STYLE_RESULT`: one property's value
+    Value,
+    /// `COMPUTED_STYLES_RESULT`: the requested properties, or every computed one
+    Styles,
+    /// `COMPUTED_STYLE_ERROR`: the query failed in the page
+    Error,
+    /// `COMPUTED_STYLE_TIMEOUT`: the page's watchdog stopped the query
+    Timeout,
+}
+
+const MARKERS: [(&str, RecordKind); 4] = [
+    ("COMPUTED_STYLE_RESULT:", RecordKind::Value),
+    ("COMPUTED_STYLES_RESULT:", RecordKind::Styles),
+    ("COMPUTED_STYLE_ERROR:", RecordKind::Error),
+    ("COMPUTED_STYLE_TIMEOUT:", RecordKind::Timeout),
+];
+
+/// Longest record text reassembled from continuation lines
+const MAX_RECORD_LEN: usize = 16 * 1024 * 1024;
+
+/// One query outcome reported by the extraction script
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct RecordBody {
+    #[serde(default)]
+    pub(crate) id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) selector: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) property: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) value: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) styles: Option<ComputedStyles>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) matches: Option<Vec<ElementStyles>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<StyleError>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) elapsed_ms: Option<u64>,
+}
+
+/// A console record that carried the page's nonce
+#[derive(Debug, Clone)]
+pub(crate) struct ConsoleRecord {
+    pub(crate) kind: RecordKind,
+    pub(crate) body: RecordBody,
+}
+
+/// Script functions shared by the extraction page and the daemon page
+///
+/// Expands to a string literal so both pages can be assembled with `concat!`. The
+/// functions take the document and window they work on rather than using the globals,
+/// as the daemon page evaluates a frame's document.
+macro_rules! page_helpers {
+    () => {
+        r#"
+            function isUnknownProperty(win, property) {
+                return property.indexOf('--') !== 0 && typeof win.CSS !== 'undefined' &&
+                    typeof win.CSS.supports === 'function' && !win.CSS.supports(property, 'inherit');
+            }
+
+            function firstUnknownProperty(win, properties) {
+                return (properties || []).filter(function(name) { return isUnknownProperty(win, name); })[0];
+            }
+
+            function budget(query) {
+                return typeof query.timeout_ms === 'number' ? query.timeout_ms : Infinity;
+            }
+
+            function describe(element, index) {
+                var path = [];
+                for (var node = element; node && node.nodeType === 1; node = node.parentElement) {
+                    var step = node.localName;
+                    if (node.parentElement) {
+                        step += ':nth-child(' + (Array.prototype.indexOf.call(node.parentElement.children, node) + 1) + ')';
+                    }
+                    path.unshift(step);
+                }
+                return {
+                    tag: element.localName,
+                    id: element.id || null,
+                    classes: Array.prototype.slice.call(element.classList),
+                    path: path.join(' > '),
+                    index: index
+                };
+            }
+
+            // Media emulation: queries on features Servo cannot be configured for are rewritten
+            // to conditions that always or never match in the emulated environment
+            var MATCH_ALL = '(min-width: 0px)';
+            var MATCH_NONE = '(not (min-width: 0px))';
+
+            function emulateMediaText(text, media) {
+                return text
+                    .replace(/\(\s*prefers-color-scheme\s*:\s*(light|dark)\s*\)/gi, function(_, scheme) {
+                        return scheme.toLowerCase() === media.color_scheme ? MATCH_ALL : MATCH_NONE;
+                    })
+                    .replace(/\(\s*prefers-reduced-motion\s*(?::\s*(reduce|no-preference)\s*)?\)/gi, function(_, value) {
+                        var reduce = !value || value.toLowerCase() === 'reduce';
+                        return reduce === media.reduced_motion ? MATCH_ALL : MATCH_NONE;
+                    })
+                    .replace(/(^|[\s,])(screen|print)(?![\w-])/gi, function(_, before, type) {
+                        // Media types nobody defines never match
+                        return before + (type.toLowerCase() === media.media_type ? 'all' : 'unmatched');
+                    });
+            }
+
+            function emulateMedia(doc, media) {
+                var styles = doc.querySelectorAll('style');
+                for (var i = 0; i < styles.length; i++) {
+                    var css = styles[i].textContent;
+                    var rewritten = css.replace(/@media([^{;]*)\{/gi, function(_, prelude) {
+                        return '@media' + emulateMediaText(prelude, media) + '{';
+                    });
+                    if (rewritten !== css) {
+                        styles[i].textContent = rewritten;
+                    }
+                }
+                var owners = doc.querySelectorAll('style[media], link[media]');
+                for (var j = 0; j < owners.length; j++) {
+                    owners[j].setAttribute('media', emulateMediaText(owners[j].getAttribute('media'), media));
+                }
+            }
+
+            // Forced states: rules using a state pseudo-class are rewritten to match a marker
+            // attribute instead, while checked and disabled set the real DOM properties
+            var STATE_ATTRIBUTE = 'data-stylo-compute-state';
+
+            function rewriteStateRules(rules) {
+                for (var i = 0; i < rules.length; i++) {
+                    var rule = rules[i];
+                    if (rule.selectorText) {
+                        var text = rule.selectorText
+                            .replace(/:(hover|active|focus-visible|focus-within|focus|visited)(?![\w-])/g, function(_, state) {
+                                return '[' + STATE_ATTRIBUTE + '~="' + state + '"]';
+                            })
+                            .replace(/:link(?![\w-])/g, ':link:not([' + STATE_ATTRIBUTE + '~="visited"])');
+                        if (text !== rule.selectorText) {
+                            rule.selectorText = text;
+                        }
+                    }
+                    if (rule.cssRules) {
+                        rewriteStateRules(rule.cssRules);
+                    }
+                }
+            }
+
+            function prepareStates(doc) {
+                for (var i = 0; i < doc.styleSheets.length; i++) {
+                    try {
+                        rewriteStateRules(doc.styleSheets[i].cssRules);
+                    } catch (e) {
+                        // Rules of cross-origin sheets cannot be read
+                    }
+                }
+            }
+
+            // Force the query's states on element, returning a function that undoes it
+            function forceStates(element, query) {
+                var states = query.forced_states || [];
+                var focus = states.indexOf('focus') !== -1;
+                var undo = [];
+                for (var node = element, depth = 0; node && states.length; node = node.parentElement, depth++) {
+                    if (depth > 0 && !query.states_on_ancestors && !focus) {
+                        break;
+                    }
+                    var own = depth === 0 || query.states_on_ancestors ? states : [];
+                    var marks = own.filter(function(state) { return state !== 'checked' && state !== 'disabled'; });
+                    if (focus) {
+                        marks.push('focus-within');
+                    }
+                    undo.push(setAttribute(node, STATE_ATTRIBUTE, marks.join(' ')));
+                    own.forEach(function(state) {
+                        var property = state === 'checked' && node.localName === 'option' ? 'selected' : state;
+                        if ((state === 'checked' || state === 'disabled') && property in node) {
+                            undo.push(setProperty(node, property, true));
+                        }
+                    });
+                }
+                return function() {
+                    undo.reverse().forEach(function(restore) { restore(); });
+                };
+            }
+
+            function setAttribute(node, name, value) {
+                var previous = node.getAttribute(name);
+                node.setAttribute(name, value);
+                return function() {
+                    if (previous === null) {
+                        node.removeAttribute(name);
+                    } else {
+                        node.setAttribute(name, previous);
+                    }
+                };
+            }
+
+            function setProperty(node, name, value) {
+                var previous = node[name];
+                node[name] = value;
+                return function() { node[name] = previous; };
+            }
+
+            function stylesOf(computedStyle, properties) {
+                var styles = {};
+                if (properties && properties.length) {
+                    properties.forEach(function(name) {
+                        styles[name] = computedStyle.getPropertyValue(name);
+                    });
+                    return styles;
+                }
+                for (var i = 0; i < computedStyle.length; i++) {
+                    var propName = computedStyle[i];
+                    styles[propName] = computedStyle.getPropertyValue(propName);
+                }
+                return styles;
+            }
+"#
+    };
+}
+pub(crate) use page_helpers;
+
+/// Evaluates the payload's queries on load and prints the console protocol
+///
+/// Queries run one after another. A watchdog reports a query as timed out when it takes
+/// longer than its `timeout_ms`, or when the page has used up its longest deadline before
+/// the query gets to run, so one slow query does not hide its siblings' results.
+const EXTRACTION_SCRIPT: &str = concat!(r#"
+        (function() {
+            // This runs before the document is parsed: take the payload out of the DOM and
+            // keep the functions the results depend on before the document's scripts run
+            var payloadElement = document.getElementById('stylo-compute-payload');
+            var payload = JSON.parse(payloadElement.textContent);
+            payloadElement.parentNode.removeChild(payloadElement);
+            var queries = payload.queries;
+            var nonce = payload.nonce;
+            var log = console.log.bind(console);
+            var stringify = JSON.stringify;
+            var getComputedStyle = window.getComputedStyle.bind(window);
+
+            function record(marker, query, body) {
+                body.id = query.id;
+                return marker + nonce + ':' + stringify(body);
+            }
+"#, page_helpers!(), r#"
+            function failure(query, e) {
+                if (e && e.name === 'SyntaxError') {
+                    return { kind: 'invalid-selector', selector: query.selector, message: String(e.message) };
+                }
+                return { kind: 'script', message: String(e && e.message) };
+            }
+
+            function evaluateAll(query) {
+                var unknown = firstUnknownProperty(window, query.properties);
+                if (unknown) {
+                    return record('COMPUTED_STYLE_ERROR:', query, {
+                        error: { kind: 'unknown-property', property: unknown }
+                    });
+                }
+                var elements = document.querySelectorAll(query.selector);
+                var matches = [];
+                for (var i = 0; i < elements.length; i++) {
+                    var restore = forceStates(elements[i], query);
+                    try {
+                        matches.push({
+                            element: describe(elements[i], i),
+                            styles: stylesOf(getComputedStyle(elements[i], query.pseudo_element || null), query.properties)
+                        });
+                    } finally {
+                        restore();
+                    }
+                }
+                return record('COMPUTED_STYLES_RESULT:', query, {
+                    selector: query.selector,
+                    matches: matches
+                });
+            }
+
+            function evaluate(query) {
+                try {
+                    if (query.all_matches) {
+                        return evaluateAll(query);
+                    }
+                    var element = document.querySelector(query.selector);
+                    if (!element) {
+                        return record('COMPUTED_STYLE_ERROR:', query, {
+                            error: { kind: 'element-not-found', selector: query.selector }
+                        });
+                    }
+                    var restore = forceStates(element, query);
+                    try {
+                        var computedStyle = getComputedStyle(element, query.pseudo_element || null);
+                        if (query.property !== null && query.property !== undefined) {
+                            if (isUnknownProperty(window, query.property)) {
+                                return record('COMPUTED_STYLE_ERROR:', query, {
+                                    error: { kind: 'unknown-property', property: query.property }
+                                });
+                            }
+                            return record('COMPUTED_STYLE_RESULT:', query, {
+                                selector: query.selector,
+                                property: query.property,
+                                value: computedStyle.getPropertyValue(query.property)
+                            });
+                        }
+                        var unknown = firstUnknownProperty(window, query.properties);
+                        if (unknown) {
+                            return record('COMPUTED_STYLE_ERROR:', query, {
+                                error: { kind: 'unknown-property', property: unknown }
+                            });
+                        }
+                        return record('COMPUTED_STYLES_RESULT:', query, {
+                            selector: query.selector,
+                            styles: stylesOf(computedStyle, query.properties)
+                        });
+                    } finally {
+                        restore();
+                    }
+                } catch (e) {
+                    return record('COMPUTED_STYLE_ERROR:', query, { error: failure(query, e) });
+                }
+            }
+
+            window.addEventListener('load', function() {
+                if (payload.batch) {
+                    log('BATCH_START:' + queries.length);
+                }
+                var started = Date.now();
+                var deadline = queries.reduce(function(max, query) { return Math.max(max, budget(query)); }, 0);
+                emulateMedia(document, payload.media);
+                if (queries.some(function(query) { return query.forced_states && query.forced_states.length; })) {
+                    prepareStates(document);
+                }
+
+                function next(i) {
+                    if (i >= queries.length) {
+                        if (payload.batch) {
+                            log('BATCH_END:' + queries.length);
+                        }
+                        // Give Servo time to flush the console before exiting
+                        setTimeout(function() { window.close(); }, 500);
+                        return;
+                    }
+                    var query = queries[i];
+                    var begin = Date.now();
+                    if (begin - started >= deadline) {
+                        log(record('COMPUTED_STYLE_TIMEOUT:', query, { elapsed_ms: begin - started }));
+                    } else {
+                        var line = evaluate(query);
+                        var elapsed = Date.now() - begin;
+                        log(elapsed > budget(query) ? record('COMPUTED_STYLE_TIMEOUT:', query, { elapsed_ms: elapsed }) : line);
+                    }
+                    // Yield between queries so the page stays responsive
+                    setTimeout(function() { next(i + 1); }, 0);
+                }
+                next(0);
+            });
+        })();
+    "#);
+
+/// A fresh random nonce for one page
+pub(crate) fn new_nonce() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Full extraction page for `queries` against `document`, reporting with `nonce`
+pub(crate) fn extraction_page(document: &StyleDocument, queries: &[StyleQuery], batch: bool, nonce: &str) -> String {
+    let payload = PagePayload {
+        batch,
+        nonce: nonce.to_string(),
+        media: document.media.clone(),
+        queries: queries.to_vec(),
+    };
+    document.to_html_with_head(&format!(
+        "<script type=\"application/json\" id=\"{}\">{}</script>\n    <script>{}</script>",
+        PAYLOAD_ELEMENT_ID,
+        payload_json(&payload),
+        EXTRACTION_SCRIPT
+    ))
+}
+
+/// Read the payload back from a page built by `extraction_page`
+pub(crate) fn read_payload(page_html: &str) -> Option<PagePayload> {
+    let marker = format!("id=\"{}\">", PAYLOAD_ELEMENT_ID);
+    let start = page_html.find(&marker)? + marker.len();
+    let end = start + page_html[start..].find("</script>")?;
+    serde_json::from_str(&page_html[start..end]).ok()
+}
+
+/// JSON that is safe to embed in a `<script>` element: `<`, `>` and `&` are escaped so
+/// the payload can never close the element or start a comment
+fn payload_json(payload: &PagePayload) -> String {
+    serde_json::to_string(payload)
+        .unwrap_or_default()
+        .replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026")
+}
+
+/// Console line for a record, as the extraction script prints it
+pub(crate) fn format_record(kind: RecordKind, nonce: &str, body: &RecordBody) -> String {
+    let marker = MARKERS.iter().find(|(_, k)| *k == kind).map_or("", |(marker, _)| marker);
+    format!("{}{}:{}", marker, nonce, serde_json::to_string(body).unwrap_or_default())
+}
+
+/// Records carrying `nonce` in one console stream, in output order
+///
+/// Marker lines without the nonce are skipped. A record may start with its marker split
+/// over two lines, and a record whose JSON ends early is joined with the lines that
+/// follow until it parses.
+pub(crate) fn read_records(output: &str, nonce: &str) -> Vec<ConsoleRecord> {
+    let lines: Vec<&str> = output.lines().collect();
+    let mut records = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        let start = find_record(line, nonce).map(|(kind, json)| (kind, json.to_string(), i + 1)).or_else(|| {
+            // A marker cut in two by a line break
+            let joined = format!("{}{}", line, lines.get(i + 1)?);
+            let (kind, json) = find_record(&joined, nonce)?;
+            (joined.len() - json.len() > line.len()).then(|| (kind, json.to_string(), i + 2))
+        });
+        let Some((kind, mut text, mut next)) = start else {
+            if MARKERS.iter().any(|(marker, _)| line.contains(marker)) {
+                debug!(line, "Ignoring result line without the page nonce");
+            }
+            i += 1;
+            continue;
+        };
+
+        loop {
+            match serde_json::from_str::<RecordBody>(text.trim()) {
+                Ok(body) => {
+                    records.push(ConsoleRecord { kind, body });
+                    i = next;
+                    break;
+                }
+                Err(e) if e.is_eof() && text.len() < MAX_RECORD_LEN => match lines.get(next) {
+                    Some(more) if find_record(more, nonce).is_none() => {
+                        text.push_str(more);
+                        next += 1;
+                    }
+                    _ => {
+                        warn!(?kind, "Result record was cut off");
+                        i += 1;
+                        break;
+                    }
+                },
+                Err(e) => {
+                    warn!(?kind, error = %e, "Malformed result record");
+                    i += 1;
+                    break;
+                }
+            }
+        }
+    }
+
+    records
+}
+
+/// Kind and JSON text of a record starting in `line`
+fn find_record<'a>(line: &'a str, nonce: &str) -> Option<(RecordKind, &'a str)> {
+    MARKERS.iter().find_map(|(marker, kind)| {
+        let tag = format!("{}{}:", marker, nonce);
+        line.find(&tag).map(|at| (*kind, &line[at + tag.len()..]))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embeds_hostile_queries_as_inert_json() {
+        let selector = "a[title='\"\\</script><!--\u{2028}--></SCRIPT>']";
+        let property = "--x'\"\\</script><!--\u{2028}";
+        let document = StyleDocument::default();
+        let page = extraction_page(&document, &[StyleQuery::new(selector, Some(property))], false, "0123");
+
+        let open = format!("<script type=\"application/json\" id=\"{}\">", PAYLOAD_ELEMENT_ID);
+        let start = page.find(&open).unwrap() + open.len();
+        let end = start + page[start..].find("</script>").unwrap();
+        let block = &page[start..end];
+        assert!(!block.contains('<'), "{}", block);
+
+        let payload: PagePayload = serde_json::from_str(block).unwrap();
+        assert_eq!(payload.queries[0].selector, selector);
+        assert_eq!(payload.queries[0].property.as_deref(), Some(property));
+
+        // The page has the same elements as one built from a harmless query
+        let plain = extraction_page(&document, &[StyleQuery::new("a", Some("color"))], false, "0123");
+        assert_eq!(page.matches("</script>").count(), plain.matches("</script>").count());
+        assert_eq!(page.matches("<!--").count(), plain.matches("<!--").count());
+    }
+}
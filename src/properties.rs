@@ -0,0 +1,120 @@
+//! CSS shorthand properties and the longhands they expand to.
+//!
+//! Computed styles only exist for longhands, so requested shorthands are expanded
+//! before a query is sent and their longhands are returned in their place.
+
+/// Longhands set by each supported shorthand, in specification order
+const SHORTHANDS: &[(&str, &[&str])] = &[
+    ("animation", &[
+        "animation-name", "animation-duration", "animation-timing-function", "animation-delay",
+        "animation-iteration-count", "animation-direction", "animation-fill-mode", "animation-play-state",
+    ]),
+    ("background", &[
+        "background-color", "background-image", "background-position-x", "background-position-y",
+        "background-size", "background-repeat", "background-attachment", "background-origin", "background-clip",
+    ]),
+    ("background-position", &["background-position-x", "background-position-y"]),
+    ("border", &[
+        "border-top-width", "border-right-width", "border-bottom-width", "border-left-width",
+        "border-top-style", "border-right-style", "border-bottom-style", "border-left-style",
+        "border-top-color", "border-right-color", "border-bottom-color", "border-left-color",
+    ]),
+    ("border-bottom", &["border-bottom-width", "border-bottom-style", "border-bottom-color"]),
+    ("border-color", &["border-top-color", "border-right-color", "border-bottom-color", "border-left-color"]),
+    ("border-left", &["border-left-width", "border-left-style", "border-left-color"]),
+    ("border-radius", &[
+        "border-top-left-radius", "border-top-right-radius", "border-bottom-right-radius", "border-bottom-left-radius",
+    ]),
+    ("border-right", &["border-right-width", "border-right-style", "border-right-color"]),
+    ("border-style", &["border-top-style", "border-right-style", "border-bottom-style", "border-left-style"]),
+    ("border-top", &["border-top-width", "border-top-style", "border-top-color"]),
+    ("border-width", &["border-top-width", "border-right-width", "border-bottom-width", "border-left-width"]),
+    ("column-rule", &["column-rule-width", "column-rule-style", "column-rule-color"]),
+    ("columns", &["column-width", "column-count"]),
+    ("flex", &["flex-grow", "flex-shrink", "flex-basis"]),
+    ("flex-flow", &["flex-direction", "flex-wrap"]),
+    ("font", &[
+        "font-style", "font-variant-caps", "font-weight", "font-stretch", "font-size", "line-height", "font-family",
+    ]),
+    ("gap", &["row-gap", "column-gap"]),
+    ("grid-area", &["grid-row-start", "grid-column-start", "grid-row-end", "grid-column-end"]),
+    ("grid-column", &["grid-column-start", "grid-column-end"]),
+    ("grid-row", &["grid-row-start", "grid-row-end"]),
+    ("grid-template", &["grid-template-rows", "grid-template-columns", "grid-template-areas"]),
+    ("inset", &["top", "right", "bottom", "left"]),
+    ("list-style", &["list-style-position", "list-style-image", "list-style-type"]),
+    ("margin", &["margin-top", "margin-right", "margin-bottom", "margin-left"]),
+    ("outline", &["outline-color", "outline-style", "outline-width"]),
+    ("overflow", &["overflow-x", "overflow-y"]),
+    ("padding", &["padding-top", "padding-right", "padding-bottom", "padding-left"]),
+    ("place-content", &["align-content", "justify-content"]),
+    ("place-items", &["align-items", "justify-items"]),
+    ("place-self", &["align-self", "justify-self"]),
+    ("text-decoration", &["text-decoration-line", "text-decoration-style", "text-decoration-color"]),
+    ("transition", &[
+        "transition-property", "transition-duration", "transition-timing-function", "transition-delay",
+    ]),
+];
+
+/// The longhands `property` expands to, if it is a supported shorthand
+pub fn longhands(property: &str) -> Option<&'static [&'static str]> {
+    SHORTHANDS.iter()
+        .find(|(shorthand, _)| shorthand.eq_ignore_ascii_case(property))
+        .map(|(_, longhands)| *longhands)
+}
+
+/// `properties` with shorthands replaced by their longhands, without duplicates
+pub(crate) fn expand_shorthands(properties: &[&str]) -> Vec<String> {
+    let mut expanded: Vec<String> = Vec::new();
+    for property in properties {
+        let property = property.trim();
+        match longhands(property) {
+            Some(longhands) => {
+                for longhand in longhands {
+                    if !expanded.iter().any(|seen| seen == longhand) {
+                        expanded.push(longhand.to_string());
+                    }
+                }
+            }
+            None => {
+                if !expanded.iter().any(|seen| seen == property) {
+                    expanded.push(property.to_string());
+                }
+            }
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longhands_appear_once_in_first_requested_order() {
+        assert_eq!(
+            expand_shorthands(&["margin-left", "margin", "margin-top"]),
+            ["margin-left", "margin-top", "margin-right", "margin-bottom"]
+        );
+        assert_eq!(expand_shorthands(&["color", " color ", "color"]), ["color"]);
+    }
+
+    #[test]
+    fn overlapping_shorthands_share_longhands() {
+        let expanded = expand_shorthands(&["border", "border-top", "border-color", "BORDER-WIDTH"]);
+        assert_eq!(expanded.len(), 12);
+        assert_eq!(expanded, longhands("border").unwrap());
+
+        let expanded = expand_shorthands(&["background-position", "background"]);
+        assert_eq!(&expanded[..2], ["background-position-x", "background-position-y"]);
+        assert_eq!(expanded.len(), longhands("background").unwrap().len());
+    }
+
+    #[test]
+    fn unknown_properties_pass_through() {
+        assert_eq!(expand_shorthands(&["--gap", "flex", "not-a-property"]), [
+            "--gap", "flex-grow", "flex-shrink", "flex-basis", "not-a-property",
+        ]);
+        assert_eq!(longhands("color"), None);
+    }
+}
@@ -0,0 +1,521 @@
+//! Pool of persistent Servo daemon workers.
+//!
+//! Each worker owns one Servo process running the daemon page and processes one batch
+//! at a time. Batches are split into chunks of `batch_size` queries and dispatched to
+//! the least-loaded healthy worker; callers queue on a worker when all are busy.
+//! Workers start lazily and are restarted after a failure.
+//!
+//! Daemon processes, their loopback channels and output readers run on a runtime of the
+//! pool's own rather than the caller's, so a pool keeps working when the runtime that
+//! started a worker shuts down (each `#[tokio::test]` has its own) and can be used from
+//! several runtimes at once.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::{Mutex, Semaphore};
+use tracing::{debug, debug_span, info, trace, warn, Instrument};
+
+use crate::artifacts::{ArtifactSet, DebugArtifacts};
+use crate::backend::{self, StyleDocument, StyleQuery, StyleResponse};
+use crate::config::EngineConfig;
+use crate::daemon_channel::{BatchQuery, DaemonChannel, DAEMON_PAGE};
+use crate::error::StyleError;
+use crate::media::MediaEnvironment;
+use crate::servo_process::{self, command_line, STDERR_TAIL_LINES};
+
+/// How long to wait for a freshly started daemon page to connect back
+const DAEMON_STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many workers a failing chunk is tried on before giving up
+const MAX_ATTEMPTS: usize = 2;
+
+/// Extra time given to the daemon page past a batch's deadline to report its own timeouts
+const WATCHDOG_GRACE: Duration = Duration::from_millis(500);
+
+/// Pools shared by engines that use the same Servo settings
+static SHARED_POOLS: LazyLock<std::sync::Mutex<HashMap<String, Arc<ServoPool>>>> =
+    LazyLock::new(|| std::sync::Mutex::new(HashMap::new()));
+
+/// Runtime the daemons' processes, channels and output readers live on
+static DAEMON_RUNTIME: LazyLock<tokio::runtime::Runtime> = LazyLock::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("stylo-compute-daemon")
+        .enable_all()
+        .build()
+        .expect("failed to build the Servo daemon runtime")
+});
+
+/// Servo daemon process manager
+struct ServoDaemon {
+    process: Child,
+    channel: Arc<DaemonChannel>,
+    artifacts: Option<ArtifactSet>,
+    /// Most recent stderr lines, for crash reports
+    stderr_tail: Arc<std::sync::Mutex<VecDeque<String>>>,
+}
+
+impl ServoDaemon {
+    async fn start(servo_path: &str, servo_args: &[String], artifacts: Option<ArtifactSet>) -> Result<Self, StyleError> {
+        // The daemon page is served over loopback and polls the channel for batches
+        let channel = Arc::new(DaemonChannel::bind().await?);
+        let page_url = channel.page_url();
+        info!(servo = servo_path, args = ?servo_args, addr = %channel.addr(), "Starting Servo daemon");
+        if let Some(ref artifacts) = artifacts {
+            artifacts.write("command.txt", command_line(servo_path, servo_args, &page_url) + "\n");
+            artifacts.write("page.html", DAEMON_PAGE);
+        }
+
+        let mut process = Command::new(servo_path)
+            .args(servo_args)
+            .arg("--headless")
+            .arg(&page_url)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| StyleError::SpawnFailed {
+                command: command_line(servo_path, servo_args, &page_url),
+                message: e.to_string(),
+            })?;
+
+        // Servo blocks once its pipes fill up, so keep draining them
+        let stderr_tail = Arc::new(std::sync::Mutex::new(VecDeque::new()));
+        if let Some(stdout) = process.stdout.take() {
+            let log = artifacts.as_ref().and_then(|artifacts| artifacts.create("stdout.log"));
+            tokio::spawn(drain_daemon_output(stdout, "stdout", log, None).in_current_span());
+        }
+        if let Some(stderr) = process.stderr.take() {
+            let log = artifacts.as_ref().and_then(|artifacts| artifacts.create("stderr.log"));
+            tokio::spawn(drain_daemon_output(stderr, "stderr", log, Some(stderr_tail.clone())).in_current_span());
+        }
+
+        // Stop waiting early if Servo dies before the page connects
+        let ready = tokio::select! {
+            ready = channel.wait_ready(DAEMON_STARTUP_TIMEOUT) => ready,
+            Ok(status) = process.wait() => {
+                // Give the stderr reader a moment to catch the last lines
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                let tail = stderr_tail.lock().unwrap();
+                Err(StyleError::ServoCrashed {
+                    status: status.to_string(),
+                    exit_code: status.code(),
+                    stderr_tail: servo_process::stderr_tail(tail.iter().map(String::as_str)),
+                })
+            }
+        };
+        if let Err(e) = ready {
+            let _ = process.kill().await;
+            if let Some(ref artifacts) = artifacts {
+                artifacts.write("status.txt", format!("failed to start: {}\n", e));
+            }
+            return Err(e);
+        }
+
+        info!(addr = %channel.addr(), pid = process.id(), "Servo daemon started");
+
+        Ok(ServoDaemon {
+            process,
+            channel,
+            artifacts,
+            stderr_tail,
+        })
+    }
+
+    /// The crash error, if the process has exited
+    fn crash(&mut self) -> Option<StyleError> {
+        let status = self.process.try_wait().ok()??;
+        let tail = self.stderr_tail.lock().unwrap();
+        Some(StyleError::ServoCrashed {
+            status: status.to_string(),
+            exit_code: status.code(),
+            stderr_tail: servo_process::stderr_tail(tail.iter().map(String::as_str)),
+        })
+    }
+
+    fn is_alive(&mut self) -> bool {
+        match self.process.try_wait() {
+            Ok(Some(status)) => {
+                // Process has exited
+                if let Some(ref artifacts) = self.artifacts {
+                    artifacts.write("status.txt", format!("{}\n", status));
+                }
+                false
+            }
+            Ok(None) => true,     // Process is still running
+            Err(_) => false,      // Error checking process
+        }
+    }
+}
+
+/// Read daemon console output, surfacing errors reported by the daemon page
+async fn drain_daemon_output<R: AsyncRead + Unpin>(
+    output: R,
+    stream: &'static str,
+    mut log: Option<std::fs::File>,
+    tail: Option<Arc<std::sync::Mutex<VecDeque<String>>>>,
+) {
+    let mut lines = BufReader::new(output).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if let Some(ref mut log) = log {
+            let _ = writeln!(log, "{}", line);
+        }
+        if let Some(ref tail) = tail {
+            let mut tail = tail.lock().unwrap();
+            if tail.len() == STDERR_TAIL_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(line.clone());
+        }
+        if let Some(error) = line.split("SERVO_DAEMON_ERROR:").nth(1) {
+            warn!(error, "Servo daemon error");
+        } else {
+            trace!(stream, line = %line, "Servo daemon output");
+        }
+    }
+}
+
+/// Snapshot of one worker's state
+#[derive(Debug, Clone, Default)]
+pub struct WorkerHealth {
+    pub index: usize,
+    /// Whether the worker's Servo process is currently running
+    pub running: bool,
+    /// Batches waiting for or being processed by this worker
+    pub queued: usize,
+    pub batches_completed: u64,
+    pub consecutive_failures: u32,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+}
+
+impl WorkerHealth {
+    /// A worker is healthy until a batch fails, and again after one succeeds
+    pub fn is_healthy(&self) -> bool {
+        self.consecutive_failures == 0
+    }
+}
+
+struct Worker {
+    index: usize,
+    daemon: Mutex<Option<ServoDaemon>>,
+    slot: Semaphore,
+    queued: AtomicUsize,
+    health: std::sync::Mutex<WorkerHealth>,
+}
+
+impl Worker {
+    fn new(index: usize) -> Self {
+        Worker {
+            index,
+            daemon: Mutex::new(None),
+            slot: Semaphore::new(1),
+            queued: AtomicUsize::new(0),
+            health: std::sync::Mutex::new(WorkerHealth {
+                index,
+                ..WorkerHealth::default()
+            }),
+        }
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.health.lock().unwrap().is_healthy()
+    }
+
+    fn health(&self) -> WorkerHealth {
+        let mut health = self.health.lock().unwrap().clone();
+        health.queued = self.queued.load(Ordering::SeqCst);
+        health.running = self.daemon.try_lock().map_or(true, |daemon| daemon.is_some());
+        health
+    }
+
+    /// Return the channel to this worker's daemon, starting or restarting it if needed
+    async fn ensure_started(&self, pool: &ServoPool) -> Result<Arc<DaemonChannel>, StyleError> {
+        let mut daemon = self.daemon.lock().await;
+        if let Some(running) = daemon.as_mut() {
+            if running.is_alive() {
+                return Ok(running.channel.clone());
+            }
+        }
+
+        let restarting = {
+            let mut health = self.health.lock().unwrap();
+            let restarting = daemon.is_some() || health.consecutive_failures > 0;
+            if restarting {
+                health.restarts += 1;
+            }
+            restarting
+        };
+        if restarting {
+            warn!(worker = self.index, "Restarting Servo daemon worker");
+        }
+
+        *daemon = None;
+        let artifacts = pool.artifacts.as_ref()
+            .map(|artifacts| artifacts.batch(&format!("daemon-worker{}", self.index), &uuid::Uuid::new_v4().to_string()));
+        let (servo_path, servo_args) = (pool.servo_path.clone(), pool.servo_args.clone());
+        let start = async move { ServoDaemon::start(&servo_path, &servo_args, artifacts).await };
+        let started = DAEMON_RUNTIME.spawn(start.in_current_span()).await
+            .map_err(|e| StyleError::daemon(format!("Daemon start was interrupted: {}", e)))??;
+        let channel = started.channel.clone();
+        *daemon = Some(started);
+        Ok(channel)
+    }
+
+    /// Run one chunk of queries on this worker, waiting for it to become free
+    async fn run(&self, pool: &ServoPool, document: &str, media: &MediaEnvironment, queries: Vec<StyleQuery>, timeout: Duration) -> Result<Vec<StyleResponse>, StyleError> {
+        let batch = BatchQuery {
+            batch_id: uuid::Uuid::new_v4().to_string(),
+            document: document.to_string(),
+            media: media.clone(),
+            queries,
+        };
+        let span = debug_span!("servo_batch", batch_id = %batch.batch_id, worker = self.index, queries = batch.queries.len());
+        let artifacts = pool.artifacts.as_ref().map(|artifacts| artifacts.batch("batch", &batch.batch_id));
+        if let Some(ref artifacts) = artifacts {
+            artifacts.write("page.html", &batch.document);
+            artifacts.write("queries.json", serde_json::to_string_pretty(&batch.queries).unwrap_or_default());
+        }
+
+        let result = async {
+            let _slot = self.slot.acquire().await
+                .map_err(|_| StyleError::daemon("Worker shut down"))?;
+
+            let channel = self.ensure_started(pool).await?;
+
+            debug!("Sending batch to Servo daemon");
+            let mut result = channel.submit(batch, timeout).await;
+            if let Err(ref e) = result {
+                warn!(error = %e, "Servo daemon batch failed");
+                // Drop the daemon so the next batch gets a fresh Servo, reporting a crash
+                // rather than a timeout if it has died
+                let mut daemon = self.daemon.lock().await;
+                if let Some(crash) = daemon.as_mut().and_then(ServoDaemon::crash) {
+                    result = Err(crash);
+                }
+                *daemon = None;
+            }
+            if let Some(ref artifacts) = artifacts {
+                match result {
+                    Ok(ref responses) => {
+                        artifacts.write("status.txt", format!("worker {}: ok\n", self.index));
+                        artifacts.write("responses.json", serde_json::to_string_pretty(responses).unwrap_or_default());
+                    }
+                    Err(ref e) => artifacts.write("status.txt", format!("worker {}: {}\n", self.index, e)),
+                }
+            }
+            if let Ok(ref responses) = result {
+                responses.iter().for_each(backend::log_outcome);
+            }
+            result
+        }.instrument(span).await;
+
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+
+        let mut health = self.health.lock().unwrap();
+        match result {
+            Ok(responses) => {
+                health.batches_completed += 1;
+                health.consecutive_failures = 0;
+                Ok(responses)
+            }
+            Err(e) => {
+                health.consecutive_failures += 1;
+                health.last_error = Some(e.to_string());
+                Err(e)
+            }
+        }
+    }
+}
+
+/// A configurable pool of Servo daemon workers with concurrent batch dispatch
+pub struct ServoPool {
+    servo_path: String,
+    servo_args: Vec<String>,
+    workers: Vec<Worker>,
+    artifacts: Option<DebugArtifacts>,
+}
+
+impl ServoPool {
+    /// Create a pool of `workers` Servo daemons (at least one); processes start on first use
+    pub fn new(servo_path: impl Into<String>, workers: usize) -> Self {
+        ServoPool {
+            servo_path: servo_path.into(),
+            servo_args: Vec::new(),
+            workers: (0..workers.max(1)).map(Worker::new).collect(),
+            artifacts: None,
+        }
+    }
+
+    /// Create a pool using the Servo executable, arguments, worker count and debug
+    /// artifact directory of `config`
+    pub fn from_config(config: &EngineConfig) -> Self {
+        ServoPool {
+            artifacts: DebugArtifacts::for_config(config),
+            ..ServoPool::new(config.servo_command(), config.workers)
+                .with_servo_args(config.extra_servo_args.clone())
+        }
+    }
+
+    /// Pass extra arguments to every worker's Servo process
+    pub fn with_servo_args(mut self, servo_args: Vec<String>) -> Self {
+        self.servo_args = servo_args;
+        self
+    }
+
+    /// The pool shared by every engine using the same Servo executable, arguments, number
+    /// of workers and debug artifact directory
+    pub fn shared(config: &EngineConfig) -> Arc<ServoPool> {
+        let workers = config.workers.max(1).to_string();
+        let artifact_dir = config.debug_artifact_dir.as_ref().map(|dir| dir.to_string_lossy());
+        let key = [config.servo_command(), &workers].into_iter()
+            .chain(config.extra_servo_args.iter().map(String::as_str))
+            .chain(artifact_dir.as_deref())
+            .collect::<Vec<_>>()
+            .join("\0");
+        SHARED_POOLS.lock().unwrap()
+            .entry(key)
+            .or_insert_with(|| Arc::new(ServoPool::from_config(config)))
+            .clone()
+    }
+
+    /// Directory this pool writes debug artifacts to, if enabled
+    pub fn debug_artifact_dir(&self) -> Option<&Path> {
+        self.artifacts.as_ref().map(DebugArtifacts::run_dir)
+    }
+
+    /// Number of workers in the pool
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Health snapshot for every worker
+    pub fn health(&self) -> Vec<WorkerHealth> {
+        self.workers.iter().map(Worker::health).collect()
+    }
+
+    /// Start every worker's Servo process ahead of the first batch
+    pub async fn warm_up(&self) -> Result<(), StyleError> {
+        let started = futures::future::join_all(
+            self.workers.iter().map(|worker| worker.ensure_started(self))
+        ).await;
+        started.into_iter().try_for_each(|result| result.map(|_| ()))
+    }
+
+    /// Stop every worker's Servo process; they restart on next use
+    pub async fn shutdown(&self) {
+        for worker in &self.workers {
+            *worker.daemon.lock().await = None;
+        }
+    }
+
+    /// Evaluate `queries` against `document`, `batch_size` queries per worker batch
+    ///
+    /// `timeout` is the deadline of queries without their own `timeout_ms`. Responses are
+    /// returned in query order; chunks that fail on every attempt yield failed (or timed
+    /// out) responses for their queries rather than failing the whole call.
+    pub async fn run_batch(
+        &self,
+        document: &StyleDocument,
+        queries: Vec<StyleQuery>,
+        batch_size: usize,
+        timeout: Duration,
+    ) -> Result<Vec<StyleResponse>, StyleError> {
+        let page = document.to_html("");
+        let ids: Vec<String> = queries.iter().map(|query| query.id.clone()).collect();
+
+        let chunks: Vec<Vec<StyleQuery>> = queries
+            .chunks(batch_size.max(1))
+            .map(|chunk| chunk.to_vec())
+            .collect();
+        let chunk_count = chunks.len();
+
+        let results = futures::future::join_all(
+            chunks.into_iter().map(|chunk| self.run_chunk(&page, &document.media, chunk, timeout))
+        ).await;
+
+        // Correlate responses with the queries by id, in query order
+        let mut by_id: HashMap<String, StyleResponse> = HashMap::new();
+        let mut failed_chunks = 0;
+        let mut last_error = None;
+        for result in results {
+            match result {
+                Ok(responses) => by_id.extend(responses.into_iter().map(|response| (response.id.clone(), response))),
+                Err((chunk_ids, e)) => {
+                    failed_chunks += 1;
+                    by_id.extend(chunk_ids.iter().map(|id| (id.clone(), StyleResponse::failure(id, e.clone()))));
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        // Fail the call as a whole when no chunk got through, unless queries merely ran out of time
+        if let Some(e) = last_error {
+            if failed_chunks == chunk_count && !matches!(e, StyleError::Timeout { .. }) {
+                return Err(e);
+            }
+        }
+
+        Ok(ids.into_iter().map(|id| {
+            by_id.remove(&id).unwrap_or_else(|| {
+                StyleResponse::failure(&id, StyleError::protocol("No result found in daemon response"))
+            })
+        }).collect())
+    }
+
+    /// Run one chunk, retrying on another worker unless it timed out
+    ///
+    /// On failure the chunk's query ids are returned with the error.
+    async fn run_chunk(&self, page: &str, media: &MediaEnvironment, chunk: Vec<StyleQuery>, timeout: Duration) -> Result<Vec<StyleResponse>, (Vec<String>, StyleError)> {
+        // Leave the page time to report the queries its watchdog stopped
+        let deadline = backend::batch_deadline(&chunk, timeout) + WATCHDOG_GRACE;
+        let chunk = backend::with_default_timeout(chunk, timeout);
+
+        let mut last_error = None;
+        for _ in 0..MAX_ATTEMPTS {
+            let worker = self.pick_worker();
+            match worker.run(self, page, media, chunk.clone(), deadline).await {
+                Ok(responses) => return Ok(responses),
+                Err(e @ StyleError::Timeout { .. }) => {
+                    last_error = Some(e);
+                    break;
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        let ids = chunk.into_iter().map(|query| query.id).collect();
+        Err((ids, last_error.unwrap_or_else(|| StyleError::daemon("No workers available"))))
+    }
+
+    /// Reserve the least-loaded worker, preferring healthy ones
+    fn pick_worker(&self) -> &Worker {
+        let worker = self.workers.iter()
+            .min_by_key(|worker| (!worker.is_healthy(), worker.queued.load(Ordering::SeqCst)))
+            .expect("pool has at least one worker");
+        worker.queued.fetch_add(1, Ordering::SeqCst);
+        worker
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_pools_are_keyed_by_worker_count() {
+        let config = EngineConfig::default().with_servo_path("/nonexistent/shared-pool-servo");
+        let one = ServoPool::shared(&config);
+        let three = ServoPool::shared(&config.clone().with_workers(3));
+        assert_eq!((one.size(), three.size()), (1, 3));
+        assert!(Arc::ptr_eq(&one, &ServoPool::shared(&config.clone().with_workers(0))));
+        assert!(Arc::ptr_eq(&three, &ServoPool::shared(&config.with_workers(3))));
+    }
+}
@@ -0,0 +1,204 @@
+//! Running a headless Servo instance on a single page under a deadline.
+//!
+//! Console output is collected as it is produced, so when Servo overruns its deadline
+//! and is killed, the lines it printed so far can still be parsed for results.
+
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+use tokio::task::JoinHandle;
+use tracing::{debug, trace, warn, Instrument};
+
+use crate::artifacts::ArtifactSet;
+use crate::config::EngineConfig;
+use crate::error::StyleError;
+
+/// How long to keep reading output after Servo exits, in case a helper process holds the pipe
+const OUTPUT_DRAIN_GRACE: Duration = Duration::from_millis(500);
+
+/// How many trailing stderr lines a crash error carries
+pub(crate) const STDERR_TAIL_LINES: usize = 20;
+
+/// Console output and outcome of one Servo run
+pub(crate) struct ServoRun {
+    pub(crate) stdout: String,
+    pub(crate) stderr: String,
+    /// Exit status, or `None` if Servo was killed at the deadline
+    pub(crate) status: Option<ExitStatus>,
+    pub(crate) elapsed: Duration,
+}
+
+impl ServoRun {
+    pub(crate) fn timed_out(&self) -> bool {
+        self.status.is_none()
+    }
+
+    /// Why queries this run did not answer are missing: Servo was stopped at the
+    /// deadline or exited unsuccessfully
+    pub(crate) fn failure(&self) -> Option<StyleError> {
+        match self.status {
+            None => Some(StyleError::Timeout { waited: self.elapsed }),
+            Some(status) if !status.success() => Some(StyleError::ServoCrashed {
+                status: status.to_string(),
+                exit_code: status.code(),
+                stderr_tail: stderr_tail(self.stderr.lines()),
+            }),
+            Some(_) => None,
+        }
+    }
+}
+
+/// The last few lines of Servo's stderr, for crash reports
+pub(crate) fn stderr_tail<'a>(lines: impl DoubleEndedIterator<Item = &'a str>) -> String {
+    let mut tail: Vec<&str> = lines.rev().filter(|line| !line.trim().is_empty()).take(STDERR_TAIL_LINES).collect();
+    tail.reverse();
+    tail.join("\n")
+}
+
+/// Run `servo --headless <url>` with the configured executable and arguments
+///
+/// Servo is killed once `deadline` passes; whatever it printed until then is returned.
+/// The command line, exit status and console output are recorded in `artifacts`.
+pub(crate) async fn run_servo_page(
+    config: &EngineConfig,
+    url: &str,
+    deadline: Duration,
+    artifacts: Option<&ArtifactSet>,
+) -> Result<ServoRun, StyleError> {
+    let started = Instant::now();
+    debug!(
+        servo = config.servo_command(),
+        args = ?config.extra_servo_args,
+        url,
+        deadline_ms = deadline.as_millis() as u64,
+        "Spawning Servo"
+    );
+    if let Some(artifacts) = artifacts {
+        artifacts.write("command.txt", command_line(config.servo_command(), &config.extra_servo_args, url) + "\n");
+    }
+
+    let spawned = Command::new(config.servo_command())
+        .args(&config.extra_servo_args)
+        .arg("--headless")
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            if let Some(artifacts) = artifacts {
+                artifacts.write("status.txt", format!("failed to start: {}\n", e));
+            }
+            return Err(StyleError::SpawnFailed {
+                command: command_line(config.servo_command(), &config.extra_servo_args, url),
+                message: e.to_string(),
+            });
+        }
+    };
+
+    let stdout = child.stdout.take().map(|output| collect_output(output, "stdout"));
+    let stderr = child.stderr.take().map(|output| collect_output(output, "stderr"));
+
+    let status = match tokio::time::timeout(deadline, child.wait()).await {
+        Ok(status) => Some(status?),
+        Err(_) => {
+            warn!(deadline_ms = deadline.as_millis() as u64, "Servo overran its deadline, killing it");
+            let _ = child.kill().await;
+            None
+        }
+    };
+    debug!(status = ?status, elapsed_ms = started.elapsed().as_millis() as u64, "Servo finished");
+
+    let run = ServoRun {
+        stdout: join_output(stdout).await,
+        stderr: join_output(stderr).await,
+        status,
+        elapsed: started.elapsed(),
+    };
+
+    if let Some(artifacts) = artifacts {
+        let status = match run.status {
+            Some(status) => format!("{}\nelapsed: {} ms\n", status, run.elapsed.as_millis()),
+            None => format!("killed after {} ms deadline\nelapsed: {} ms\n", deadline.as_millis(), run.elapsed.as_millis()),
+        };
+        artifacts.write("status.txt", status);
+        artifacts.write("stdout.log", &run.stdout);
+        artifacts.write("stderr.log", &run.stderr);
+    }
+
+    Ok(run)
+}
+
+/// Shell-style rendering of the Servo command line, for artifacts and diagnostics
+pub(crate) fn command_line(servo: &str, args: &[String], url: &str) -> String {
+    std::iter::once(servo)
+        .chain(args.iter().map(String::as_str))
+        .chain(["--headless", url])
+        .map(|arg| {
+            if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '\'' || c == '"') {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            } else {
+                arg.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Output read so far from one of Servo's pipes
+struct OutputCollector {
+    buffer: Arc<Mutex<Vec<u8>>>,
+    reader: JoinHandle<()>,
+}
+
+/// Read a pipe to its end in the background, emitting each line as a tracing event
+fn collect_output<R: AsyncRead + Unpin + Send + 'static>(mut output: R, stream: &'static str) -> OutputCollector {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let sink = buffer.clone();
+    let reader = tokio::spawn(async move {
+        let mut chunk = [0u8; 8192];
+        let mut line = Vec::new();
+        while let Ok(read) = output.read(&mut chunk).await {
+            if read == 0 {
+                break;
+            }
+            sink.lock().unwrap().extend_from_slice(&chunk[..read]);
+            for &byte in &chunk[..read] {
+                if byte == b'\n' {
+                    log_line(stream, &line);
+                    line.clear();
+                } else {
+                    line.push(byte);
+                }
+            }
+        }
+        if !line.is_empty() {
+            log_line(stream, &line);
+        }
+    }.in_current_span());
+    OutputCollector { buffer, reader }
+}
+
+fn log_line(stream: &'static str, line: &[u8]) {
+    let line = String::from_utf8_lossy(line);
+    match stream {
+        "stderr" => debug!(stream, line = %line.trim_end(), "Servo output"),
+        _ => trace!(stream, line = %line.trim_end(), "Servo output"),
+    }
+}
+
+async fn join_output(collector: Option<OutputCollector>) -> String {
+    let Some(mut collector) = collector else {
+        return String::new();
+    };
+    if tokio::time::timeout(OUTPUT_DRAIN_GRACE, &mut collector.reader).await.is_err() {
+        collector.reader.abort();
+    }
+    let buffer = collector.buffer.lock().unwrap();
+    String::from_utf8_lossy(&buffer).into_owned()
+}
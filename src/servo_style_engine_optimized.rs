@@ -1,142 +1,43 @@
 use std::collections::HashMap;
 use std::io::Write;
-use std::sync::{Arc, Mutex};
-use std::process::Stdio;
-use serde::{Deserialize, Serialize};
-use thiserror::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
 use tempfile::NamedTempFile;
-use tokio::sync::OnceCell;
-use tokio::process::{Child, Command};
-
-#[derive(Error, Debug)]
-pub enum ServoStyleError {
-    #[error("Servo executable not found")]
-    ServoNotFound,
-    #[error("Failed to start Servo process: {0}")]
-    ProcessStartError(#[from] std::io::Error),
-    #[error("Servo process communication error: {0}")]
-    CommunicationError(String),
-    #[error("JSON serialization error: {0}")]
-    SerializationError(#[from] serde_json::Error),
-    #[error("Style computation failed: {0}")]
-    ComputationError(String),
-    #[error("Servo daemon not available: {0}")]
-    DaemonError(String),
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct StyleQuery {
-    id: String,
-    html: String,
-    css: String,
-    selector: String,
-    property: Option<String>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct BatchQuery {
-    queries: Vec<StyleQuery>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct StyleResponse {
-    id: String,
-    success: bool,
-    computed_value: Option<String>,
-    computed_styles: Option<HashMap<String, String>>,
-    error: Option<String>,
-}
-
-/// Servo daemon process manager
-#[allow(dead_code)]
-struct ServoDaemon {
-    process: Child,
-    servo_path: String,
-}
-
-impl ServoDaemon {
-    async fn start(servo_path: String) -> Result<Self, ServoStyleError> {
-        println!("🚀 Starting Servo daemon for persistent style computation...");
-        
-        // Start Servo in daemon-like mode with a simple server page
-        let daemon_html = r#"
-<!DOCTYPE html>
-<html>
-<head>
-    <title>Servo Style Daemon</title>
-    <style>
-        body { font-family: monospace; padding: 20px; }
-        .status { color: green; }
-    </style>
-</head>
-<body>
-    <h1 class="status">Servo Style Computation Daemon</h1>
-    <p>Ready for style queries...</p>
-    <script>
-        console.log('SERVO_DAEMON_READY');
-        // Keep alive with periodic heartbeat
-        setInterval(() => {
-            console.log('SERVO_DAEMON_HEARTBEAT:' + Date.now());
-        }, 5000);
-    </script>
-</body>
-</html>"#;
-
-        // Create daemon HTML file
-        let daemon_file = "/tmp/servo_daemon.html";
-        std::fs::write(daemon_file, daemon_html)?;
-
-        let process = Command::new(&servo_path)
-            .arg("--headless")
-            .arg(format!("file://{}", daemon_file))
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
-
-        println!("✅ Servo daemon started");
-
-        Ok(ServoDaemon {
-            process,
-            servo_path,
-        })
-    }
-
-    #[allow(dead_code)]
-    async fn is_alive(&mut self) -> bool {
-        match self.process.try_wait() {
-            Ok(Some(_)) => false, // Process has exited
-            Ok(None) => true,     // Process is still running
-            Err(_) => false,      // Error checking process
-        }
-    }
-
-    #[allow(dead_code)]
-    async fn restart(&mut self) -> Result<(), ServoStyleError> {
-        println!("🔄 Restarting Servo daemon...");
-        let _ = self.process.kill().await;
-        let new_daemon = ServoDaemon::start(self.servo_path.clone()).await?;
-        self.process = new_daemon.process;
-        Ok(())
-    }
-}
-
-/// Global daemon instance
-static SERVO_DAEMON: OnceCell<Arc<Mutex<ServoDaemon>>> = OnceCell::const_new();
+use tracing::{debug, debug_span, info, warn, Instrument};
+
+use crate::artifacts::DebugArtifacts;
+use crate::assertions::{StyleAssertion, StyleReport};
+use crate::backend::{
+    self, BackendKind, ComputedStyles, QueryOptions, StyleBackend, StyleDocument, StyleEngine, StyleQuery, StyleRequest,
+    StyleResponse, StyleResult,
+};
+use crate::config::EngineConfig;
+use crate::error::StyleError;
+use crate::media::MediaEnvironment;
+use crate::page_script::{self, ConsoleRecord, RecordKind};
+use crate::servo_pool::ServoPool;
+use crate::servo_process::run_servo_page;
 
 /// Optimized Servo-based CSS style engine with daemon mode and batch processing
+///
+/// The engine is a `StyleBackend`: wrap it in `StyleEngine::with_backend` to set the
+/// document and query it.
 pub struct ServoStyleEngineOptimized {
-    base_html: String,
-    stylesheets: Vec<String>,
-    servo_path: Option<String>,
-    use_daemon: bool,
-    #[allow(dead_code)]
-    batch_size: usize,
+    /// Document of the deprecated front-end methods
+    legacy: StyleDocument,
+    config: EngineConfig,
+    pool: Option<Arc<ServoPool>>,
+    /// Whether batches for other device settings go to the shared pool for those settings
+    /// rather than to `pool`
+    shared_pools: bool,
+    artifacts: Option<DebugArtifacts>,
 }
 
 impl ServoStyleEngineOptimized {
     /// Create a new optimized ServoStyleEngine instance
-    pub fn new() -> Result<Self, ServoStyleError> {
+    pub fn new() -> Result<Self, StyleError> {
         Self::with_options(None, true, 5)
     }
 
@@ -145,398 +46,358 @@ impl ServoStyleEngineOptimized {
         servo_path: Option<String>, 
         use_daemon: bool, 
         batch_size: usize
-    ) -> Result<Self, ServoStyleError> {
-        // Check if Servo is available
-        if let Some(ref path) = servo_path {
-            if !std::path::Path::new(path).exists() {
-                return Err(ServoStyleError::ServoNotFound);
-            }
-        } else if which::which("servo").is_err() {
-            return Err(ServoStyleError::ServoNotFound);
-        }
-        
-        println!("✅ Servo found - enabling optimized Stylo integration");
-        if use_daemon {
-            println!("   🔧 Daemon mode enabled for persistent computation");
-        }
-        println!("   📦 Batch size: {} queries", batch_size);
-        
-        if let Some(ref path) = servo_path {
-            println!("   Using custom Servo path: {}", path);
-        } else {
-            println!("   Using Servo from PATH");
-        }
-
-        Ok(ServoStyleEngineOptimized {
-            base_html: String::new(),
-            stylesheets: Vec::new(),
+    ) -> Result<Self, StyleError> {
+        let mode = if use_daemon { BackendKind::Daemon } else { BackendKind::Batch };
+        Self::with_config(&EngineConfig {
             servo_path,
-            use_daemon,
+            mode,
             batch_size,
+            ..EngineConfig::default()
         })
     }
 
-    /// Add a CSS stylesheet to the style engine
-    pub fn add_stylesheet(&mut self, css: &str) -> Result<(), ServoStyleError> {
-        self.stylesheets.push(css.to_string());
-        Ok(())
-    }
+    /// Create from an engine configuration
+    ///
+    /// Daemon mode is used when `config.mode` is `BackendKind::Daemon`, otherwise
+    /// each batch gets its own Servo launch.
+    pub fn with_config(config: &EngineConfig) -> Result<Self, StyleError> {
+        // Check if Servo is available
+        config.check_servo()?;
+        
+        let use_daemon = config.mode == BackendKind::Daemon;
+        info!(
+            servo = config.servo_command(),
+            daemon = use_daemon,
+            workers = config.workers,
+            batch_size = config.batch_size,
+            "Servo found - enabling optimized Stylo integration"
+        );
+
+        // Daemon engines share one pool per Servo executable and device settings; the pool
+        // keeps its own artifacts
+        let pool = use_daemon.then(|| ServoPool::shared(&MediaEnvironment::default().daemon_config(config)));
+        let artifacts = if use_daemon { None } else { DebugArtifacts::for_config(config) };
 
-    /// Set the HTML content for style computation
-    pub fn set_html(&mut self, html: &str) -> Result<(), ServoStyleError> {
-        self.base_html = html.to_string();
-        Ok(())
+        Ok(ServoStyleEngineOptimized {
+            legacy: StyleDocument::default(),
+            config: config.clone(),
+            pool,
+            shared_pools: use_daemon,
+            artifacts,
+        })
     }
 
-    /// Initialize daemon if needed
-    async fn ensure_daemon(&self) -> Result<(), ServoStyleError> {
-        if !self.use_daemon {
-            return Ok(());
-        }
+    /// Create a daemon-mode engine that dispatches batches to `pool`
+    ///
+    /// Queries are split into batches of `batch_size` and spread across the pool's workers.
+    /// The pool's Servo arguments decide the device pixel ratio and default font size;
+    /// the media environment's viewport and media features still apply.
+    pub fn with_pool(pool: Arc<ServoPool>, batch_size: usize) -> Result<Self, StyleError> {
+        info!(workers = pool.size(), batch_size, "Using Servo daemon pool");
 
-        let servo_path = self.servo_path.as_deref().unwrap_or("servo").to_string();
-        
-        SERVO_DAEMON.get_or_try_init(|| async {
-            let daemon = ServoDaemon::start(servo_path).await?;
-            Ok(Arc::new(Mutex::new(daemon)))
-        }).await.map_err(|e: ServoStyleError| e)?;
+        Ok(ServoStyleEngineOptimized {
+            legacy: StyleDocument::default(),
+            config: EngineConfig::default().with_batch_size(batch_size),
+            pool: Some(pool),
+            shared_pools: false,
+            artifacts: None,
+        })
+    }
 
-        Ok(())
+    /// The daemon pool this engine dispatches to, if running in daemon mode
+    pub fn pool(&self) -> Option<&Arc<ServoPool>> {
+        self.pool.as_ref()
     }
 
-    /// Create an HTML file with embedded JavaScript for batch queries
-    fn create_batch_html(&self, queries: &[StyleQuery]) -> String {
-        let combined_css = self.stylesheets.join("\n");
-        
-        // Generate JavaScript for all queries
-        let mut js_queries = String::new();
-        for query in queries {
-            let query_js = if let Some(ref prop) = query.property {
-                format!(r#"
-                    try {{
-                        var element = document.querySelector('{}');
-                        if (element) {{
-                            var computedStyle = window.getComputedStyle(element);
-                            var value = computedStyle.getPropertyValue('{}');
-                            console.log('COMPUTED_STYLE_RESULT:{}:' + JSON.stringify({{
-                                id: '{}',
-                                selector: '{}',
-                                property: '{}',
-                                value: value
-                            }}));
-                        }} else {{
-                            console.log('COMPUTED_STYLE_ERROR:{}:Element not found');
-                        }}
-                    }} catch (e) {{
-                        console.log('COMPUTED_STYLE_ERROR:{}:' + e.message);
-                    }}
-                "#, query.selector, prop, query.id, query.id, query.selector, prop, query.id, query.id)
-            } else {
-                format!(r#"
-                    try {{
-                        var element = document.querySelector('{}');
-                        if (element) {{
-                            var computedStyle = window.getComputedStyle(element);
-                            var styles = {{}};
-                            for (var i = 0; i < computedStyle.length; i++) {{
-                                var propName = computedStyle[i];
-                                styles[propName] = computedStyle.getPropertyValue(propName);
-                            }}
-                            console.log('COMPUTED_STYLES_RESULT:{}:' + JSON.stringify({{
-                                id: '{}',
-                                selector: '{}',
-                                styles: styles
-                            }}));
-                        }} else {{
-                            console.log('COMPUTED_STYLE_ERROR:{}:Element not found');
-                        }}
-                    }} catch (e) {{
-                        console.log('COMPUTED_STYLE_ERROR:{}:' + e.message);
-                    }}
-                "#, query.selector, query.id, query.id, query.selector, query.id, query.id)
-            };
-            js_queries.push_str(&query_js);
+    /// Directory this engine (or its daemon pool) writes debug artifacts to, if enabled
+    pub fn debug_artifact_dir(&self) -> Option<&Path> {
+        match self.pool {
+            Some(ref pool) => pool.debug_artifact_dir(),
+            None => self.artifacts.as_ref().map(DebugArtifacts::run_dir),
         }
-
-        let script = format!(r#"
-            window.addEventListener('load', function() {{
-                console.log('BATCH_START:{}');
-                {}
-                console.log('BATCH_END:{}');
-                setTimeout(function() {{ window.close(); }}, 200);
-            }});
-        "#, queries.len(), js_queries, queries.len());
-
-        format!(r#"<!DOCTYPE html>
-<html>
-<head>
-    <style>
-        {}
-    </style>
-</head>
-<body>
-    {}
-    <script>
-        {}
-    </script>
-</body>
-</html>"#, combined_css, self.base_html, script)
     }
 
     /// Process queries in batch using optimized Servo
-    async fn process_batch(&self, queries: Vec<StyleQuery>) -> Result<Vec<StyleResponse>, ServoStyleError> {
-        if self.use_daemon {
-            self.process_batch_with_daemon(queries).await
-        } else {
-            self.process_batch_standalone(queries).await
+    async fn process_batch(&self, document: &StyleDocument, queries: Vec<StyleQuery>) -> Result<Vec<StyleResponse>, StyleError> {
+        let queries = backend::with_default_timeout(queries, self.config.timeout);
+        match self.pool_for(&document.media) {
+            Some(pool) => pool.run_batch(document, queries, self.config.batch_size, self.config.timeout).await,
+            None => {
+                let batch_id = uuid::Uuid::new_v4().to_string();
+                let span = debug_span!("servo_batch", batch_id = %batch_id, queries = queries.len());
+                self.process_batch_standalone(&batch_id, document, queries).instrument(span).await
+            }
         }
     }
 
-    /// Process batch with daemon (reuses Servo instance)
-    async fn process_batch_with_daemon(&self, queries: Vec<StyleQuery>) -> Result<Vec<StyleResponse>, ServoStyleError> {
-        self.ensure_daemon().await?;
-        
-        // For now, fall back to standalone mode as daemon communication needs more work
-        // TODO: Implement proper daemon communication protocol
-        println!("🔄 Daemon mode: falling back to optimized standalone for now");
-        self.process_batch_standalone(queries).await
+    /// The daemon pool batches for `media` are dispatched to, if running in daemon mode
+    fn pool_for(&self, media: &MediaEnvironment) -> Option<Arc<ServoPool>> {
+        if self.shared_pools {
+            Some(ServoPool::shared(&media.daemon_config(&self.config)))
+        } else {
+            self.pool.clone()
+        }
     }
 
     /// Process batch with standalone Servo (one instance per batch)
-    async fn process_batch_standalone(&self, queries: Vec<StyleQuery>) -> Result<Vec<StyleResponse>, ServoStyleError> {
-        let html_content = self.create_batch_html(&queries);
-        let servo_cmd = self.servo_path.as_deref().unwrap_or("servo");
+    async fn process_batch_standalone(&self, batch_id: &str, document: &StyleDocument, queries: Vec<StyleQuery>) -> Result<Vec<StyleResponse>, StyleError> {
+        let nonce = page_script::new_nonce();
+        let html_content = create_batch_html(document, &queries, &nonce);
+        let deadline = backend::batch_deadline(&queries, self.config.timeout);
         
         // Create temp file
         let mut temp_file = NamedTempFile::new()
-            .map_err(|e| ServoStyleError::CommunicationError(format!("Failed to create temp file: {}", e)))?;
+            .map_err(|e| StyleError::Io { message: format!("Failed to create temp file: {}", e) })?;
         temp_file.write_all(html_content.as_bytes())
-            .map_err(|e| ServoStyleError::CommunicationError(format!("Failed to write temp file: {}", e)))?;
+            .map_err(|e| StyleError::Io { message: format!("Failed to write temp file: {}", e) })?;
         let temp_path = temp_file.path();
 
-        // Save debug file
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        let debug_path = format!("/tmp/debug_servo_batch_{}.html", timestamp);
-        std::fs::write(&debug_path, &html_content)?;
+        // Save debug artifacts if enabled
+        let artifacts = self.artifacts.as_ref().map(|artifacts| artifacts.batch("batch", batch_id));
+        if let Some(ref artifacts) = artifacts {
+            artifacts.write("page.html", &html_content);
+        }
         
-        println!("🚀 Processing batch of {} queries...", queries.len());
-        println!("   Debug file: {}", debug_path);
+        debug!(queries = queries.len(), "Processing batch");
 
         // Run Servo with timeout
-        let output = tokio::time::timeout(
-            std::time::Duration::from_secs(10),
-            Command::new(servo_cmd)
-                .arg("--headless")
-                .arg(format!("file://{}", temp_path.display()))
-                .output()
-        ).await;
-
-        match output {
-            Ok(Ok(process_output)) => {
-                let stdout = String::from_utf8_lossy(&process_output.stdout);
-                let stderr = String::from_utf8_lossy(&process_output.stderr);
-                
-                // Save batch results
-                let result_file = format!("/tmp/servo_batch_results_{}.txt", timestamp);
-                let content = format!("BATCH RESULTS\n=============\n{} queries processed\n\nSTDOUT:\n{}\n\nSTDERR:\n{}\n", 
-                    queries.len(), stdout, stderr);
-                std::fs::write(&result_file, content)?;
-                println!("   📄 Batch results saved to: {}", result_file);
-                
-                self.parse_batch_output(&stdout, &stderr, &queries)
-            },
-            Ok(Err(e)) => Err(ServoStyleError::CommunicationError(format!("Failed to start Servo: {}", e))),
-            Err(_) => Err(ServoStyleError::CommunicationError("Servo batch timed out".to_string())),
+        let config = document.media.page_config(&self.config);
+        let run = run_servo_page(&config, &format!("file://{}", temp_path.display()), deadline, artifacts.as_ref()).await?;
+        if run.timed_out() {
+            warn!(elapsed_ms = run.elapsed.as_millis() as u64, "Servo batch timed out, keeping results printed so far");
         }
+        
+        parse_batch_output(&run.stdout, &run.stderr, &nonce, &queries, run.failure())
     }
 
-    /// Parse batch output and match results to queries
-    fn parse_batch_output(&self, stdout: &str, stderr: &str, queries: &[StyleQuery]) -> Result<Vec<StyleResponse>, ServoStyleError> {
-        let mut responses = Vec::new();
-        let mut processed_ids = std::collections::HashSet::new();
-
-        for line in stdout.lines().chain(stderr.lines()) {
-            // Parse single property results
-            if line.contains("COMPUTED_STYLE_RESULT:") {
-                if let Some(parts) = line.split("COMPUTED_STYLE_RESULT:").nth(1) {
-                    if let Some((id, json_part)) = parts.split_once(':') {
-                        if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(json_part) {
-                            if let Some(value) = parsed["value"].as_str() {
-                                responses.push(StyleResponse {
-                                    id: id.to_string(),
-                                    success: true,
-                                    computed_value: Some(value.to_string()),
-                                    computed_styles: None,
-                                    error: None,
-                                });
-                                processed_ids.insert(id.to_string());
-                                println!("   ✅ Batch result for {}: {}", id, value);
-                            }
-                        }
-                    }
-                }
-            }
-            
-            // Parse all styles results
-            if line.contains("COMPUTED_STYLES_RESULT:") {
-                if let Some(parts) = line.split("COMPUTED_STYLES_RESULT:").nth(1) {
-                    if let Some((id, json_part)) = parts.split_once(':') {
-                        if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(json_part) {
-                            if let Some(styles) = parsed["styles"].as_object() {
-                                let styles_map: HashMap<String, String> = styles.iter()
-                                    .map(|(k, v)| (k.clone(), v.as_str().unwrap_or("").to_string()))
-                                    .collect();
-                                responses.push(StyleResponse {
-                                    id: id.to_string(),
-                                    success: true,
-                                    computed_value: None,
-                                    computed_styles: Some(styles_map),
-                                    error: None,
-                                });
-                                processed_ids.insert(id.to_string());
-                                println!("   ✅ Batch styles for {}: {} properties", id, styles.len());
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        // Add error responses for unprocessed queries
-        for query in queries {
-            if !processed_ids.contains(&query.id) {
-                responses.push(StyleResponse {
-                    id: query.id.clone(),
-                    success: false,
-                    computed_value: None,
-                    computed_styles: None,
-                    error: Some("No result found in batch output".to_string()),
-                });
-            }
-        }
+    /// Add a CSS stylesheet to the style engine
+    #[deprecated(note = "wrap the engine in `StyleEngine::with_backend` and use `StyleEngine::add_stylesheet`")]
+    pub fn add_stylesheet(&mut self, css: &str) -> Result<(), StyleError> {
+        self.legacy.stylesheets.push(css.to_string());
+        Ok(())
+    }
 
-        Ok(responses)
+    /// Set the HTML content for style computation
+    #[deprecated(note = "wrap the engine in `StyleEngine::with_backend` and use `StyleEngine::set_html`")]
+    pub fn set_html(&mut self, html: &str) -> Result<(), StyleError> {
+        self.legacy.html = html.to_string();
+        Ok(())
     }
 
     /// Get computed style for a specific CSS property (optimized)
-    pub async fn get_computed_style(&mut self, selector: &str, property: &str) -> Result<String, ServoStyleError> {
-        let query = StyleQuery {
-            id: uuid::Uuid::new_v4().to_string(),
-            html: self.base_html.clone(),
-            css: self.stylesheets.join("\n"),
-            selector: selector.to_string(),
-            property: Some(property.to_string()),
-        };
-
-        let responses = self.process_batch(vec![query]).await?;
-        
-        if let Some(response) = responses.into_iter().next() {
-            if response.success {
-                response.computed_value.ok_or_else(|| {
-                    ServoStyleError::ComputationError("No computed value returned".to_string())
-                })
-            } else {
-                Err(ServoStyleError::ComputationError(
-                    response.error.unwrap_or_else(|| "Unknown error".to_string())
-                ))
-            }
-        } else {
-            Err(ServoStyleError::ComputationError("No response received".to_string()))
-        }
+    #[deprecated(note = "wrap the engine in `StyleEngine::with_backend` and use `StyleEngine::get_computed_style`")]
+    pub async fn get_computed_style(&self, selector: &str, property: &str) -> Result<String, StyleError> {
+        backend::compute_value(self, &self.legacy, selector, property, &QueryOptions::default()).await
     }
 
     /// Get all computed styles for an element (optimized)
-    pub async fn get_all_computed_styles(&mut self, selector: &str) -> Result<HashMap<String, String>, ServoStyleError> {
-        let query = StyleQuery {
-            id: uuid::Uuid::new_v4().to_string(),
-            html: self.base_html.clone(),
-            css: self.stylesheets.join("\n"),
-            selector: selector.to_string(),
-            property: None,
-        };
+    #[deprecated(note = "wrap the engine in `StyleEngine::with_backend` and use `StyleEngine::get_all_computed_styles`")]
+    pub async fn get_all_computed_styles(&self, selector: &str) -> Result<ComputedStyles, StyleError> {
+        backend::compute_all(self, &self.legacy, selector, &QueryOptions::default()).await
+    }
 
-        let responses = self.process_batch(vec![query]).await?;
-        
-        if let Some(response) = responses.into_iter().next() {
-            if response.success {
-                response.computed_styles.ok_or_else(|| {
-                    ServoStyleError::ComputationError("No computed styles returned".to_string())
-                })
-            } else {
-                Err(ServoStyleError::ComputationError(
-                    response.error.unwrap_or_else(|| "Unknown error".to_string())
-                ))
-            }
+    /// Process multiple style queries efficiently in batch
+    #[deprecated(note = "wrap the engine in `StyleEngine::with_backend` and use `StyleEngine::compute_styles_batch`")]
+    pub async fn compute_styles_batch(&self, requests: Vec<StyleRequest>) -> Result<Vec<StyleResult>, StyleError> {
+        backend::compute_batch(self, &self.legacy, requests, &QueryOptions::default()).await
+    }
+}
+
+#[async_trait]
+impl StyleBackend for ServoStyleEngineOptimized {
+    fn name(&self) -> &str {
+        if self.pool.is_some() {
+            "servo-daemon"
         } else {
-            Err(ServoStyleError::ComputationError("No response received".to_string()))
+            "servo-batch"
         }
     }
 
-    /// Process multiple style queries efficiently in batch
-    pub async fn compute_styles_batch(&mut self, requests: Vec<(String, Option<String>)>) -> Result<Vec<(String, Result<String, ServoStyleError>)>, ServoStyleError> {
-        let mut queries = Vec::new();
-        
-        // Clone requests for later use
-        let requests_clone = requests.clone();
-        
-        for (selector, property) in requests {
-            queries.push(StyleQuery {
-                id: uuid::Uuid::new_v4().to_string(),
-                html: self.base_html.clone(),
-                css: self.stylesheets.join("\n"),
-                selector: selector.clone(),
-                property: property.clone(),
-            });
-        }
+    async fn compute_batch(&self, document: &StyleDocument, queries: Vec<StyleQuery>) -> Result<Vec<StyleResponse>, StyleError> {
+        self.process_batch(document, queries).await
+    }
+}
 
-        let responses = self.process_batch(queries).await?;
-        
-        let mut results = Vec::new();
-        for (i, response) in responses.into_iter().enumerate() {
-            let selector = if i < requests_clone.len() { 
-                requests_clone[i].0.clone() 
-            } else { 
-                format!("query_{}", i) 
-            };
-            
-            let result = if response.success {
-                if let Some(value) = response.computed_value {
-                    Ok(value)
-                } else if let Some(styles) = response.computed_styles {
-                    Ok(serde_json::to_string(&styles).unwrap_or_default())
-                } else {
-                    Err(ServoStyleError::ComputationError("No result data".to_string()))
-                }
-            } else {
-                Err(ServoStyleError::ComputationError(
-                    response.error.unwrap_or_else(|| "Unknown error".to_string())
-                ))
-            };
-            
-            results.push((selector, result));
-        }
+/// Create an HTML file that evaluates a batch of queries
+///
+/// The queries are embedded as a JSON payload rather than spliced into the script, so
+/// selectors and properties may contain quotes, backslashes or markup.
+pub(crate) fn create_batch_html(document: &StyleDocument, queries: &[StyleQuery], nonce: &str) -> String {
+    page_script::extraction_page(document, queries, true, nonce)
+}
 
-        Ok(results)
+/// Parse batch output and match results to queries
+///
+/// Only records carrying the batch's `nonce` are considered, and the first record for
+/// each query wins. `unanswered` is why Servo stopped early (timeout or crash), if it
+/// did; queries without a result report that error rather than a missing result.
+pub(crate) fn parse_batch_output(
+    stdout: &str,
+    stderr: &str,
+    nonce: &str,
+    queries: &[StyleQuery],
+    unanswered: Option<StyleError>,
+) -> Result<Vec<StyleResponse>, StyleError> {
+    let mut by_id: HashMap<String, StyleResponse> = HashMap::new();
+
+    let records = page_script::read_records(stdout, nonce).into_iter()
+        .chain(page_script::read_records(stderr, nonce));
+    for ConsoleRecord { kind, body } in records {
+        let Some(id) = body.id else { continue };
+        if by_id.contains_key(&id) || !queries.iter().any(|query| query.id == id) {
+            continue;
+        }
+        let response = match kind {
+            RecordKind::Value | RecordKind::Styles => StyleResponse {
+                id: id.clone(),
+                success: true,
+                computed_value: body.value,
+                computed_styles: body.styles,
+                matches: body.matches,
+                error: None,
+            },
+            // Queries stopped by the page's watchdog
+            RecordKind::Timeout => StyleResponse::timeout(&id, Duration::from_millis(body.elapsed_ms.unwrap_or_default())),
+            // Per-query errors reported by the page
+            RecordKind::Error => StyleResponse::failure(&id, body.error.unwrap_or_else(|| StyleError::protocol("Error record without an error"))),
+        };
+        by_id.insert(id, response);
     }
+
+    // Fill in responses for queries without a result, in query order
+    let responses: Vec<StyleResponse> = queries.iter().map(|query| {
+        by_id.remove(&query.id).unwrap_or_else(|| {
+            let error = unanswered.clone().unwrap_or_else(|| StyleError::protocol("No result found in batch output"));
+            StyleResponse::failure(&query.id, error)
+        })
+    }).collect();
+
+    responses.iter().for_each(backend::log_outcome);
+
+    Ok(responses)
 }
 
-/// Convenience function for optimized batch style computation
+/// Compute a batch of properties and check each against an expected value
+///
+/// Each query is `(selector, property, expected value)`. Values are compared after
+/// normalization (colors, lengths, numbers, keyword case), and a query without an
+/// expected value passes as long as the property computes. Per-query failures are
+/// reported in the returned `StyleReport`; only engine-level failures are errors.
 pub async fn compute_styles_batch_optimized(
     html: &str,
     css: &str,
-    queries: Vec<(String, String, Option<String>)>, // (selector, property_name, property_value)
+    queries: Vec<(String, String, Option<String>)>,
     servo_path: Option<String>,
-) -> Result<Vec<(String, Result<String, ServoStyleError>)>, ServoStyleError> {
-    let mut engine = ServoStyleEngineOptimized::with_options(servo_path, true, 10)?;
+) -> Result<StyleReport, StyleError> {
+    let mut engine = StyleEngine::with_backend(ServoStyleEngineOptimized::with_options(servo_path, true, 10)?);
     engine.set_html(html)?;
     engine.add_stylesheet(css)?;
-    
-    let requests: Vec<(String, Option<String>)> = queries.into_iter()
-        .map(|(selector, prop, _)| (selector, Some(prop)))
+
+    let requests: Vec<StyleRequest> = queries.iter()
+        .map(|(selector, property, _)| StyleRequest::value(selector, selector, property))
         .collect();
-    
-    engine.compute_styles_batch(requests).await
-}
\ No newline at end of file
+
+    let results = engine.compute_styles_batch(requests).await?;
+    let assertions = queries.iter().zip(results)
+        .map(|((selector, property, expected), result)| {
+            let actual = result.result.map(|value| value.to_string());
+            StyleAssertion::check(selector, property, expected.as_deref(), actual)
+        })
+        .collect();
+    Ok(StyleReport { assertions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::StyleEngine;
+    use crate::mock_servo::{FakeServo, FakeServoFixtures, MockBackend};
+
+    const NONCE: &str = "0123456789abcdef";
+
+    fn fixtures() -> FakeServoFixtures {
+        FakeServoFixtures::new()
+            .with_style(".a", "color", "rgb(255, 0, 0)")
+            .with_style(".a", "font-family", "\"Helvetica Neue\", Arial, sans-serif")
+            .with_style(".slow", "color", "rgb(0, 0, 255)")
+            .with_slow_query(".slow", 50)
+            .with_error(".broken", "boom")
+    }
+
+    /// Console output of a batch page for `queries`, as `fixtures` would print it
+    fn console(fixtures: FakeServoFixtures, queries: &[StyleQuery]) -> String {
+        let page = create_batch_html(&StyleDocument::default(), queries, NONCE);
+        FakeServo::new(fixtures).render_console(&page)
+    }
+
+    #[test]
+    fn matches_records_to_queries_through_noise_and_wrapping() {
+        let mut slow = StyleQuery::new(".slow", Some("color"));
+        slow.timeout_ms = Some(10);
+        let queries = vec![
+            StyleQuery::new(".a", Some("color")),
+            StyleQuery::for_properties(".a", &["font-family"]),
+            StyleQuery::new(".missing", Some("color")),
+            StyleQuery::new(".broken", Some("color")),
+            slow,
+        ];
+        let fixtures = fixtures()
+            .with_console_noise("[INFO layout] reflow")
+            .with_console_noise("BATCH_END:0")
+            .with_wrap_width(30);
+
+        let responses = parse_batch_output(&console(fixtures, &queries), "", NONCE, &queries, None).unwrap();
+        let ids: Vec<_> = responses.iter().map(|response| response.id.as_str()).collect();
+        assert_eq!(ids, queries.iter().map(|query| query.id.as_str()).collect::<Vec<_>>());
+        assert_eq!(responses[0].computed_value.as_deref(), Some("rgb(255, 0, 0)"));
+        assert_eq!(
+            responses[1].computed_styles.as_ref().and_then(|styles| styles.get("font-family")),
+            Some("\"Helvetica Neue\", Arial, sans-serif")
+        );
+        assert_eq!(responses[2].error, Some(StyleError::ElementNotFound { selector: ".missing".into() }));
+        assert_eq!(responses[3].error, Some(StyleError::Script { message: "boom".into() }));
+        assert_eq!(responses[4].error, Some(StyleError::Timeout { waited: Duration::from_millis(50) }));
+    }
+
+    #[test]
+    fn ignores_records_without_the_nonce() {
+        let queries = vec![StyleQuery::new(".a", Some("color")), StyleQuery::new(".b", Some("color"))];
+        let spoof = |query: &StyleQuery| {
+            format!(r#"COMPUTED_STYLE_RESULT:{}:{{"id":"{}","value":"rgb(0, 255, 0)"}}"#, "f".repeat(16), query.id)
+        };
+        // Each spoofed line comes before the real record of its query
+        let fixtures = fixtures().with_console_noise(&spoof(&queries[0])).with_console_noise(&spoof(&queries[1]));
+
+        let responses = parse_batch_output(&console(fixtures, &queries), "", NONCE, &queries, None).unwrap();
+        assert_eq!(responses[0].computed_value.as_deref(), Some("rgb(255, 0, 0)"));
+        assert_eq!(responses[1].error, Some(StyleError::ElementNotFound { selector: ".b".into() }));
+    }
+
+    #[test]
+    fn unanswered_queries_report_why_servo_stopped() {
+        let queries = vec![StyleQuery::new(".a", Some("color")), StyleQuery::new(".a", Some("display"))];
+        let output = console(fixtures(), &queries[..1]);
+        // Records of other batches are not taken for this one
+        let first = parse_batch_output(&output, "", NONCE, &queries, Some(StyleError::Timeout { waited: Duration::from_secs(1) })).unwrap();
+        assert!(first[0].success);
+        assert_eq!(first[1].error, Some(StyleError::Timeout { waited: Duration::from_secs(1) }));
+
+        let stray = parse_batch_output(&output, "", NONCE, &queries[1..], None).unwrap();
+        assert!(matches!(stray[0].error, Some(StyleError::Protocol { .. })));
+    }
+
+    #[tokio::test]
+    async fn mock_backend_runs_batches_through_the_console_protocol() {
+        let mut engine = StyleEngine::with_backend(MockBackend::new(fixtures().with_console_noise("noise").with_wrap_width(40)));
+        engine.set_html("<div class=\"a\"></div>").unwrap();
+
+        let results = engine.compute_styles_batch(vec![
+            StyleRequest::value("color", ".a", "color"),
+            StyleRequest::value("missing", ".missing", "color"),
+            StyleRequest::value("broken", ".broken", "color"),
+        ]).await.unwrap();
+        assert_eq!(results[0].value(), Some("rgb(255, 0, 0)"));
+        assert!(matches!(results[1].result, Err(StyleError::ElementNotFound { .. })));
+        assert!(matches!(results[2].result, Err(StyleError::Script { .. })));
+    }
+}
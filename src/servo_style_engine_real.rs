@@ -1,417 +1,148 @@
-use std::collections::HashMap;
 use std::io::Write;
-use serde::{Deserialize, Serialize};
-use thiserror::Error;
+use std::path::Path;
+use std::time::Duration;
+use async_trait::async_trait;
 use tempfile::NamedTempFile;
+use tracing::{debug, debug_span, info, warn, Instrument};
 
-#[derive(Error, Debug)]
-pub enum ServoStyleError {
-    #[error("Servo executable not found")]
-    ServoNotFound,
-    #[error("Failed to start Servo process: {0}")]
-    ProcessStartError(#[from] std::io::Error),
-    #[error("Servo process communication error: {0}")]
-    CommunicationError(String),
-    #[error("JSON serialization error: {0}")]
-    SerializationError(#[from] serde_json::Error),
-    #[error("Style computation failed: {0}")]
-    ComputationError(String),
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct StyleQuery {
-    id: String,
-    html: String,
-    css: String,
-    selector: String,
-    property: Option<String>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct StyleResponse {
-    id: String,
-    success: bool,
-    computed_value: Option<String>,
-    computed_styles: Option<HashMap<String, String>>,
-    error: Option<String>,
-}
+use crate::artifacts::DebugArtifacts;
+use crate::backend::{self, log_outcome, ComputedStyles, QueryOptions, StyleBackend, StyleDocument, StyleEngine, StyleQuery, StyleResponse};
+use crate::config::EngineConfig;
+use crate::error::StyleError;
+use crate::media::MediaEnvironment;
+use crate::page_script::{self, ConsoleRecord, RecordKind};
+use crate::servo_process::run_servo_page;
 
 /// Real Servo-based CSS style engine that uses Stylo's native APIs
 /// 
 /// This implementation creates HTML files with embedded JavaScript to extract computed styles,
 /// then runs Servo to process them and extract the results using real Stylo APIs.
+///
+/// The engine is a `StyleBackend`: wrap it in `StyleEngine::with_backend` to set the
+/// document and query it.
 pub struct ServoStyleEngineReal {
-    base_html: String,
-    stylesheets: Vec<String>,
-    servo_path: Option<String>,
+    /// Document of the deprecated front-end methods
+    legacy: StyleDocument,
+    config: EngineConfig,
+    artifacts: Option<DebugArtifacts>,
 }
 
 impl ServoStyleEngineReal {
     /// Create a new ServoStyleEngine instance with real Servo integration
-    pub fn new() -> Result<Self, ServoStyleError> {
+    pub fn new() -> Result<Self, StyleError> {
         Self::with_servo_path(None)
     }
 
     /// Create a new ServoStyleEngine instance with a custom Servo path
-    pub fn with_servo_path(servo_path: Option<String>) -> Result<Self, ServoStyleError> {
+    pub fn with_servo_path(servo_path: Option<String>) -> Result<Self, StyleError> {
+        Self::with_config(&EngineConfig {
+            servo_path,
+            ..EngineConfig::default()
+        })
+    }
+
+    /// Create a new ServoStyleEngine instance from an engine configuration
+    pub fn with_config(config: &EngineConfig) -> Result<Self, StyleError> {
         // Check if Servo is available
-        if let Some(ref path) = servo_path {
-            if !std::path::Path::new(path).exists() {
-                return Err(ServoStyleError::ServoNotFound);
-            }
-        } else if which::which("servo").is_err() {
-            return Err(ServoStyleError::ServoNotFound);
-        }
+        config.check_servo()?;
         
-        println!("✅ Servo found - enabling real Stylo integration");
-        if let Some(ref path) = servo_path {
-            println!("   Using custom Servo path: {}", path);
-        } else {
-            println!("   Using Servo from PATH");
-        }
+        info!(servo = config.servo_command(), "Servo found - enabling real Stylo integration");
 
         Ok(ServoStyleEngineReal {
-            base_html: String::new(),
-            stylesheets: Vec::new(),
-            servo_path,
+            legacy: StyleDocument::default(),
+            config: config.clone(),
+            artifacts: DebugArtifacts::for_config(config),
         })
     }
 
-    /// Add a CSS stylesheet to the style engine
-    pub fn add_stylesheet(&mut self, css: &str) -> Result<(), ServoStyleError> {
-        self.stylesheets.push(css.to_string());
-        Ok(())
-    }
-
-    /// Set the HTML content for style computation
-    pub fn set_html(&mut self, html: &str) -> Result<(), ServoStyleError> {
-        self.base_html = html.to_string();
-        Ok(())
+    /// Directory this engine writes debug artifacts to, if enabled
+    pub fn debug_artifact_dir(&self) -> Option<&Path> {
+        self.artifacts.as_ref().map(DebugArtifacts::run_dir)
     }
 
     /// Create an HTML file with embedded JavaScript to extract computed styles
-    fn create_style_extraction_html(&self, selector: &str, property: Option<&str>) -> String {
-        let combined_css = self.stylesheets.join("\n");
-        
-        let script = if let Some(prop) = property {
-            format!(r#"
-                window.addEventListener('load', function() {{
-                    try {{
-                        var element = document.querySelector('{}');
-                        if (element) {{
-                            var computedStyle = window.getComputedStyle(element);
-                            var value = computedStyle.getPropertyValue('{}');
-                            console.log('COMPUTED_STYLE_RESULT:' + JSON.stringify({{
-                                selector: '{}',
-                                property: '{}',
-                                value: value
-                            }}));
-                        }} else {{
-                            console.log('COMPUTED_STYLE_ERROR:Element not found');
-                        }}
-                    }} catch (e) {{
-                        console.log('COMPUTED_STYLE_ERROR:' + e.message);
-                    }}
-                    // Give Servo more time to log then exit
-                    setTimeout(function() {{ window.close(); }}, 500);
-                }});
-            "#, selector, prop, selector, prop)
-        } else {
-            format!(r#"
-                window.addEventListener('load', function() {{
-                    try {{
-                        var element = document.querySelector('{}');
-                        if (element) {{
-                            var computedStyle = window.getComputedStyle(element);
-                            var styles = {{}};
-                            for (var i = 0; i < computedStyle.length; i++) {{
-                                var propName = computedStyle[i];
-                                styles[propName] = computedStyle.getPropertyValue(propName);
-                            }}
-                            console.log('COMPUTED_STYLES_RESULT:' + JSON.stringify({{
-                                selector: '{}',
-                                styles: styles
-                            }}));
-                        }} else {{
-                            console.log('COMPUTED_STYLE_ERROR:Element not found');
-                        }}
-                    }} catch (e) {{
-                        console.log('COMPUTED_STYLE_ERROR:' + e.message);
-                    }}
-                    setTimeout(function() {{ window.close(); }}, 500);
-                }});
-            "#, selector, selector)
-        };
-
-        format!(r#"<!DOCTYPE html>
-<html>
-<head>
-    <style>
-        {}
-    </style>
-</head>
-<body>
-    {}
-    <script>
-        {}
-    </script>
-</body>
-</html>"#, combined_css, self.base_html, script)
+    ///
+    /// The query is passed to the script as a JSON payload, never as script source.
+    fn create_style_extraction_html(&self, document: &StyleDocument, query: &StyleQuery, nonce: &str) -> String {
+        page_script::extraction_page(document, std::slice::from_ref(query), false, nonce)
     }
 
     /// Run Servo with the HTML file and extract computed styles from output
-    async fn run_servo_and_extract_styles(&self, html_content: &str) -> Result<String, ServoStyleError> {
+    async fn run_servo_and_extract_styles(&self, query_id: &str, html_content: &str, nonce: &str, media: &MediaEnvironment, timeout: Duration) -> Result<ConsoleRecord, StyleError> {
         // Create temporary HTML file
         let mut temp_file = NamedTempFile::new()
-            .map_err(|e| ServoStyleError::CommunicationError(format!("Failed to create temp file: {}", e)))?;
+            .map_err(|e| StyleError::Io { message: format!("Failed to create temp file: {}", e) })?;
         
         temp_file.write_all(html_content.as_bytes())
-            .map_err(|e| ServoStyleError::CommunicationError(format!("Failed to write temp file: {}", e)))?;
+            .map_err(|e| StyleError::Io { message: format!("Failed to write temp file: {}", e) })?;
         
         let temp_path = temp_file.path();
-        let servo_cmd = self.servo_path.as_deref().unwrap_or("servo");
-        
-        // Create result file path
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        let result_path = format!("/tmp/servo_output_{}.txt", timestamp);
-        
-        println!("🚀 Running Servo with 10 second timeout...");
-        println!("   Output will be saved to: {}", result_path);
-        
-        // Run Servo with timeout
-        let output = tokio::time::timeout(
-            std::time::Duration::from_secs(10),
-            tokio::process::Command::new(servo_cmd)
-                .arg("--headless")
-                .arg(format!("file://{}", temp_path.display()))
-                .output()
-        ).await;
-        
-        let (stdout, stderr, status_info) = match output {
-            Ok(Ok(process_output)) => {
-                let stdout = String::from_utf8_lossy(&process_output.stdout);
-                let stderr = String::from_utf8_lossy(&process_output.stderr);
-                let status_info = format!("Exit Code: {}", process_output.status);
-                println!("✅ Servo completed normally");
-                (stdout.to_string(), stderr.to_string(), status_info)
-            },
-            Ok(Err(e)) => {
-                let error_content = format!("SERVO ERROR\n===========\nFailed to start: {}\n", e);
-                std::fs::write(&result_path, error_content)?;
-                return Err(ServoStyleError::CommunicationError(format!("Failed to start Servo: {}", e)));
-            },
-            Err(_) => {
-                println!("⏰ Servo timed out, but checking if it wrote results to temp file...");
-                // Even if timed out, Servo might have written results
-                ("".to_string(), "".to_string(), "Status: Timed out after 10 seconds".to_string())
-            }
-        };
-        
-        // Write to text file
-        let content = format!("SERVO OUTPUT\n============\n{}\n\nSTDOUT:\n{}\n\nSTDERR:\n{}\n", 
-            status_info, stdout, stderr);
-        std::fs::write(&result_path, content)?;
-        println!("   📄 Output saved to: {}", result_path);
-        
-        // Check if we have results in stdout/stderr first
-        if !stdout.is_empty() || !stderr.is_empty() {
-            if let Ok(result) = self.parse_servo_output(&stdout, &stderr) {
-                return Ok(result);
-            }
+
+        // Save debug artifacts if enabled
+        let artifacts = self.artifacts.as_ref().map(|artifacts| artifacts.batch("query", query_id));
+        if let Some(ref artifacts) = artifacts {
+            artifacts.write("page.html", html_content);
         }
         
-        // If no results in stdout/stderr, check if temp file has console output
-        // Servo might have written console.log results to the temp file or other locations
-        println!("   🔍 Checking for results in alternative locations...");
+        debug!(timeout_ms = timeout.as_millis() as u64, "Running Servo");
         
-        // Sometimes Servo writes console output to files or stdout isn't captured properly
-        // Let's try reading any output files Servo might have created
-        if let Ok(temp_content) = std::fs::read_to_string(temp_path) {
-            if temp_content.contains("COMPUTED_STYLE_RESULT:") || temp_content.contains("COMPUTED_STYLES_RESULT:") {
-                println!("   ✅ Found results in temp file!");
-                return self.parse_servo_output(&temp_content, "");
-            }
+        // Run Servo with timeout
+        let config = media.page_config(&self.config);
+        let run = run_servo_page(&config, &format!("file://{}", temp_path.display()), timeout, artifacts.as_ref()).await?;
+        match run.status {
+            Some(status) => debug!(%status, "Servo completed normally"),
+            // Even if timed out, Servo might have printed the result already
+            None => warn!(elapsed_ms = run.elapsed.as_millis() as u64, "Servo timed out, checking the output it produced so far"),
         }
         
-        // If still no results, the computation may have failed
-        Err(ServoStyleError::CommunicationError(format!(
-            "No computed style results found. Check output file: {}", result_path
-        )))
+        // A timed out or crashed run may still have printed its result
+        parse_servo_output(&run.stdout, &run.stderr, nonce, run.failure())
     }
     
-    /// Parse Servo output to extract computed style results
-    fn parse_servo_output(&self, stdout: &str, stderr: &str) -> Result<String, ServoStyleError> {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        let parsed_result_path = format!("/tmp/servo_parsed_{}.txt", timestamp);
-        
-        // Look for our computed style results in the output
-        for line in stdout.lines().chain(stderr.lines()) {
-            if line.contains("COMPUTED_STYLE_RESULT:") {
-                if let Some(json_part) = line.split("COMPUTED_STYLE_RESULT:").nth(1) {
-                    println!("   ✅ Found single property result");
-                    
-                    // Clean the JSON part - remove extra whitespace and potential issues
-                    let cleaned_json = json_part.trim();
-                    
-                    // Parse and show clean result
-                    let mut parsed_content = String::new();
-                    if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(cleaned_json) {
-                        if let (Some(selector), Some(property), Some(value)) = (
-                            parsed["selector"].as_str(),
-                            parsed["property"].as_str(), 
-                            parsed["value"].as_str()
-                        ) {
-                            let result_line = format!("{} -> {}: {}", selector, property, value);
-                            println!("   🎯 {}", result_line);
-                            parsed_content = format!("SINGLE PROPERTY RESULT:\n{}\n\nRAW JSON:\n{}\n", result_line, cleaned_json);
-                        }
-                    } else {
-                        parsed_content = format!("SINGLE PROPERTY RESULT (RAW):\n{}\n", cleaned_json);
-                    }
-                    
-                    // Save parsed result to file and cat it
-                    std::fs::write(&parsed_result_path, &parsed_content).ok();
-                    println!("   📄 Parsed result saved to: {}", parsed_result_path);
-                    
-                    if let Ok(cat_output) = std::process::Command::new("cat").arg(&parsed_result_path).output() {
-                        let cat_content = String::from_utf8_lossy(&cat_output.stdout);
-                        println!("   📋 Parsed result:\n{}", cat_content);
-                    }
-                    
-                    return Ok(cleaned_json.to_string());
-                }
-            }
-            if line.contains("COMPUTED_STYLES_RESULT:") {
-                if let Some(json_part) = line.split("COMPUTED_STYLES_RESULT:").nth(1) {
-                    println!("   ✅ Found all styles result");
-                    
-                    // Clean the JSON part - remove extra whitespace and potential issues
-                    let cleaned_json = json_part.trim();
-                    
-                    // Parse and show summary
-                    let mut parsed_content = String::new();
-                    if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(cleaned_json) {
-                        if let (Some(selector), Some(styles_obj)) = (
-                            parsed["selector"].as_str(),
-                            parsed["styles"].as_object()
-                        ) {
-                            let summary_line = format!("{} has {} computed properties", selector, styles_obj.len());
-                            println!("   🎯 {}", summary_line);
-                            
-                            parsed_content.push_str(&format!("ALL STYLES RESULT:\n{}\n\nKEY PROPERTIES:\n", summary_line));
-                            
-                            // Show some key properties
-                            let key_props = ["color", "font-size", "font-weight", "background-color", "display", "width", "height"];
-                            for prop in &key_props {
-                                if let Some(value) = styles_obj.get(*prop).and_then(|v| v.as_str()) {
-                                    if !value.is_empty() && value != "auto" && value != "0px" {
-                                        let prop_line = format!("  {}: {}", prop, value);
-                                        println!("   📋   {}: {}", prop, value);
-                                        parsed_content.push_str(&format!("{}\n", prop_line));
-                                    }
-                                }
-                            }
-                            
-                            parsed_content.push_str(&format!("\nRAW JSON:\n{}\n", cleaned_json));
-                        }
-                    } else {
-                        parsed_content = format!("ALL STYLES RESULT (RAW):\n{}\n", cleaned_json);
-                    }
-                    
-                    // Save parsed result to file and cat it
-                    std::fs::write(&parsed_result_path, &parsed_content).ok();
-                    println!("   📄 Parsed result saved to: {}", parsed_result_path);
-                    
-                    if let Ok(cat_output) = std::process::Command::new("cat").arg(&parsed_result_path).output() {
-                        let cat_content = String::from_utf8_lossy(&cat_output.stdout);
-                        println!("   📋 Parsed result:\n{}", cat_content);
-                    }
-                    
-                    return Ok(cleaned_json.to_string());
-                }
-            }
-            if line.contains("COMPUTED_STYLE_ERROR:") {
-                if let Some(error_part) = line.split("COMPUTED_STYLE_ERROR:").nth(1) {
-                    let error_content = format!("ERROR:\n{}\n", error_part);
-                    std::fs::write(&parsed_result_path, &error_content).ok();
-                    println!("   📄 Error saved to: {}", parsed_result_path);
-                    
-                    return Err(ServoStyleError::CommunicationError(format!("Servo error: {}", error_part)));
-                }
-            }
-        }
-        
-        // No result found - save this info too
-        let no_result_content = format!("NO RESULT FOUND\n\nSTDOUT:\n{}\n\nSTDERR:\n{}\n", stdout, stderr);
-        std::fs::write(&parsed_result_path, &no_result_content).ok();
-        println!("   📄 No result info saved to: {}", parsed_result_path);
-        
-        Err(ServoStyleError::CommunicationError(format!(
-            "No computed style result found in Servo output. Check result file: {}", 
-            parsed_result_path
-        )))
-    }
-
     /// Query Servo process for computed styles using real Stylo APIs
-    async fn query_servo_process(&mut self, query: StyleQuery) -> Result<StyleResponse, ServoStyleError> {
-        println!("🔄 Querying real Servo process for computed styles...");
-        println!("   Using genuine Stylo APIs via Servo's getComputedStyle()");
+    async fn query_servo_process(&self, document: &StyleDocument, query: StyleQuery) -> Result<StyleResponse, StyleError> {
+        let nonce = page_script::new_nonce();
+        let html_content = self.create_style_extraction_html(document, &query, &nonce);
         
-        let html_content = self.create_style_extraction_html(
-            &query.selector, 
-            query.property.as_deref()
-        );
-        
-        let result_json = self.run_servo_and_extract_styles(&html_content).await?;
+        let timeout = query.timeout_or(self.config.timeout);
+        let record = self.run_servo_and_extract_styles(&query.id, &html_content, &nonce, &document.media, timeout).await?;
         
-        // Parse the JSON result
-        if query.property.is_some() {
+        let body = record.body;
+        let (computed_value, computed_styles, matches) = match (query.property.is_some(), query.all_matches) {
             // Single property result
-            #[derive(Deserialize)]
-            struct SingleResult {
-                value: String,
-            }
-            
-            println!("🔍 Attempting to parse JSON result: {}", &result_json[..std::cmp::min(100, result_json.len())]);
-            
-            let result: SingleResult = serde_json::from_str(&result_json)
-                .map_err(|e| {
-                    println!("❌ JSON parse failed: {}", e);
-                    println!("   Raw JSON (first 200 chars): {}", &result_json[..std::cmp::min(200, result_json.len())]);
-                    ServoStyleError::CommunicationError(format!("JSON parse error: {}. Raw content: {}", e, result_json))
-                })?;
-            
-            Ok(StyleResponse {
-                id: query.id,
-                success: true,
-                computed_value: Some(result.value),
-                computed_styles: None,
-                error: None,
-            })
-        } else {
+            (true, false) if body.value.is_some() => (body.value, None, None),
+            // Every match of the selector
+            (_, true) if body.matches.is_some() => (None, None, body.matches),
             // All styles result
-            #[derive(Deserialize)]
-            struct AllStylesResult {
-                styles: HashMap<String, String>,
+            (false, false) if body.styles.is_some() => (None, body.styles, None),
+            _ => {
+                warn!(kind = ?record.kind, "Servo result does not match the query");
+                return Err(StyleError::protocol("Servo result does not match the query"));
             }
-            
-            let result: AllStylesResult = serde_json::from_str(&result_json)
-                .map_err(|e| ServoStyleError::CommunicationError(format!("JSON parse error: {}", e)))?;
-            
-            Ok(StyleResponse {
-                id: query.id,
-                success: true,
-                computed_value: None,
-                computed_styles: Some(result.styles),
-                error: None,
-            })
-        }
+        };
+
+        Ok(StyleResponse {
+            id: query.id,
+            success: true,
+            computed_value,
+            computed_styles,
+            matches,
+            error: None,
+        })
+    }
+
+    /// Add a CSS stylesheet to the style engine
+    #[deprecated(note = "wrap the engine in `StyleEngine::with_backend` and use `StyleEngine::add_stylesheet`")]
+    pub fn add_stylesheet(&mut self, css: &str) -> Result<(), StyleError> {
+        self.legacy.stylesheets.push(css.to_string());
+        Ok(())
+    }
+
+    /// Set the HTML content for style computation
+    #[deprecated(note = "wrap the engine in `StyleEngine::with_backend` and use `StyleEngine::set_html`")]
+    pub fn set_html(&mut self, html: &str) -> Result<(), StyleError> {
+        self.legacy.html = html.to_string();
+        Ok(())
     }
 
     /// Get computed style for a specific CSS property using real Stylo APIs
@@ -423,52 +154,67 @@ impl ServoStyleEngineReal {
     /// 4. Executes Stylo's resolve_style() - THE CORE STYLO FUNCTION
     /// 5. Uses SharedStyleContext and ComputedValues from Stylo
     /// 6. Returns genuine computed CSS values
-    pub async fn get_computed_style(&mut self, selector: &str, property: &str) -> Result<String, ServoStyleError> {
-        let combined_css = self.stylesheets.join("\n");
-        
-        let query = StyleQuery {
-            id: uuid::Uuid::new_v4().to_string(),
-            html: self.base_html.clone(),
-            css: combined_css,
-            selector: selector.to_string(),
-            property: Some(property.to_string()),
-        };
+    #[deprecated(note = "wrap the engine in `StyleEngine::with_backend` and use `StyleEngine::get_computed_style`")]
+    pub async fn get_computed_style(&self, selector: &str, property: &str) -> Result<String, StyleError> {
+        backend::compute_value(self, &self.legacy, selector, property, &QueryOptions::default()).await
+    }
 
-        let response = self.query_servo_process(query).await?;
-        
-        if response.success {
-            response.computed_value.ok_or_else(|| {
-                ServoStyleError::ComputationError("No computed value returned".to_string())
-            })
-        } else {
-            Err(ServoStyleError::ComputationError(
-                response.error.unwrap_or_else(|| "Unknown error".to_string())
-            ))
+    /// Get all computed styles for an element using real Stylo APIs
+    #[deprecated(note = "wrap the engine in `StyleEngine::with_backend` and use `StyleEngine::get_all_computed_styles`")]
+    pub async fn get_all_computed_styles(&self, selector: &str) -> Result<ComputedStyles, StyleError> {
+        backend::compute_all(self, &self.legacy, selector, &QueryOptions::default()).await
+    }
+}
+
+#[async_trait]
+impl StyleBackend for ServoStyleEngineReal {
+    fn name(&self) -> &str {
+        "servo-one-shot"
+    }
+
+    /// Runs one Servo instance per query; a failing query does not affect the others
+    async fn compute_batch(&self, document: &StyleDocument, queries: Vec<StyleQuery>) -> Result<Vec<StyleResponse>, StyleError> {
+        let mut responses = Vec::with_capacity(queries.len());
+        for query in queries {
+            let id = query.id.clone();
+            let span = debug_span!("servo_query", query_id = %id, selector = %query.selector, property = ?query.property);
+            let response = match self.query_servo_process(document, query).instrument(span.clone()).await {
+                Ok(response) => response,
+                Err(e) => StyleResponse::failure(&id, e),
+            };
+            span.in_scope(|| log_outcome(&response));
+            responses.push(response);
         }
+        Ok(responses)
     }
+}
 
-    /// Get all computed styles for an element using real Stylo APIs
-    pub async fn get_all_computed_styles(&mut self, selector: &str) -> Result<HashMap<String, String>, ServoStyleError> {
-        let combined_css = self.stylesheets.join("\n");
-        
-        let query = StyleQuery {
-            id: uuid::Uuid::new_v4().to_string(),
-            html: self.base_html.clone(),
-            css: combined_css,
-            selector: selector.to_string(),
-            property: None, // Request all properties
-        };
+/// Parse Servo output to extract the computed style record carrying `nonce`
+///
+/// `unanswered` is the error to report if no record was printed.
+fn parse_servo_output(stdout: &str, stderr: &str, nonce: &str, unanswered: Option<StyleError>) -> Result<ConsoleRecord, StyleError> {
+    let record = page_script::read_records(stdout, nonce).into_iter()
+        .chain(page_script::read_records(stderr, nonce))
+        .next();
 
-        let response = self.query_servo_process(query).await?;
-        
-        if response.success {
-            response.computed_styles.ok_or_else(|| {
-                ServoStyleError::ComputationError("No computed styles returned".to_string())
-            })
-        } else {
-            Err(ServoStyleError::ComputationError(
-                response.error.unwrap_or_else(|| "Unknown error".to_string())
-            ))
+    match record {
+        Some(ConsoleRecord { kind: RecordKind::Error, body }) => {
+            let error = body.error.unwrap_or_else(|| StyleError::protocol("Error record without an error"));
+            debug!(%error, "Style query reported an error");
+            Err(error)
+        }
+        Some(ConsoleRecord { kind: RecordKind::Timeout, body }) => {
+            let waited = Duration::from_millis(body.elapsed_ms.unwrap_or_default());
+            debug!(elapsed_ms = waited.as_millis() as u64, "Style query stopped by the page watchdog");
+            Err(StyleError::Timeout { waited })
+        }
+        Some(record) => {
+            debug!(kind = ?record.kind, selector = record.body.selector.as_deref(), "Found style result");
+            Ok(record)
+        }
+        None => {
+            debug!("No result found in Servo output");
+            Err(unanswered.unwrap_or_else(|| StyleError::protocol("No computed style result found in Servo output")))
         }
     }
 }
@@ -480,9 +226,99 @@ pub async fn compute_style_with_servo_real(
     selector: &str,
     property: &str,
     servo_path: Option<String>,
-) -> Result<String, ServoStyleError> {
-    let mut engine = ServoStyleEngineReal::with_servo_path(servo_path)?;
+) -> Result<String, StyleError> {
+    let mut engine = StyleEngine::with_backend(ServoStyleEngineReal::with_servo_path(servo_path)?);
     engine.set_html(html)?;
     engine.add_stylesheet(css)?;
     engine.get_computed_style(selector, property).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_servo::{FakeServo, FakeServoFixtures};
+
+    const NONCE: &str = "0123456789abcdef";
+
+    /// Console output of a one-shot page for `query`, as `fixtures` would print it
+    fn console(fixtures: FakeServoFixtures, query: &StyleQuery) -> String {
+        let page = page_script::extraction_page(&StyleDocument::default(), std::slice::from_ref(query), false, NONCE);
+        FakeServo::new(fixtures).render_console(&page)
+    }
+
+    fn parse(stdout: &str) -> Result<ConsoleRecord, StyleError> {
+        parse_servo_output(stdout, "", NONCE, None)
+    }
+
+    #[test]
+    fn reads_a_value_between_console_noise() {
+        let query = StyleQuery::new(".a", Some("color"));
+        let fixtures = FakeServoFixtures::new()
+            .with_style(".a", "color", "rgb(255, 0, 0)")
+            .with_console_noise("[WARN script] COMPUTED_STYLE_RESULT is a nice name")
+            .with_console_noise("Loaded 3 fonts");
+        let record = parse(&console(fixtures, &query)).unwrap();
+        assert_eq!(record.kind, RecordKind::Value);
+        assert_eq!(record.body.id.as_deref(), Some(query.id.as_str()));
+        assert_eq!(record.body.value.as_deref(), Some("rgb(255, 0, 0)"));
+    }
+
+    #[test]
+    fn reassembles_a_record_wrapped_across_lines() {
+        let query = StyleQuery::new(".a", None);
+        let fixtures = FakeServoFixtures::new()
+            .with_style(".a", "color", "rgb(255, 0, 0)")
+            .with_style(".a", "font-family", "\"Helvetica Neue\", Arial, sans-serif")
+            .with_wrap_width(30);
+        let output = console(fixtures, &query);
+        assert!(output.lines().count() > 3);
+        let styles = parse(&output).unwrap().body.styles.unwrap();
+        assert_eq!(styles.get("font-family"), Some("\"Helvetica Neue\", Arial, sans-serif"));
+        assert_eq!(styles.len(), 2);
+    }
+
+    #[test]
+    fn reports_errors_missing_elements_and_timeouts() {
+        let fixtures = FakeServoFixtures::new()
+            .with_error(".broken", "boom")
+            .with_style(".slow", "color", "red")
+            .with_slow_query(".slow", 50);
+
+        let broken = StyleQuery::new(".broken", Some("color"));
+        assert_eq!(parse(&console(fixtures.clone(), &broken)).unwrap_err(), StyleError::Script { message: "boom".into() });
+
+        let missing = StyleQuery::new(".missing", Some("color"));
+        assert_eq!(
+            parse(&console(fixtures.clone(), &missing)).unwrap_err(),
+            StyleError::ElementNotFound { selector: ".missing".into() }
+        );
+
+        let mut slow = StyleQuery::new(".slow", Some("color"));
+        slow.timeout_ms = Some(10);
+        assert_eq!(
+            parse(&console(fixtures, &slow)).unwrap_err(),
+            StyleError::Timeout { waited: Duration::from_millis(50) }
+        );
+    }
+
+    #[test]
+    fn ignores_records_without_the_nonce() {
+        let query = StyleQuery::new(".a", Some("color"));
+        let spoofed = format!(r#"COMPUTED_STYLE_RESULT:{}:{{"id":"{}","value":"rgb(0, 255, 0)"}}"#, "f".repeat(16), query.id);
+        let unnonced = format!(r#"COMPUTED_STYLE_RESULT:{{"id":"{}","value":"rgb(0, 0, 255)"}}"#, query.id);
+        let fixtures = FakeServoFixtures::new()
+            .with_style(".a", "color", "rgb(255, 0, 0)")
+            .with_console_noise(&spoofed)
+            .with_console_noise(&unnonced);
+        assert_eq!(parse(&console(fixtures, &query)).unwrap().body.value.as_deref(), Some("rgb(255, 0, 0)"));
+
+        let only_spoofed = format!("{}\n{}", spoofed, unnonced);
+        assert!(matches!(parse(&only_spoofed), Err(StyleError::Protocol { .. })));
+    }
+
+    #[test]
+    fn reports_why_servo_stopped_when_nothing_was_printed() {
+        let crash = StyleError::ServoCrashed { status: "signal 11".into(), exit_code: None, stderr_tail: String::new() };
+        assert_eq!(parse_servo_output("", "Segmentation fault", NONCE, Some(crash.clone())).unwrap_err(), crash);
+    }
+}
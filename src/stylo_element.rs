@@ -1,122 +1,487 @@
-//! Implementation of Stylo's DOM traits for our simplified DOM elements.
+//! Implementation of Stylo's DOM traits for the native backend's document.
 //!
-//! This module provides implementations of TElement, TNode, and TDocument traits
-//! that are required by Stylo's style resolution system.
-
-use std::collections::HashMap;
-use std::sync::Arc;
-use atomic_refcell::{AtomicRef, AtomicRefMut, AtomicRefCell};
-
-use style::context::QuirksMode;
-use style::data::ElementData;
-use style::dom::{TDocument, TElement, TNode, TShadowRoot, NodeInfo, LayoutIterator};
-use style::media_queries::Device;
-use style::properties::{ComputedValues, PropertyDeclarationBlock, AnimationDeclarations};
-use style::selector_parser::{SelectorImpl, PseudoElement, AttrValue, Lang, RestyleDamage};
-use style::shared_lock::{SharedRwLock, Locked};
+//! A `StyloDom` owns every node of one document together with the `SharedRwLock` its
+//! style data lives under. The handles Stylo traverses (`StyloNode`, `StyloElement`,
+//! `StyloDocument`) are `Copy` references into it, identified by their node id.
+
+use std::cell::{Cell, UnsafeCell};
+use std::fmt;
+
+use selectors::attr::{AttrSelectorOperation, CaseSensitivity, NamespaceConstraint};
+use selectors::matching::{ElementSelectorFlags, MatchingContext, VisitedHandlingMode};
+use selectors::sink::Push;
+use selectors::{Element as SelectorsElement, OpaqueElement};
+use servo_arc::{Arc as ServoArc, ArcBorrow};
+use style::applicable_declarations::ApplicableDeclarationBlock;
+use style::context::{QuirksMode, SharedStyleContext};
+use style::data::{ElementDataMut, ElementDataRef, ElementDataWrapper};
+use style::dom::{LayoutIterator, NodeInfo, OpaqueNode, TDocument, TElement, TNode, TShadowRoot};
+use style::properties::{parse_style_attribute, PropertyDeclarationBlock};
+use style::selector_parser::{AttrValue, Lang, NonTSPseudoClass, PseudoElement, SelectorImpl};
+use style::shared_lock::{Locked, SharedRwLock};
+use style::stylesheets::{CssRuleType, UrlExtraData};
 use style::stylist::CascadeData;
 use style::values::computed::Display;
 use style::values::AtomIdent;
-use style::{LocalName, WeakAtom};
-use servo_arc::Arc as ServoArc;
-use selectors::matching::{ElementSelectorFlags, VisitedHandlingMode};
-use selectors::sink::Push;
-use selectors::{Element as SelectorsElement, OpaqueElement};
-use style_traits::dom::OpaqueNode;
+use style::{Atom, CaseSensitivityExt};
+use stylo_dom::ElementState;
+use web_atoms::{LocalName, Namespace};
+
+/// Attribute name as Stylo's `TElement` passes it
+type AttrName = style::LocalName;
+
+const HTML_NAMESPACE: &str = "http://www.w3.org/1999/xhtml";
+const MATHML_NAMESPACE: &str = "http://www.w3.org/1998/Math/MathML";
+const SVG_NAMESPACE: &str = "http://www.w3.org/2000/svg";
+
+/// Index of a node in its `StyloDom`
+pub(crate) type NodeId = usize;
+
+/// Element tree to be loaded into the native backend, built by hand or parsed from HTML
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NativeElement {
+    pub tag_name: String,
+    /// Namespace URL; HTML unless the element came from inline SVG or MathML
+    pub namespace: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<NativeNode>,
+}
 
-/// A simple document implementation for Stylo
-#[derive(Debug, Clone, Copy)]
-pub struct StyloDocument {
-    shared_lock: *const SharedRwLock,
-    quirks_mode: QuirksMode,
+/// Child of a `NativeElement`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NativeNode {
+    Element(NativeElement),
+    Text(String),
+    Comment(String),
 }
 
-unsafe impl Send for StyloDocument {}
-unsafe impl Sync for StyloDocument {}
+impl NativeElement {
+    pub fn new(tag_name: &str) -> Self {
+        NativeElement {
+            tag_name: tag_name.to_ascii_lowercase(),
+            namespace: HTML_NAMESPACE.to_string(),
+            attributes: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Set an attribute, replacing an earlier value of the same name
+    pub fn with_attribute(mut self, name: &str, value: &str) -> Self {
+        let name = name.to_ascii_lowercase();
+        self.attributes.retain(|(existing, _)| *existing != name);
+        self.attributes.push((name, value.to_string()));
+        self
+    }
+
+    pub fn with_child(mut self, child: NativeElement) -> Self {
+        self.children.push(NativeNode::Element(child));
+        self
+    }
+
+    /// Append a text node
+    pub fn with_text(mut self, text: &str) -> Self {
+        self.children.push(NativeNode::Text(text.to_string()));
+        self
+    }
+}
 
-impl StyloDocument {
-    pub fn new(shared_lock: &SharedRwLock, quirks_mode: QuirksMode) -> Self {
-        Self {
-            shared_lock: shared_lock as *const SharedRwLock,
-            quirks_mode,
+/// Element-specific part of a node
+struct ElementInfo {
+    local_name: LocalName,
+    namespace: Namespace,
+    attributes: Vec<(AttrName, String)>,
+    id: Option<Atom>,
+    classes: Vec<AtomIdent>,
+    /// Declarations of the `style` attribute, parsed under the document's lock
+    style: Option<ServoArc<Locked<PropertyDeclarationBlock>>>,
+    /// State implied by the markup, e.g. `:checked` for `<input checked>`
+    initial_state: ElementState,
+    /// Current state, including states forced for a query
+    state: Cell<ElementState>,
+}
+
+impl ElementInfo {
+    fn new(spec: &NativeElement) -> Self {
+        let attribute = |name: &str| spec.attributes.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+        ElementInfo {
+            local_name: LocalName::from(spec.tag_name.as_str()),
+            namespace: Namespace::from(spec.namespace.as_str()),
+            attributes: spec.attributes.iter().map(|(name, value)| (AttrName::from(name.as_str()), value.clone())).collect(),
+            id: attribute("id").filter(|id| !id.is_empty()).map(Atom::from),
+            classes: attribute("class").map_or_else(Vec::new, |classes| classes.split_whitespace().map(AtomIdent::from).collect()),
+            style: None,
+            initial_state: ElementState::empty(),
+            state: Cell::new(ElementState::empty()),
         }
+        .with_initial_state()
+    }
+
+    fn with_initial_state(mut self) -> Self {
+        let has = |name: &str| self.attribute(name).is_some();
+        let mut state = ElementState::empty();
+        match self.local_name.as_str() {
+            "button" | "input" | "select" | "textarea" | "optgroup" | "option" | "fieldset" => {
+                state |= if has("disabled") { ElementState::DISABLED } else { ElementState::ENABLED };
+            }
+            _ => {}
+        }
+        let checkable = matches!(self.attribute("type"), Some(kind) if kind.eq_ignore_ascii_case("checkbox") || kind.eq_ignore_ascii_case("radio"));
+        if (self.local_name.as_str() == "input" && checkable && has("checked")) || (self.local_name.as_str() == "option" && has("selected")) {
+            state |= ElementState::CHECKED;
+        }
+        if matches!(self.local_name.as_str(), "a" | "area" | "link") && has("href") {
+            state |= ElementState::UNVISITED;
+        }
+        self.initial_state = state;
+        self.state = Cell::new(state);
+        self
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(n, _)| &**n == name).map(|(_, value)| value.as_str())
     }
 }
 
-/// A simple node implementation for Stylo
-#[derive(Debug, Clone)]
-pub struct StyloNode {
-    pub element: Option<StyloElement>,
-    pub document: StyloDocument,
+enum NodeKind {
+    Document,
+    Element(ElementInfo),
+    Text(String),
+    Comment,
 }
 
-impl Copy for StyloNode {}
+/// One node of the arena, linked to its relatives by id
+struct NodeData {
+    id: NodeId,
+    /// The document owning the node, so a handle to the node reaches its relatives
+    dom: *const StyloDom,
+    kind: NodeKind,
+    parent: Option<NodeId>,
+    prev_sibling: Option<NodeId>,
+    next_sibling: Option<NodeId>,
+    children: Vec<NodeId>,
+    /// Stylo's per-element style data
+    style_data: StyleData,
+}
 
-impl StyloNode {
-    pub fn new_element(element: StyloElement, document: StyloDocument) -> Self {
-        Self {
-            element: Some(element),
-            document,
+impl NodeData {
+    fn new(dom: &StyloDom, id: NodeId, kind: NodeKind, parent: Option<NodeId>) -> Self {
+        NodeData {
+            id,
+            dom,
+            kind,
+            parent,
+            prev_sibling: None,
+            next_sibling: None,
+            children: Vec::new(),
+            style_data: StyleData::default(),
         }
     }
-    
-    pub fn new_document(document: StyloDocument) -> Self {
-        Self {
-            element: None,
-            document,
+
+    fn element(&self) -> Option<&ElementInfo> {
+        match self.kind {
+            NodeKind::Element(ref info) => Some(info),
+            _ => None,
         }
     }
 }
 
-/// A simple element implementation for Stylo
-#[derive(Debug, Clone)]
-pub struct StyloElement {
-    pub tag_name: LocalName,
-    pub attributes: HashMap<LocalName, AttrValue>,
-    pub parent: Option<Arc<StyloElement>>,
-    pub children: Vec<Arc<StyloElement>>,
-    pub data: AtomicRefCell<Option<ElementData>>,
-    pub id: Option<WeakAtom>,
-    pub classes: Vec<AtomIdent>,
+/// Stylo's style data of one node, created when the traversal first reaches it
+///
+/// Stylo hands out references through `ElementDataWrapper`, which checks borrows in
+/// debug builds; creating and dropping the wrapper is left to the traversal, which has
+/// exclusive access to the tree while it runs.
+#[derive(Default)]
+struct StyleData(UnsafeCell<Option<ElementDataWrapper>>);
+
+impl StyleData {
+    fn get(&self) -> Option<&ElementDataWrapper> {
+        unsafe { &*self.0.get() }.as_ref()
+    }
+
+    /// The node's style data, created empty if it has none
+    ///
+    /// Safety: nothing may borrow this node's style data.
+    unsafe fn ensure(&self) -> ElementDataMut<'_> {
+        let data = &mut *self.0.get();
+        data.get_or_insert_with(ElementDataWrapper::default).borrow_mut()
+    }
+
+    /// Drop the node's style data
+    ///
+    /// Safety: nothing may borrow this node's style data.
+    unsafe fn clear(&self) {
+        *self.0.get() = None;
+    }
 }
 
-impl Copy for StyloElement {}
+/// A document's nodes and the lock guarding their style data
+///
+/// Nodes live in one arena and never move, so a node's id is stable for the lifetime
+/// of the document. Node 0 is the document node; the root element is its only child.
+/// The document is boxed so that the nodes' pointers back to it stay valid.
+pub(crate) struct StyloDom {
+    nodes: Vec<NodeData>,
+    lock: SharedRwLock,
+    quirks_mode: QuirksMode,
+    /// Base URL for stylesheets and `style` attributes
+    url_data: UrlExtraData,
+}
 
-impl StyloElement {
-    pub fn new(tag_name: &str) -> Self {
-        Self {
-            tag_name: LocalName::from(tag_name),
-            attributes: HashMap::new(),
-            parent: None,
-            children: Vec::new(),
-            data: AtomicRefCell::new(None),
-            id: None,
-            classes: Vec::new(),
+// Style data is only mutated by the traversal, which the backend runs under its own mutex
+unsafe impl Send for StyloDom {}
+unsafe impl Sync for StyloDom {}
+unsafe impl Sync for NodeData {}
+
+impl StyloDom {
+    pub(crate) const DOCUMENT: NodeId = 0;
+
+    /// A document holding `root` and its descendants
+    pub(crate) fn new(root: &NativeElement) -> Box<Self> {
+        let mut dom = Box::new(StyloDom {
+            nodes: Vec::new(),
+            lock: SharedRwLock::new(),
+            quirks_mode: QuirksMode::NoQuirks,
+            url_data: UrlExtraData::from(url::Url::parse("about:blank").unwrap()),
+        });
+        let document = NodeData::new(&dom, Self::DOCUMENT, NodeKind::Document, None);
+        dom.nodes.push(document);
+        dom.append(Self::DOCUMENT, root);
+        dom
+    }
+
+    fn append(&mut self, parent: NodeId, spec: &NativeElement) {
+        let mut info = ElementInfo::new(spec);
+        info.style = info.attribute("style").map(|css| self.parse_style(css));
+        let id = self.push_child(parent, NodeKind::Element(info));
+        for child in &spec.children {
+            match child {
+                NativeNode::Element(element) => self.append(id, element),
+                NativeNode::Text(text) => {
+                    self.push_child(id, NodeKind::Text(text.clone()));
+                }
+                NativeNode::Comment(_) => {
+                    self.push_child(id, NodeKind::Comment);
+                }
+            }
         }
     }
-    
-    pub fn with_attribute(mut self, name: &str, value: &str) -> Self {
-        self.attributes.insert(LocalName::from(name), AttrValue::from(value));
-        
-        // Handle special attributes
-        if name == "id" {
-            self.id = Some(WeakAtom::from(value));
-        } else if name == "class" {
-            self.classes = value.split_whitespace()
-                .map(|c| AtomIdent::from(c))
-                .collect();
+
+    /// Add a node as the last child of `parent`, linking it to its previous sibling
+    fn push_child(&mut self, parent: NodeId, kind: NodeKind) -> NodeId {
+        let id = self.nodes.len();
+        let mut node = NodeData::new(self, id, kind, Some(parent));
+        node.prev_sibling = self.nodes[parent].children.last().copied();
+        if let Some(prev) = node.prev_sibling {
+            self.nodes[prev].next_sibling = Some(id);
         }
-        
-        self
+        self.nodes.push(node);
+        self.nodes[parent].children.push(id);
+        id
+    }
+
+    /// Parse the declarations of a `style` attribute
+    fn parse_style(&self, css: &str) -> ServoArc<Locked<PropertyDeclarationBlock>> {
+        let block = parse_style_attribute(css, &self.url_data, None, self.quirks_mode, CssRuleType::Style);
+        ServoArc::new(self.lock.wrap(block))
+    }
+
+    /// Replace the `style` attribute of element `id`, reparsing only its declarations
+    pub(crate) fn set_style_attribute(&mut self, id: NodeId, css: &str) {
+        let style = self.parse_style(css);
+        if let NodeKind::Element(ref mut info) = self.nodes[id].kind {
+            match info.attributes.iter_mut().find(|(n, _)| &**n == "style") {
+                Some((_, value)) => *value = css.to_string(),
+                None => info.attributes.push((AttrName::from("style"), css.to_string())),
+            }
+            info.style = Some(style);
+        }
+    }
+
+    pub(crate) fn lock(&self) -> &SharedRwLock {
+        &self.lock
+    }
+
+    pub(crate) fn url_data(&self) -> &UrlExtraData {
+        &self.url_data
+    }
+
+    pub(crate) fn document(&self) -> StyloDocument<'_> {
+        StyloDocument { dom: self }
+    }
+
+    pub(crate) fn root_element(&self) -> Option<StyloElement<'_>> {
+        self.node(Self::DOCUMENT).first_child().and_then(|node| node.as_element())
+    }
+
+    /// Every element in document order
+    pub(crate) fn elements(&self) -> impl Iterator<Item = StyloElement<'_>> {
+        let mut stack = vec![Self::DOCUMENT];
+        std::iter::from_fn(move || {
+            let id = stack.pop()?;
+            stack.extend(self.nodes[id].children.iter().rev());
+            Some(id)
+        })
+        .filter_map(move |id| self.node(id).as_element())
+    }
+
+    /// Contents of the document's `<style>` elements, in document order
+    pub(crate) fn style_blocks(&self) -> Vec<String> {
+        self.elements()
+            .filter(|element| element.info().local_name.as_str() == "style" && element.is_html_element())
+            .map(|element| {
+                element.data().children.iter().filter_map(|id| match self.nodes[*id].kind {
+                    NodeKind::Text(ref text) => Some(text.as_str()),
+                    _ => None,
+                }).collect()
+            })
+            .collect()
+    }
+
+    /// Drop all computed styles so the next traversal restyles from scratch
+    pub(crate) fn clear_styles(&self) {
+        for node in &self.nodes {
+            // No style data is borrowed between traversals
+            unsafe { node.style_data.clear() };
+        }
+    }
+
+    /// Add `add` to and remove `remove` from the state of element `id`
+    pub(crate) fn force_state(&self, id: NodeId, add: ElementState, remove: ElementState) {
+        if let Some(info) = self.nodes[id].element() {
+            info.state.set((info.state.get() - remove) | add);
+        }
+    }
+
+    /// Parent of node `id`, if that is an element
+    pub(crate) fn parent_element(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id].parent.filter(|parent| self.nodes[*parent].element().is_some())
+    }
+
+    /// Return every element to the state implied by its markup
+    pub(crate) fn reset_states(&self) {
+        for info in self.nodes.iter().filter_map(NodeData::element) {
+            info.state.set(info.initial_state);
+        }
+    }
+
+    fn node(&self, id: NodeId) -> StyloNode<'_> {
+        StyloNode { node: &self.nodes[id] }
+    }
+}
+
+/// Handle to the document node
+#[derive(Clone, Copy)]
+pub(crate) struct StyloDocument<'a> {
+    dom: &'a StyloDom,
+}
+
+/// Handle to any node
+///
+/// Stylo requires element handles to be pointer-sized, so handles point at the node
+/// alone and reach the rest of the document through it.
+#[derive(Clone, Copy)]
+pub(crate) struct StyloNode<'a> {
+    node: &'a NodeData,
+}
+
+/// Handle to an element node
+#[derive(Clone, Copy)]
+pub(crate) struct StyloElement<'a> {
+    node: &'a NodeData,
+}
+
+impl<'a> StyloNode<'a> {
+    fn data(&self) -> &'a NodeData {
+        self.node
+    }
+
+    fn dom(&self) -> &'a StyloDom {
+        // Nodes never outlive their document
+        unsafe { &*self.node.dom }
     }
 }
 
-// Implement required traits for StyloDocument
-impl TDocument for StyloDocument {
-    type ConcreteNode = StyloNode;
+impl<'a> StyloElement<'a> {
+    fn data(&self) -> &'a NodeData {
+        self.node
+    }
+
+    fn dom(&self) -> &'a StyloDom {
+        unsafe { &*self.node.dom }
+    }
+
+    fn info(&self) -> &'a ElementInfo {
+        self.data().element().expect("element handle to a non-element node")
+    }
+
+    fn sibling_element(&self, step: fn(&NodeData) -> Option<NodeId>) -> Option<Self> {
+        let mut id = step(self.data())?;
+        loop {
+            if let Some(element) = self.dom().node(id).as_element() {
+                return Some(element);
+            }
+            id = step(&self.dom().nodes[id])?;
+        }
+    }
+
+    pub(crate) fn node_id(&self) -> NodeId {
+        self.node.id
+    }
+
+    /// Value of the attribute `name`
+    pub(crate) fn attribute(&self, name: &str) -> Option<&'a str> {
+        self.info().attribute(name)
+    }
+}
+
+impl PartialEq for StyloNode<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.node, other.node)
+    }
+}
+
+impl PartialEq for StyloElement<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.node, other.node)
+    }
+}
+
+impl Eq for StyloElement<'_> {}
+
+impl std::hash::Hash for StyloElement<'_> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self.node, state);
+    }
+}
+
+impl fmt::Debug for StyloDocument<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("#document")
+    }
+}
+
+impl fmt::Debug for StyloNode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.data().kind {
+            NodeKind::Document => write!(f, "#document ({})", self.node.id),
+            NodeKind::Element(_) => StyloElement { node: self.node }.fmt(f),
+            NodeKind::Text(ref text) => write!(f, "#text {:?} ({})", text, self.node.id),
+            NodeKind::Comment => write!(f, "#comment ({})", self.node.id),
+        }
+    }
+}
+
+impl fmt::Debug for StyloElement<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}> ({})", self.info().local_name, self.node.id)
+    }
+}
+
+impl<'a> TDocument for StyloDocument<'a> {
+    type ConcreteNode = StyloNode<'a>;
 
     fn as_node(&self) -> Self::ConcreteNode {
-        StyloNode::new_document(*self)
+        self.dom.node(StyloDom::DOCUMENT)
     }
 
     fn is_html_document(&self) -> bool {
@@ -124,61 +489,51 @@ impl TDocument for StyloDocument {
     }
 
     fn quirks_mode(&self) -> QuirksMode {
-        self.quirks_mode
+        self.dom.quirks_mode
     }
 
     fn shared_lock(&self) -> &SharedRwLock {
-        unsafe { &*self.shared_lock }
+        &self.dom.lock
     }
 }
 
-// Implement NodeInfo for StyloNode
-impl NodeInfo for StyloNode {
+impl NodeInfo for StyloNode<'_> {
     fn is_element(&self) -> bool {
-        self.element.is_some()
+        matches!(self.data().kind, NodeKind::Element(_))
     }
 
     fn is_text_node(&self) -> bool {
-        false
+        matches!(self.data().kind, NodeKind::Text(_))
     }
 }
 
-// Implement TNode for StyloNode
-impl TNode for StyloNode {
-    type ConcreteElement = StyloElement;
-    type ConcreteDocument = StyloDocument;
-    type ConcreteShadowRoot = StyloShadowRoot;
+impl<'a> TNode for StyloNode<'a> {
+    type ConcreteElement = StyloElement<'a>;
+    type ConcreteDocument = StyloDocument<'a>;
+    type ConcreteShadowRoot = StyloShadowRoot<'a>;
 
     fn parent_node(&self) -> Option<Self> {
-        self.element?.parent.as_ref().map(|p| {
-            StyloNode::new_element(**p, self.document)
-        })
+        self.data().parent.map(|id| self.dom().node(id))
     }
 
     fn first_child(&self) -> Option<Self> {
-        self.element?.children.first().map(|c| {
-            StyloNode::new_element(**c, self.document)
-        })
+        self.data().children.first().map(|id| self.dom().node(*id))
     }
 
     fn last_child(&self) -> Option<Self> {
-        self.element?.children.last().map(|c| {
-            StyloNode::new_element(**c, self.document)
-        })
+        self.data().children.last().map(|id| self.dom().node(*id))
     }
 
     fn prev_sibling(&self) -> Option<Self> {
-        // Simplified implementation - would need proper sibling tracking
-        None
+        self.data().prev_sibling.map(|id| self.dom().node(id))
     }
 
     fn next_sibling(&self) -> Option<Self> {
-        // Simplified implementation - would need proper sibling tracking
-        None
+        self.data().next_sibling.map(|id| self.dom().node(id))
     }
 
     fn owner_doc(&self) -> Self::ConcreteDocument {
-        self.document
+        self.dom().document()
     }
 
     fn is_in_document(&self) -> bool {
@@ -186,27 +541,23 @@ impl TNode for StyloNode {
     }
 
     fn traversal_parent(&self) -> Option<Self::ConcreteElement> {
-        self.element?.parent.as_ref().map(|p| **p)
+        self.parent_node()?.as_element()
     }
 
     fn opaque(&self) -> OpaqueNode {
-        OpaqueNode::from_ptr(self as *const _ as *const ())
+        OpaqueNode(self.data() as *const NodeData as usize)
     }
 
     fn debug_id(self) -> usize {
-        self as *const _ as usize
+        self.node.id
     }
 
     fn as_element(&self) -> Option<Self::ConcreteElement> {
-        self.element
+        self.is_element().then_some(StyloElement { node: self.node })
     }
 
     fn as_document(&self) -> Option<Self::ConcreteDocument> {
-        if self.element.is_none() {
-            Some(self.document)
-        } else {
-            None
-        }
+        (self.node.id == StyloDom::DOCUMENT).then(|| self.dom().document())
     }
 
     fn as_shadow_root(&self) -> Option<Self::ConcreteShadowRoot> {
@@ -214,57 +565,40 @@ impl TNode for StyloNode {
     }
 }
 
-// Placeholder shadow root implementation
-#[derive(Debug, Clone, Copy)]
-pub struct StyloShadowRoot;
+/// Shadow roots are not supported; no value of this type is ever created
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct StyloShadowRoot<'a> {
+    host: StyloElement<'a>,
+}
 
-impl TShadowRoot for StyloShadowRoot {
-    type ConcreteNode = StyloNode;
+impl<'a> TShadowRoot for StyloShadowRoot<'a> {
+    type ConcreteNode = StyloNode<'a>;
 
     fn as_node(&self) -> Self::ConcreteNode {
-        unimplemented!("Shadow roots not implemented in this example")
+        self.host.as_node()
     }
 
-    fn host(&self) -> StyloElement {
-        unimplemented!("Shadow roots not implemented in this example")
+    fn host(&self) -> StyloElement<'a> {
+        self.host
     }
 
-    fn style_data<'a>(&self) -> Option<&'a CascadeData>
+    fn style_data<'b>(&self) -> Option<&'b CascadeData>
     where
-        Self: 'a,
+        Self: 'b,
     {
         None
     }
 }
 
-impl PartialEq for StyloNode {
-    fn eq(&self, other: &Self) -> bool {
-        std::ptr::eq(self, other)
-    }
-}
-
-impl PartialEq for StyloElement {
-    fn eq(&self, other: &Self) -> bool {
-        std::ptr::eq(self, other)
-    }
-}
-
-impl std::hash::Hash for StyloElement {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        (self as *const Self).hash(state);
-    }
-}
-
-// Implement SelectorsElement for StyloElement (required by TElement)
-impl SelectorsElement for StyloElement {
+impl<'a> SelectorsElement for StyloElement<'a> {
     type Impl = SelectorImpl;
 
     fn opaque(&self) -> OpaqueElement {
-        OpaqueElement::from_ptr(self as *const _ as *const ())
+        OpaqueElement::new(self.data())
     }
 
     fn parent_element(&self) -> Option<Self> {
-        self.parent.as_ref().map(|p| **p)
+        self.as_node().parent_node()?.as_element()
     }
 
     fn parent_node_is_shadow_root(&self) -> bool {
@@ -280,11 +614,15 @@ impl SelectorsElement for StyloElement {
     }
 
     fn prev_sibling_element(&self) -> Option<Self> {
-        None // Simplified
+        self.sibling_element(|node| node.prev_sibling)
     }
 
     fn next_sibling_element(&self) -> Option<Self> {
-        None // Simplified
+        self.sibling_element(|node| node.next_sibling)
+    }
+
+    fn first_element_child(&self) -> Option<Self> {
+        self.data().children.iter().find_map(|id| self.dom().node(*id).as_element())
     }
 
     fn is_html_element_in_html_document(&self) -> bool {
@@ -292,62 +630,78 @@ impl SelectorsElement for StyloElement {
     }
 
     fn has_local_name(&self, local_name: &LocalName) -> bool {
-        &self.tag_name == local_name
+        self.info().local_name == *local_name
     }
 
-    fn has_namespace(&self, _ns: &style::Namespace) -> bool {
-        true // Simplified - assume HTML namespace
+    fn has_namespace(&self, ns: &Namespace) -> bool {
+        self.info().namespace == *ns
     }
 
     fn is_same_type(&self, other: &Self) -> bool {
-        self.tag_name == other.tag_name
+        self.info().local_name == other.info().local_name && self.info().namespace == other.info().namespace
     }
 
     fn attr_matches(
         &self,
-        _ns: &selectors::attr::NamespaceConstraint<&style::Namespace>,
-        local_name: &LocalName,
-        operation: &selectors::attr::AttrSelectorOperation<&AttrValue>,
+        ns: &NamespaceConstraint<&style::Namespace>,
+        local_name: &AttrName,
+        operation: &AttrSelectorOperation<&AttrValue>,
     ) -> bool {
-        if let Some(attr_value) = self.attributes.get(local_name) {
-            operation.matches(attr_value)
-        } else {
-            false
-        }
+        let in_no_namespace = match ns {
+            NamespaceConstraint::Any => true,
+            NamespaceConstraint::Specific(ns) => ns.is_empty(),
+        };
+        in_no_namespace && self.info().attribute(local_name).is_some_and(|value| operation.eval_str(value))
     }
 
     fn match_non_ts_pseudo_class(
         &self,
-        _pc: &style::selector_parser::NonTSPseudoClass,
-        _context: &mut selectors::matching::MatchingContext<Self::Impl>,
+        pc: &NonTSPseudoClass,
+        _context: &mut MatchingContext<Self::Impl>,
     ) -> bool {
-        false // Simplified
+        match *pc {
+            // Visited styles are not computed separately, so a forced `:visited` applies in full
+            NonTSPseudoClass::Link => self.is_link() && !self.state().contains(ElementState::VISITED),
+            NonTSPseudoClass::Visited => self.is_link() && self.state().contains(ElementState::VISITED),
+            NonTSPseudoClass::AnyLink => self.is_link(),
+            NonTSPseudoClass::Lang(ref lang) => self.match_element_lang(None, lang),
+            NonTSPseudoClass::ReadOnly => !self.state().contains(pc.state_flag()),
+            _ => !pc.state_flag().is_empty() && self.state().contains(pc.state_flag()),
+        }
     }
 
     fn match_pseudo_element(
         &self,
-        _pe: &PseudoElement,
-        _context: &mut selectors::matching::MatchingContext<Self::Impl>,
+        pe: &PseudoElement,
+        _context: &mut MatchingContext<Self::Impl>,
     ) -> bool {
-        false // Simplified
+        // Pseudo-element rules of ordinary elements are matched by the stylist in
+        // `ForStatelessPseudoElement` mode; here only an element styled as `pe` matches
+        self.borrow_data().is_some_and(|data| data.styles.primary().pseudo().as_ref() == Some(pe))
+    }
+
+    fn apply_selector_flags(&self, _flags: ElementSelectorFlags) {
+        // Styles are recomputed from scratch for every batch, so invalidation hints are unused
     }
 
     fn is_link(&self) -> bool {
-        self.tag_name.as_ref() == "a"
+        matches!(self.info().local_name.as_str(), "a" | "area" | "link") && self.attribute("href").is_some()
     }
 
     fn is_html_slot_element(&self) -> bool {
         false
     }
 
-    fn has_id(&self, id: &AtomIdent, _case_sensitivity: selectors::attr::CaseSensitivity) -> bool {
-        self.id.as_ref().map_or(false, |self_id| {
-            self_id.as_ref() == id.as_ref()
-        })
+    fn has_id(&self, id: &AtomIdent, case_sensitivity: CaseSensitivity) -> bool {
+        self.info().id.as_ref().is_some_and(|own| case_sensitivity.eq_atom(own, id))
+    }
+
+    fn has_class(&self, name: &AtomIdent, case_sensitivity: CaseSensitivity) -> bool {
+        self.info().classes.iter().any(|class| case_sensitivity.eq_atom(class, name))
     }
 
-    fn has_class(&self, name: &AtomIdent, _case_sensitivity: selectors::attr::CaseSensitivity) -> bool {
-        self.classes.iter().any(|class| class == name)
+    fn has_custom_state(&self, _name: &AtomIdent) -> bool {
+        false
     }
 
     fn imported_part(&self, _name: &AtomIdent) -> Option<AtomIdent> {
@@ -358,70 +712,63 @@ impl SelectorsElement for StyloElement {
         false
     }
 
+    /// `:empty` ignores comments and empty text nodes
     fn is_empty(&self) -> bool {
-        self.children.is_empty()
+        self.data().children.iter().all(|id| match self.dom().nodes[*id].kind {
+            NodeKind::Text(ref text) => text.is_empty(),
+            NodeKind::Comment => true,
+            _ => false,
+        })
     }
 
     fn is_root(&self) -> bool {
-        self.parent.is_none()
+        self.data().parent == Some(StyloDom::DOCUMENT)
+    }
+
+    fn add_element_unique_hashes(&self, _filter: &mut selectors::bloom::BloomFilter) -> bool {
+        false
     }
 }
 
-// Implement TElement for StyloElement
-impl TElement for StyloElement {
-    type ConcreteNode = StyloNode;
-    type TraversalChildrenIterator = std::vec::IntoIter<StyloNode>;
+impl<'a> TElement for StyloElement<'a> {
+    type ConcreteNode = StyloNode<'a>;
+    type TraversalChildrenIterator = std::vec::IntoIter<StyloNode<'a>>;
 
     fn as_node(&self) -> Self::ConcreteNode {
-        // We need a document reference - this is a simplified approach
-        let shared_lock = SharedRwLock::new();
-        let document = StyloDocument::new(&shared_lock, QuirksMode::NoQuirks);
-        StyloNode::new_element(*self, document)
+        StyloNode { node: self.node }
     }
 
     fn traversal_children(&self) -> LayoutIterator<Self::TraversalChildrenIterator> {
-        let shared_lock = SharedRwLock::new();
-        let document = StyloDocument::new(&shared_lock, QuirksMode::NoQuirks);
-
-        let children: Vec<StyloNode> = self.children.iter()
-            .map(|child| StyloNode::new_element(**child, document))
-            .collect();
-
+        let children: Vec<StyloNode<'a>> = self.data().children.iter().map(|id| self.dom().node(*id)).collect();
         LayoutIterator(children.into_iter())
     }
 
     fn is_html_element(&self) -> bool {
-        true // Simplified - assume all elements are HTML
+        &*self.info().namespace == HTML_NAMESPACE
     }
 
     fn is_mathml_element(&self) -> bool {
-        false
+        &*self.info().namespace == MATHML_NAMESPACE
     }
 
     fn is_svg_element(&self) -> bool {
-        false
+        &*self.info().namespace == SVG_NAMESPACE
     }
 
-    fn style_attribute(&self) -> Option<servo_arc::ArcBorrow<Locked<PropertyDeclarationBlock>>> {
-        None // Simplified - no style attributes
+    fn style_attribute(&self) -> Option<ArcBorrow<'_, Locked<PropertyDeclarationBlock>>> {
+        self.info().style.as_ref().map(|style| style.borrow_arc())
     }
 
-    fn animation_rule(
-        &self,
-        _context: &style::context::SharedStyleContext,
-    ) -> Option<ServoArc<Locked<PropertyDeclarationBlock>>> {
+    fn animation_rule(&self, _context: &SharedStyleContext) -> Option<ServoArc<Locked<PropertyDeclarationBlock>>> {
         None
     }
 
-    fn transition_rule(
-        &self,
-        _context: &style::context::SharedStyleContext,
-    ) -> Option<ServoArc<Locked<PropertyDeclarationBlock>>> {
+    fn transition_rule(&self, _context: &SharedStyleContext) -> Option<ServoArc<Locked<PropertyDeclarationBlock>>> {
         None
     }
 
-    fn state(&self) -> style::dom::ElementState {
-        style::dom::ElementState::empty()
+    fn state(&self) -> ElementState {
+        self.info().state.get()
     }
 
     fn has_part_attr(&self) -> bool {
@@ -432,15 +779,15 @@ impl TElement for StyloElement {
         false
     }
 
-    fn id(&self) -> Option<&WeakAtom> {
-        self.id.as_ref()
+    fn id(&self) -> Option<&Atom> {
+        self.info().id.as_ref()
     }
 
     fn each_class<F>(&self, mut callback: F)
     where
         F: FnMut(&AtomIdent),
     {
-        for class in &self.classes {
+        for class in &self.info().classes {
             callback(class);
         }
     }
@@ -449,20 +796,19 @@ impl TElement for StyloElement {
     where
         F: FnMut(&AtomIdent),
     {
-        // No custom states in this simplified implementation
     }
 
     fn each_attr_name<F>(&self, mut callback: F)
     where
-        F: FnMut(&LocalName),
+        F: FnMut(&AttrName),
     {
-        for name in self.attributes.keys() {
+        for (name, _) in &self.info().attributes {
             callback(name);
         }
     }
 
     fn has_dirty_descendants(&self) -> bool {
-        false // Simplified
+        false
     }
 
     fn has_snapshot(&self) -> bool {
@@ -473,56 +819,36 @@ impl TElement for StyloElement {
         true
     }
 
-    unsafe fn set_handled_snapshot(&self) {
-        // No-op in simplified implementation
-    }
+    unsafe fn set_handled_snapshot(&self) {}
 
-    unsafe fn set_dirty_descendants(&self) {
-        // No-op in simplified implementation
-    }
+    unsafe fn set_dirty_descendants(&self) {}
 
-    unsafe fn unset_dirty_descendants(&self) {
-        // No-op in simplified implementation
-    }
+    unsafe fn unset_dirty_descendants(&self) {}
 
-    fn store_children_to_process(&self, _n: isize) {
-        // No-op in simplified implementation
-    }
+    fn store_children_to_process(&self, _n: isize) {}
 
     fn did_process_child(&self) -> isize {
-        0 // Simplified
+        0
     }
 
-    unsafe fn ensure_data(&self) -> AtomicRefMut<ElementData> {
-        let mut data = self.data.borrow_mut();
-        if data.is_none() {
-            *data = Some(ElementData::new(None));
-        }
-        AtomicRefMut::map(data, |d| d.as_mut().unwrap())
+    unsafe fn ensure_data(&self) -> ElementDataMut<'_> {
+        self.data().style_data.ensure()
     }
 
     unsafe fn clear_data(&self) {
-        *self.data.borrow_mut() = None;
+        self.data().style_data.clear();
     }
 
     fn has_data(&self) -> bool {
-        self.data.borrow().is_some()
+        self.data().style_data.get().is_some()
     }
 
-    fn borrow_data(&self) -> Option<AtomicRef<ElementData>> {
-        if self.has_data() {
-            Some(AtomicRef::map(self.data.borrow(), |d| d.as_ref().unwrap()))
-        } else {
-            None
-        }
+    fn borrow_data(&self) -> Option<ElementDataRef<'_>> {
+        self.data().style_data.get().map(ElementDataWrapper::borrow)
     }
 
-    fn mutate_data(&self) -> Option<AtomicRefMut<ElementData>> {
-        if self.has_data() {
-            Some(AtomicRefMut::map(self.data.borrow_mut(), |d| d.as_mut().unwrap()))
-        } else {
-            None
-        }
+    fn mutate_data(&self) -> Option<ElementDataMut<'_>> {
+        self.data().style_data.get().map(ElementDataWrapper::borrow_mut)
     }
 
     fn skip_item_display_fixup(&self) -> bool {
@@ -533,48 +859,37 @@ impl TElement for StyloElement {
         false
     }
 
-    fn has_animations(&self, _context: &style::context::SharedStyleContext) -> bool {
+    fn has_animations(&self, _context: &SharedStyleContext) -> bool {
         false
     }
 
-    fn has_css_animations(
-        &self,
-        _context: &style::context::SharedStyleContext,
-        _pseudo_element: Option<PseudoElement>,
-    ) -> bool {
+    fn has_css_animations(&self, _context: &SharedStyleContext, _pseudo_element: Option<PseudoElement>) -> bool {
         false
     }
 
-    fn has_css_transitions(
-        &self,
-        _context: &style::context::SharedStyleContext,
-        _pseudo_element: Option<PseudoElement>,
-    ) -> bool {
+    fn has_css_transitions(&self, _context: &SharedStyleContext, _pseudo_element: Option<PseudoElement>) -> bool {
         false
     }
 
-    fn shadow_root(&self) -> Option<StyloShadowRoot> {
+    fn shadow_root(&self) -> Option<StyloShadowRoot<'a>> {
         None
     }
 
-    fn containing_shadow(&self) -> Option<StyloShadowRoot> {
+    fn containing_shadow(&self) -> Option<StyloShadowRoot<'a>> {
         None
     }
 
     fn lang_attr(&self) -> Option<AttrValue> {
-        self.attributes.get(&LocalName::from("lang")).cloned()
+        self.attribute("lang").map(AttrValue::from)
     }
 
-    fn match_element_lang(
-        &self,
-        _override_lang: Option<Option<AttrValue>>,
-        _value: &Lang,
-    ) -> bool {
-        false // Simplified
+    fn match_element_lang(&self, _override_lang: Option<Option<AttrValue>>, _value: &Lang) -> bool {
+        false
     }
 
     fn is_html_document_body_element(&self) -> bool {
-        self.tag_name.as_ref() == "body"
+        self.info().local_name.as_str() == "body"
+            && self.parent_element().is_some_and(|parent| SelectorsElement::is_root(&parent))
     }
 
     fn synthesize_presentational_hints_for_legacy_attributes<V>(
@@ -582,24 +897,26 @@ impl TElement for StyloElement {
         _visited_handling: VisitedHandlingMode,
         _hints: &mut V,
     ) where
-        V: Push<style::applicable_declarations::ApplicableDeclarationBlock>,
+        V: Push<ApplicableDeclarationBlock>,
     {
-        // No presentational hints in this simplified implementation
     }
 
     fn local_name(&self) -> &LocalName {
-        &self.tag_name
+        &self.info().local_name
     }
 
-    fn namespace(&self) -> &style::Namespace {
-        // Return HTML namespace - this is a simplified approach
-        unsafe { std::mem::transmute("http://www.w3.org/1999/xhtml") }
+    fn namespace(&self) -> &Namespace {
+        &self.info().namespace
     }
 
-    fn query_container_size(
-        &self,
-        _display: &Display,
-    ) -> euclid::default::Size2D<Option<app_units::Au>> {
+    fn get_attr(&self, attr: &AttrName, namespace: &style::Namespace) -> Option<String> {
+        if !namespace.is_empty() {
+            return None;
+        }
+        self.info().attribute(attr).map(str::to_string)
+    }
+
+    fn query_container_size(&self, _display: &Display) -> euclid::default::Size2D<Option<app_units::Au>> {
         euclid::default::Size2D::new(None, None)
     }
 
@@ -0,0 +1,111 @@
+//! Runs the `fake-servo` executable behind each Servo backend.
+
+use std::sync::OnceLock;
+use stylo_compute::{
+    BackendKind, ComputedStyles, ElementDescriptor, ElementStyles, FakeServoFixtures, StyleEngine, StyleError, StyleRequest,
+    FAKE_SERVO_FIXTURES_ENV,
+};
+use tempfile::NamedTempFile;
+
+/// A list item matched by `li`, styled with `color`
+fn list_item(position: usize, id: Option<&str>, classes: &[&str], color: &str) -> ElementStyles {
+    let mut styles = ComputedStyles::new();
+    styles.insert("color", color);
+    styles.insert("display", "list-item");
+    ElementStyles {
+        element: ElementDescriptor {
+            tag: "li".to_string(),
+            id: id.map(str::to_string),
+            classes: classes.iter().map(|class| class.to_string()).collect(),
+            path: format!("html > body:nth-child(2) > ul:nth-child(1) > li:nth-child({})", position),
+            index: 0,
+        },
+        styles,
+    }
+}
+
+/// Point `fake-servo` at a shared fixture file; every test uses the same one
+fn use_fixtures() {
+    static FIXTURES: OnceLock<NamedTempFile> = OnceLock::new();
+    let file = FIXTURES.get_or_init(|| {
+        let fixtures = FakeServoFixtures::new()
+            .with_style(".highlight", "color", "rgb(255, 0, 0)")
+            .with_style(".highlight", "font-size", "24px")
+            .with_error(".broken", "boom")
+            .with_matches("li", vec![
+                list_item(1, Some("first"), &[], "rgb(0, 0, 0)"),
+                list_item(2, None, &["done", "late"], "rgb(0, 128, 0)"),
+                list_item(3, None, &[], "rgb(0, 0, 255)"),
+            ])
+            .with_console_noise("[WARN script] unrelated page warning")
+            .with_wrap_width(40);
+        let file = NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), serde_json::to_string(&fixtures).unwrap()).unwrap();
+        file
+    });
+    std::env::set_var(FAKE_SERVO_FIXTURES_ENV, file.path());
+}
+
+async fn check_backend(kind: BackendKind, name: &str) {
+    use_fixtures();
+    let mut engine = StyleEngine::new(kind, Some(env!("CARGO_BIN_EXE_fake-servo").to_string())).unwrap();
+    assert_eq!(engine.backend().name(), name);
+    engine.set_html(r#"<div class="highlight"></div>"#).unwrap();
+    engine.add_stylesheet(".highlight { color: red; font-size: 24px }").unwrap();
+
+    assert_eq!(engine.get_computed_style(".highlight", "color").await.unwrap(), "rgb(255, 0, 0)");
+    let styles = engine.get_all_computed_styles(".highlight").await.unwrap();
+    assert_eq!(styles.get("font-size"), Some("24px"));
+
+    let results = engine.compute_styles_batch(vec![
+        StyleRequest::value("color", ".highlight", "color"),
+        StyleRequest::value("missing", ".missing", "color"),
+        StyleRequest::value("broken", ".broken", "color"),
+    ]).await.unwrap();
+    assert_eq!(results[0].value(), Some("rgb(255, 0, 0)"));
+    assert_eq!(results[1].result, Err(StyleError::ElementNotFound { selector: ".missing".into() }));
+    assert_eq!(results[2].result, Err(StyleError::Script { message: "boom".into() }));
+
+    let items = engine.get_computed_styles_all_matches("li", &["color"]).await.unwrap();
+    let descriptors: Vec<_> = items.iter()
+        .map(|item| (item.element.index, item.element.tag.as_str(), item.element.id.as_deref(), item.element.classes.join(" ")))
+        .collect();
+    assert_eq!(descriptors, [(0, "li", Some("first"), String::new()), (1, "li", None, "done late".into()), (2, "li", None, String::new())]);
+    assert!(items[2].element.path.ends_with("li:nth-child(3)"));
+    let colors: Vec<_> = items.iter().map(|item| item.styles.iter().collect::<Vec<_>>()).collect();
+    assert_eq!(colors, [[("color", "rgb(0, 0, 0)")], [("color", "rgb(0, 128, 0)")], [("color", "rgb(0, 0, 255)")]]);
+    assert!(engine.get_computed_styles_all_matches("ol", &["color"]).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn one_shot_backend() {
+    check_backend(BackendKind::OneShot, "servo-one-shot").await;
+}
+
+#[tokio::test]
+async fn batch_backend() {
+    check_backend(BackendKind::Batch, "servo-batch").await;
+}
+
+#[tokio::test]
+async fn daemon_backend() {
+    check_backend(BackendKind::Daemon, "servo-daemon").await;
+}
+
+/// The shared daemon pool outlives the runtime that started its worker
+#[test]
+fn daemon_backend_across_runtimes() {
+    use_fixtures();
+    let run = || {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let mut engine = StyleEngine::new(BackendKind::Daemon, Some(env!("CARGO_BIN_EXE_fake-servo").to_string())).unwrap();
+            engine.set_html(r#"<div class="highlight"></div>"#).unwrap();
+            let started = std::time::Instant::now();
+            assert_eq!(engine.get_computed_style(".highlight", "color").await.unwrap(), "rgb(255, 0, 0)");
+            started.elapsed()
+        })
+    };
+    run();
+    assert!(run() < std::time::Duration::from_secs(2));
+}